@@ -0,0 +1,259 @@
+use super::{Form, FormRejection};
+use axum::{
+    async_trait,
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    response::{IntoResponse, Response},
+    Json,
+};
+use http::{header::CONTENT_TYPE, HeaderMap, Method, StatusCode};
+use serde::de::DeserializeOwned;
+use std::fmt;
+
+/// Extractor that deserializes either `application/json` or
+/// `application/x-www-form-urlencoded` request bodies into some type, depending on the
+/// `Content-Type`.
+///
+/// This makes it possible to serve an HTML `<form>` and a JavaScript client from the same
+/// handler. JSON bodies are extracted with [`axum::Json`] and forms with [`Form`], so their
+/// rejections are kept as is.
+///
+/// `GET` and `HEAD` requests don't have a body, for them the query string is deserialized, like
+/// [`Form`] does.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use axum_extra::extract::JsonOrForm;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct SignUp {
+///     username: String,
+///     password: String,
+/// }
+///
+/// async fn sign_up(JsonOrForm(payload): JsonOrForm<SignUp>) {
+///     // ...
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg(feature = "json-or-form")]
+pub struct JsonOrForm<T>(pub T);
+
+axum_core::__impl_deref!(JsonOrForm);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for JsonOrForm<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = JsonOrFormRejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if json_content_type(req.headers()) {
+            return Json::<T>::from_request(req, state)
+                .await
+                .map(|Json(value)| Self(value))
+                .map_err(JsonOrFormRejection::JsonRejection);
+        }
+
+        if req.method() == Method::GET
+            || req.method() == Method::HEAD
+            || form_content_type(req.headers())
+        {
+            return Form::<T>::from_request(req, state)
+                .await
+                .map(|Form(value)| Self(value))
+                .map_err(JsonOrFormRejection::FormRejection);
+        }
+
+        Err(JsonOrFormRejection::UnsupportedMediaType)
+    }
+}
+
+fn mime(headers: &HeaderMap) -> Option<mime::Mime> {
+    headers.get(CONTENT_TYPE)?.to_str().ok()?.parse().ok()
+}
+
+fn json_content_type(headers: &HeaderMap) -> bool {
+    mime(headers).map_or(false, |mime| {
+        mime.type_() == "application"
+            && (mime.subtype() == "json" || mime.suffix().map_or(false, |name| name == "json"))
+    })
+}
+
+fn form_content_type(headers: &HeaderMap) -> bool {
+    mime(headers).map_or(false, |mime| {
+        mime.essence_str() == mime::APPLICATION_WWW_FORM_URLENCODED.as_ref()
+    })
+}
+
+/// Rejection used for [`JsonOrForm`].
+///
+/// Contains one variant for each way the [`JsonOrForm`] extractor can fail.
+#[derive(Debug)]
+#[non_exhaustive]
+#[cfg(feature = "json-or-form")]
+pub enum JsonOrFormRejection {
+    #[allow(missing_docs)]
+    JsonRejection(JsonRejection),
+    #[allow(missing_docs)]
+    FormRejection(FormRejection),
+    /// The `Content-Type` is neither JSON nor a form.
+    UnsupportedMediaType,
+}
+
+impl IntoResponse for JsonOrFormRejection {
+    fn into_response(self) -> Response {
+        match self {
+            Self::JsonRejection(inner) => inner.into_response(),
+            Self::FormRejection(inner) => inner.into_response(),
+            Self::UnsupportedMediaType => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, self.to_string()).into_response()
+            }
+        }
+    }
+}
+
+impl fmt::Display for JsonOrFormRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::JsonRejection(inner) => inner.fmt(f),
+            Self::FormRejection(inner) => inner.fmt(f),
+            Self::UnsupportedMediaType => f.write_str(
+                "Expected request with `Content-Type: application/json` or \
+                 `Content-Type: application/x-www-form-urlencoded`",
+            ),
+        }
+    }
+}
+
+impl std::error::Error for JsonOrFormRejection {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::JsonRejection(inner) => Some(inner),
+            Self::FormRejection(inner) => Some(inner),
+            Self::UnsupportedMediaType => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+    use axum::{
+        routing::{get, post},
+        Router,
+    };
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Login {
+        username: String,
+        #[serde(default)]
+        remember: bool,
+    }
+
+    fn app() -> Router {
+        Router::new().route(
+            "/",
+            post(|JsonOrForm(login): JsonOrForm<Login>| async move {
+                format!("{} {}", login.username, login.remember)
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn accepts_json() {
+        let client = TestClient::new(app());
+
+        let res = client
+            .post("/")
+            .header(CONTENT_TYPE, "application/json")
+            .body(r#"{"username":"alice","remember":true}"#)
+            .send()
+            .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.text().await, "alice true");
+    }
+
+    #[tokio::test]
+    async fn accepts_forms() {
+        let client = TestClient::new(app());
+
+        let res = client
+            .post("/")
+            .header(
+                CONTENT_TYPE,
+                "application/x-www-form-urlencoded; charset=utf-8",
+            )
+            .body("username=alice&remember=true")
+            .send()
+            .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.text().await, "alice true");
+    }
+
+    #[tokio::test]
+    async fn reads_the_query_of_get_requests() {
+        let app = Router::new().route(
+            "/",
+            get(|JsonOrForm(login): JsonOrForm<Login>| async move { login.username }),
+        );
+        let client = TestClient::new(app);
+
+        let res = client.get("/?username=alice").send().await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.text().await, "alice");
+    }
+
+    #[tokio::test]
+    async fn keeps_the_inner_rejections() {
+        let client = TestClient::new(app());
+
+        let res = client
+            .post("/")
+            .header(CONTENT_TYPE, "application/json")
+            .body(r#"{"username":"#)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = client
+            .post("/")
+            .header(CONTENT_TYPE, "application/json")
+            .body(r#"{"name":"alice"}"#)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let res = client
+            .post("/")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body("remember=maybe")
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn rejects_other_content_types() {
+        let client = TestClient::new(app());
+
+        let res = client
+            .post("/")
+            .header(CONTENT_TYPE, "text/plain")
+            .body("alice")
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let res = client.post("/").body("username=alice").send().await;
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+}
@@ -10,6 +10,9 @@ mod form;
 #[cfg(feature = "cookie")]
 pub mod cookie;
 
+#[cfg(feature = "json-or-form")]
+mod json_or_form;
+
 #[cfg(feature = "query")]
 mod query;
 
@@ -30,6 +33,9 @@ pub use self::cookie::SignedCookieJar;
 #[cfg(feature = "form")]
 pub use self::form::{Form, FormRejection};
 
+#[cfg(feature = "json-or-form")]
+pub use self::json_or_form::{JsonOrForm, JsonOrFormRejection};
+
 #[cfg(feature = "query")]
 pub use self::query::{Query, QueryRejection};
 
@@ -17,6 +17,8 @@
 //! `erased-json` | Enables the `ErasedJson` response | No
 //! `form` | Enables the `Form` extractor | No
 //! `json-lines` | Enables the `JsonLines` extractor and response | No
+//! `json-or-form` | Enables the `JsonOrForm` extractor | No
+//! `metrics` | Enables the Prometheus `Metrics` registry and `MetricsLayer` | No
 //! `multipart` | Enables the `Multpart` extractor | No
 //! `protobuf` | Enables the `Protobuf` extractor and response | No
 //! `query` | Enables the `Query` extractor | No
@@ -81,6 +83,9 @@ pub mod routing;
 #[cfg(feature = "json-lines")]
 pub mod json_lines;
 
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
 #[cfg(feature = "typed-header")]
 pub mod typed_header;
 
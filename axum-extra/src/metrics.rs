@@ -0,0 +1,620 @@
+//! Prometheus metrics.
+//!
+//! [`Metrics`] is a registry of counters, gauges and histograms that renders itself in the
+//! Prometheus text format, and [`MetricsLayer`] records every request the router handles.
+//!
+//! # Example
+//!
+//! ```rust
+//! use axum::{Router, routing::{get, post}};
+//! use axum_extra::metrics::Metrics;
+//!
+//! let metrics = Metrics::new();
+//! let logins = metrics.counter("logins_total", "Successful logins.", &[]);
+//!
+//! let app = Router::new()
+//!     .route("/login", post(move || async move {
+//!         logins.inc();
+//!         // ...
+//!     }))
+//!     .route("/metrics", get({
+//!         let metrics = metrics.clone();
+//!         move || async move { metrics }
+//!     }))
+//!     .layer(metrics.layer());
+//! # let _: Router = app;
+//! ```
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use pin_project_lite::pin_project;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Instant,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Bucket upper bounds used by [`MetricsLayer`] for request durations, in seconds.
+pub const DEFAULT_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The content type of the Prometheus text format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// A registry of metrics.
+///
+/// Metrics are identified by their name and labels. Asking for one that already exists returns a
+/// handle to the existing one, so handles can be created wherever they're needed. Cloning a
+/// `Metrics` is cheap and the clones share their metrics.
+///
+/// Responding with a `Metrics` renders every metric in the [Prometheus text format].
+///
+/// See the [module docs](self) for an example.
+///
+/// [Prometheus text format]: https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format
+#[derive(Clone, Default)]
+pub struct Metrics {
+    families: Arc<Mutex<BTreeMap<&'static str, Family>>>,
+}
+
+struct Family {
+    help: &'static str,
+    kind: &'static str,
+    series: BTreeMap<String, Series>,
+}
+
+#[derive(Clone)]
+enum Series {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+impl Metrics {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get or register the counter `name` with the given labels.
+    ///
+    /// # Panics
+    ///
+    /// If `name` is already used by a gauge or histogram.
+    pub fn counter(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+    ) -> Counter {
+        let series = self.series(name, help, "counter", labels, || {
+            Series::Counter(Counter::default())
+        });
+
+        match series {
+            Series::Counter(counter) => counter,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Get or register the gauge `name` with the given labels.
+    ///
+    /// # Panics
+    ///
+    /// If `name` is already used by a counter or histogram.
+    pub fn gauge(&self, name: &'static str, help: &'static str, labels: &[(&str, &str)]) -> Gauge {
+        let series = self.series(name, help, "gauge", labels, || {
+            Series::Gauge(Gauge::default())
+        });
+
+        match series {
+            Series::Gauge(gauge) => gauge,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Get or register the histogram `name` with the given labels.
+    ///
+    /// `buckets` are the upper bounds of the buckets in ascending order, an `+Inf` bucket is
+    /// always added. They're only used when the histogram is registered.
+    ///
+    /// # Panics
+    ///
+    /// If `name` is already used by a counter or gauge.
+    pub fn histogram(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        buckets: &[f64],
+    ) -> Histogram {
+        let series = self.series(name, help, "histogram", labels, || {
+            Series::Histogram(Histogram::new(buckets))
+        });
+
+        match series {
+            Series::Histogram(histogram) => histogram,
+            _ => unreachable!(),
+        }
+    }
+
+    fn series(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        kind: &'static str,
+        labels: &[(&str, &str)],
+        make_series: impl FnOnce() -> Series,
+    ) -> Series {
+        let mut families = self.families.lock().unwrap();
+
+        let family = families.entry(name).or_insert_with(|| Family {
+            help,
+            kind,
+            series: BTreeMap::new(),
+        });
+
+        assert_eq!(
+            family.kind, kind,
+            "metric `{name}` is already registered as a {}",
+            family.kind
+        );
+
+        family
+            .series
+            .entry(format_labels(labels))
+            .or_insert_with(make_series)
+            .clone()
+    }
+
+    /// Create a [`MetricsLayer`] that records requests in this registry.
+    pub fn layer(&self) -> MetricsLayer {
+        MetricsLayer {
+            metrics: self.clone(),
+        }
+    }
+
+    /// Render every metric in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut out = String::new();
+
+        for (name, family) in families.iter() {
+            let _ = writeln!(out, "# HELP {name} {}", escape(family.help, false));
+            let _ = writeln!(out, "# TYPE {name} {}", family.kind);
+
+            for (labels, series) in &family.series {
+                let _ = match series {
+                    Series::Counter(counter) => {
+                        writeln!(out, "{name}{} {}", braces(labels), counter.get())
+                    }
+                    Series::Gauge(gauge) => {
+                        writeln!(out, "{name}{} {}", braces(labels), gauge.get())
+                    }
+                    Series::Histogram(histogram) => histogram.render(&mut out, name, labels),
+                };
+            }
+        }
+
+        out
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+impl IntoResponse for Metrics {
+    fn into_response(self) -> Response {
+        (
+            [(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE))],
+            self.render(),
+        )
+            .into_response()
+    }
+}
+
+/// A value that only goes up, such as the number of requests served.
+#[derive(Clone, Debug, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    /// Add one.
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    /// Add `n`.
+    pub fn inc_by(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// The current value.
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A value that can go up and down, such as the number of open connections.
+#[derive(Clone, Debug, Default)]
+pub struct Gauge(Arc<AtomicI64>);
+
+impl Gauge {
+    /// Replace the value.
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    /// Add one.
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Subtract one.
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// The current value.
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Counts observations, such as request durations, in buckets.
+#[derive(Clone, Debug)]
+pub struct Histogram(Arc<HistogramInner>);
+
+#[derive(Debug)]
+struct HistogramInner {
+    bounds: Vec<f64>,
+    // not cumulative, `buckets[i]` counts observations in `(bounds[i - 1], bounds[i]]` and the
+    // last one those above every bound
+    buckets: Vec<AtomicU64>,
+    // an `f64` stored as bits
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &[f64]) -> Self {
+        Self(Arc::new(HistogramInner {
+            bounds: bounds.to_vec(),
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0.0_f64.to_bits()),
+        }))
+    }
+
+    /// Record one observation.
+    pub fn observe(&self, value: f64) {
+        let idx = self
+            .0
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.0.bounds.len());
+        self.0.buckets[idx].fetch_add(1, Ordering::Relaxed);
+
+        let _ = self
+            .0
+            .sum
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| {
+                Some((f64::from_bits(sum) + value).to_bits())
+            });
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) -> fmt::Result {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+
+        for (idx, bucket) in self.0.buckets.iter().enumerate() {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let le = match self.0.bounds.get(idx) {
+                Some(bound) => bound.to_string(),
+                None => "+Inf".to_owned(),
+            };
+            writeln!(
+                out,
+                "{name}_bucket{{{labels}{separator}le=\"{le}\"}} {cumulative}"
+            )?;
+        }
+
+        let sum = f64::from_bits(self.0.sum.load(Ordering::Relaxed));
+        writeln!(out, "{name}_sum{} {sum}", braces(labels))?;
+        writeln!(out, "{name}_count{} {cumulative}", braces(labels))
+    }
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    labels
+        .iter()
+        .map(|(name, value)| format!("{name}=\"{}\"", escape(value, true)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn braces(labels: &str) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{labels}}}")
+    }
+}
+
+fn escape(value: &str, quotes: bool) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '"' if quotes => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Layer that records the requests handled by a router, created with [`Metrics::layer`].
+///
+/// It keeps two metrics, both labeled with the route's [`MatchedPath`] (`unmatched` for requests
+/// no route matched) and the response status:
+///
+/// - `http_requests_total`, the number of requests.
+/// - `http_request_duration_seconds`, a histogram of the time until the response was ready,
+///   using [`DEFAULT_BUCKETS`].
+///
+/// Add it with [`Router::layer`] so the matched path is known by the time it runs.
+///
+/// [`Router::layer`]: axum::Router::layer
+#[derive(Clone, Debug)]
+pub struct MetricsLayer {
+    metrics: Metrics,
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+/// Service created by [`MetricsLayer`].
+#[derive(Clone, Debug)]
+pub struct MetricsService<S> {
+    inner: S,
+    metrics: Metrics,
+}
+
+impl<S, B> Service<Request<B>> for MetricsService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let path = match req.extensions().get::<MatchedPath>() {
+            Some(path) => path.as_str().to_owned(),
+            None => "unmatched".to_owned(),
+        };
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            metrics: self.metrics.clone(),
+            path,
+            started: Instant::now(),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`MetricsService`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        metrics: Metrics,
+        path: String,
+        started: Instant,
+    }
+}
+
+impl<F> fmt::Debug for ResponseFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = futures_util::ready!(this.inner.poll(cx));
+
+        if let Ok(res) = &result {
+            let status = res.status().as_u16().to_string();
+            let labels = [("path", this.path.as_str()), ("status", status.as_str())];
+
+            this.metrics
+                .counter(
+                    "http_requests_total",
+                    "Number of HTTP requests handled.",
+                    &labels,
+                )
+                .inc();
+            this.metrics
+                .histogram(
+                    "http_request_duration_seconds",
+                    "Time until the response to an HTTP request was ready.",
+                    &labels,
+                    DEFAULT_BUCKETS,
+                )
+                .observe(this.started.elapsed().as_secs_f64());
+        }
+
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+    use axum::{routing::get, Router};
+
+    fn app(metrics: &Metrics) -> Router {
+        Router::new()
+            .route("/users/:id", get(|| async {}))
+            .route(
+                "/metrics",
+                get({
+                    let metrics = metrics.clone();
+                    move || async move { metrics }
+                }),
+            )
+            .layer(metrics.layer())
+    }
+
+    #[crate::test]
+    async fn records_requests_by_route_and_status() {
+        let metrics = Metrics::new();
+        let client = TestClient::new(app(&metrics));
+
+        client.get("/users/1").send().await;
+        client.get("/users/2").send().await;
+        client.get("/nope").send().await;
+
+        let res = client.get("/metrics").send().await;
+        assert_eq!(res.headers()["content-type"], CONTENT_TYPE);
+
+        let body = res.text().await;
+        assert!(
+            body.contains("# TYPE http_requests_total counter\n"),
+            "{body}"
+        );
+        assert!(
+            body.contains("http_requests_total{path=\"/users/:id\",status=\"200\"} 2\n"),
+            "{body}"
+        );
+        assert!(
+            body.contains("http_requests_total{path=\"unmatched\",status=\"404\"} 1\n"),
+            "{body}"
+        );
+        assert!(
+            body.contains(
+                "http_request_duration_seconds_bucket{path=\"/users/:id\",status=\"200\",le=\"+Inf\"} 2\n"
+            ),
+            "{body}"
+        );
+        assert!(
+            body.contains(
+                "http_request_duration_seconds_count{path=\"/users/:id\",status=\"200\"} 2\n"
+            ),
+            "{body}"
+        );
+    }
+
+    #[crate::test]
+    async fn renders_custom_metrics() {
+        let metrics = Metrics::new();
+        metrics
+            .counter("logins_total", "Logins.", &[("result", "failed")])
+            .inc_by(3);
+        let sessions = metrics.gauge("active_sessions", "Sessions.", &[]);
+        sessions.set(5);
+        sessions.dec();
+
+        let client = TestClient::new(app(&metrics));
+        let body = client.get("/metrics").send().await.text().await;
+
+        assert!(body.contains("# HELP logins_total Logins.\n"), "{body}");
+        assert!(
+            body.contains("logins_total{result=\"failed\"} 3\n"),
+            "{body}"
+        );
+        assert!(body.contains("# TYPE active_sessions gauge\n"), "{body}");
+        assert!(body.contains("active_sessions 4\n"), "{body}");
+    }
+
+    #[test]
+    fn handles_refer_to_the_same_metric() {
+        let metrics = Metrics::new();
+        metrics.counter("hits_total", "Hits.", &[("a", "1")]).inc();
+        metrics.counter("hits_total", "Hits.", &[("a", "1")]).inc();
+
+        assert_eq!(
+            metrics.counter("hits_total", "Hits.", &[("a", "1")]).get(),
+            2
+        );
+        assert_eq!(
+            metrics.counter("hits_total", "Hits.", &[("a", "2")]).get(),
+            0
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "metric `hits` is already registered as a counter")]
+    fn names_have_one_type() {
+        let metrics = Metrics::new();
+        metrics.counter("hits", "Hits.", &[]);
+        metrics.gauge("hits", "Hits.", &[]);
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let metrics = Metrics::new();
+        let histogram = metrics.histogram("latency", "Latency.", &[], &[0.1, 1.0]);
+        histogram.observe(0.0625);
+        histogram.observe(0.5);
+        histogram.observe(4.0);
+
+        let body = metrics.render();
+        assert!(body.contains("latency_bucket{le=\"0.1\"} 1\n"), "{body}");
+        assert!(body.contains("latency_bucket{le=\"1\"} 2\n"), "{body}");
+        assert!(body.contains("latency_bucket{le=\"+Inf\"} 3\n"), "{body}");
+        assert!(body.contains("latency_sum 4.5625\n"), "{body}");
+        assert!(body.contains("latency_count 3\n"), "{body}");
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        let metrics = Metrics::new();
+        metrics
+            .counter("odd_total", "Odd.", &[("path", "a\"b\\c\nd")])
+            .inc();
+
+        let body = metrics.render();
+        assert!(
+            body.contains("odd_total{path=\"a\\\"b\\\\c\\nd\"} 1\n"),
+            "{body}"
+        );
+    }
+}
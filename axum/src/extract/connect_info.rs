@@ -5,7 +5,10 @@
 //! [`Router::into_make_service_with_connect_info`]: crate::routing::Router::into_make_service_with_connect_info
 
 use super::{Extension, FromRequestParts};
-use crate::{middleware::AddExtension, serve::IncomingStream};
+use crate::{
+    middleware::AddExtension,
+    serve::{IncomingStream, Listener},
+};
 use async_trait::async_trait;
 use http::request::Parts;
 use std::{
@@ -13,7 +16,6 @@ use std::{
     fmt,
     future::ready,
     marker::PhantomData,
-    net::SocketAddr,
     task::{Context, Poll},
 };
 use tower_layer::Layer;
@@ -82,9 +84,13 @@ pub trait Connected<T>: Clone + Send + Sync + 'static {
     fn connect_info(target: T) -> Self;
 }
 
-impl Connected<IncomingStream<'_>> for SocketAddr {
-    fn connect_info(target: IncomingStream<'_>) -> Self {
-        target.remote_addr()
+impl<L> Connected<IncomingStream<'_, L>> for L::Addr
+where
+    L: Listener,
+    L::Addr: Clone + Sync,
+{
+    fn connect_info(target: IncomingStream<'_, L>) -> Self {
+        target.remote_addr().clone()
     }
 }
 
@@ -252,8 +258,8 @@ mod tests {
             value: &'static str,
         }
 
-        impl Connected<IncomingStream<'_>> for MyConnectInfo {
-            fn connect_info(_target: IncomingStream<'_>) -> Self {
+        impl Connected<IncomingStream<'_, TcpListener>> for MyConnectInfo {
+            fn connect_info(_target: IncomingStream<'_, TcpListener>) -> Self {
                 Self {
                     value: "it worked!",
                 }
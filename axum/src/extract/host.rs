@@ -7,9 +7,15 @@ use http::{
     header::{HeaderMap, FORWARDED},
     request::Parts,
 };
+use std::{
+    convert::Infallible,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
 
 const X_FORWARDED_HOST_HEADER_KEY: &str = "X-Forwarded-Host";
 
+const X_FORWARDED_FOR_HEADER_KEY: &str = "X-Forwarded-For";
+
 /// Extractor that resolves the hostname of the request.
 ///
 /// Hostname is resolved through the following, in order:
@@ -76,6 +82,98 @@ fn parse_forwarded(headers: &HeaderMap) -> Option<&str> {
     })
 }
 
+/// Extractor for the client addresses that proxies recorded in the request.
+///
+/// The addresses are taken from the `for` parameters of the `Forwarded` headers and from the
+/// `X-Forwarded-For` headers. The two are kept apart: a proxy usually maintains only one of them,
+/// and whatever the client sent in the other is passed through unchanged, so use only the header
+/// your proxies set.
+///
+/// The entries are in the order they were added, so the first is the original client as claimed
+/// by the first proxy and the last was added by the proxy that connected to this server. Entries
+/// that aren't IP addresses, such as `unknown` or obfuscated identifiers, and `Forwarded` elements
+/// without a `for` parameter are `None`, ports are dropped.
+///
+/// Clients can send these headers themselves, so only the entries added by proxies you trust can
+/// be relied on. Walk the list from the end, starting at the connection's peer address (see
+/// [`ConnectInfo`](super::ConnectInfo)), for as long as the address is one of your proxies, and
+/// stop at the first `None`: the addresses before it can't be attributed to anyone.
+///
+/// The lists are empty when there are no such headers, this extractor never rejects.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForwardedFor {
+    /// The `for` parameters of every element of every `Forwarded` header.
+    pub forwarded: Vec<Option<IpAddr>>,
+    /// The entries of every `X-Forwarded-For` header.
+    pub x_forwarded_for: Vec<Option<IpAddr>>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ForwardedFor
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(ForwardedFor {
+            forwarded: forwarded_parameters(&parts.headers),
+            x_forwarded_for: x_forwarded_for(&parts.headers),
+        })
+    }
+}
+
+/// The entries of every `X-Forwarded-For` header, in order.
+fn x_forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    headers
+        .get_all(X_FORWARDED_FOR_HEADER_KEY)
+        .iter()
+        .flat_map(|value| match value.to_str() {
+            Ok(value) => value.split(',').map(parse_node).collect(),
+            Err(_) => vec![None],
+        })
+        .collect()
+}
+
+/// The `for` parameters of every element of every `Forwarded` header, in order.
+fn forwarded_parameters(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    headers
+        .get_all(FORWARDED)
+        .iter()
+        .flat_map(|value| match value.to_str() {
+            Ok(value) => value.split(',').map(forwarded_for_parameter).collect(),
+            Err(_) => vec![None],
+        })
+        .collect()
+}
+
+fn forwarded_for_parameter(element: &str) -> Option<IpAddr> {
+    let node = element.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        key.trim().eq_ignore_ascii_case("for").then(|| value)
+    })?;
+
+    parse_node(node)
+}
+
+/// Parses a node as in RFC 7239 section 6: `192.0.2.43`, `192.0.2.43:47011`, `[2001:db8::17]` or
+/// `[2001:db8::17]:4711`, optionally quoted. `X-Forwarded-For` also has bare IPv6 addresses.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    if let Some(rest) = node.strip_prefix('[') {
+        let (address, _port) = rest.split_once(']')?;
+        return address.parse::<Ipv6Addr>().ok().map(IpAddr::V6);
+    }
+
+    if let Ok(address) = node.parse::<IpAddr>() {
+        return Some(address);
+    }
+
+    let (address, _port) = node.split_once(':')?;
+    address.parse::<Ipv4Addr>().ok().map(IpAddr::V4)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -168,6 +266,75 @@ mod tests {
         assert_eq!(value, "192.0.2.60");
     }
 
+    #[test]
+    fn forwarded_for_parsing() {
+        let ips = |ips: &[Option<&str>]| {
+            ips.iter()
+                .map(|ip| ip.map(|ip| ip.parse::<IpAddr>().unwrap()))
+                .collect::<Vec<_>>()
+        };
+
+        // every element, in order, with and without ports
+        let value = forwarded_parameters(&header_map(&[(
+            FORWARDED,
+            "for=192.0.2.60;proto=http, For=\"[2001:db8:cafe::17]:4711\", for=\"198.51.100.7:8080\"",
+        )]));
+        assert_eq!(
+            value,
+            ips(&[Some("192.0.2.60"), Some("2001:db8:cafe::17"), Some("198.51.100.7")])
+        );
+
+        // across headers, keeping the place of what isn't an address
+        let value = forwarded_parameters(&header_map(&[
+            (FORWARDED, "for=unknown;by=203.0.113.43"),
+            (FORWARDED, "for=_hidden, proto=https, for=192.0.2.61"),
+        ]));
+        assert_eq!(value, ips(&[None, None, None, Some("192.0.2.61")]));
+
+        let x_forwarded_for_header = HeaderName::from_static("x-forwarded-for");
+        let value = x_forwarded_for(&header_map(&[
+            (x_forwarded_for_header.clone(), "203.0.113.1, 2001:db8::1"),
+            (x_forwarded_for_header.clone(), "bogus, 198.51.100.2"),
+        ]));
+        assert_eq!(
+            value,
+            ips(&[Some("203.0.113.1"), Some("2001:db8::1"), None, Some("198.51.100.2")])
+        );
+    }
+
+    #[crate::test]
+    async fn forwarded_for_extractor() {
+        async fn forwarded_for_as_body(forwarded_for: ForwardedFor) -> String {
+            let join = |ips: Vec<Option<IpAddr>>| {
+                ips.iter()
+                    .map(|ip| ip.map_or("-".to_owned(), |ip| ip.to_string()))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+
+            format!(
+                "{} | {}",
+                join(forwarded_for.forwarded),
+                join(forwarded_for.x_forwarded_for)
+            )
+        }
+
+        let client = TestClient::new(Router::new().route("/", get(forwarded_for_as_body)));
+
+        let body = client
+            .get("/")
+            .header("x-forwarded-for", "203.0.113.1, unknown, 10.0.0.2")
+            .header("forwarded", "for=192.0.2.60")
+            .send()
+            .await
+            .text()
+            .await;
+        assert_eq!(body, "192.0.2.60 | 203.0.113.1 - 10.0.0.2");
+
+        let body = client.get("/").send().await.text().await;
+        assert_eq!(body, " | ");
+    }
+
     fn header_map(values: &[(HeaderName, &str)]) -> HeaderMap {
         let mut headers = HeaderMap::new();
         for (key, value) in values {
@@ -25,7 +25,7 @@ pub use axum_macros::{FromRef, FromRequest, FromRequestParts};
 #[doc(inline)]
 #[allow(deprecated)]
 pub use self::{
-    host::Host,
+    host::{ForwardedFor, Host},
     path::{Path, RawPathParams},
     raw_form::RawForm,
     raw_query::RawQuery,
@@ -562,7 +562,7 @@ pub enum Message {
 }
 
 impl Message {
-    fn into_tungstenite(self) -> ts::Message {
+    pub(crate) fn into_tungstenite(self) -> ts::Message {
         match self {
             Self::Text(text) => ts::Message::Text(text),
             Self::Binary(binary) => ts::Message::Binary(binary),
@@ -576,7 +576,7 @@ impl Message {
         }
     }
 
-    fn from_tungstenite(message: ts::Message) -> Option<Self> {
+    pub(crate) fn from_tungstenite(message: ts::Message) -> Option<Self> {
         match message {
             ts::Message::Text(text) => Some(Self::Text(text)),
             ts::Message::Binary(binary) => Some(Self::Binary(binary)),
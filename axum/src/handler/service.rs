@@ -180,11 +180,12 @@ where
 // for `axum::serve(listener, handler)`
 #[cfg(feature = "tokio")]
 const _: () = {
-    use crate::serve::IncomingStream;
+    use crate::serve::{IncomingStream, Listener};
 
-    impl<H, T, S> Service<IncomingStream<'_>> for HandlerService<H, T, S>
+    impl<H, T, S, L> Service<IncomingStream<'_, L>> for HandlerService<H, T, S>
     where
         H: Clone,
+        L: Listener,
         S: Clone,
     {
         type Response = Self;
@@ -195,7 +196,7 @@ const _: () = {
             Poll::Ready(Ok(()))
         }
 
-        fn call(&mut self, _req: IncomingStream<'_>) -> Self::Future {
+        fn call(&mut self, _req: IncomingStream<'_, L>) -> Self::Future {
             std::future::ready(Ok(self.clone()))
         }
     }
@@ -337,12 +337,14 @@
 //! Name | Description | Default?
 //! ---|---|---
 //! `http1` | Enables hyper's `http1` feature | Yes
-//! `http2` | Enables hyper's `http2` feature | No
+//! `http2` | Enables hyper's `http2` feature and HTTP/2 support in `axum::serve` | No
 //! `json` | Enables the [`Json`] type and some similar convenience functionality | Yes
 //! `macros` | Enables optional utility macros | No
 //! `matched-path` | Enables capturing of every request's router path and the [`MatchedPath`] extractor | Yes
 //! `multipart` | Enables parsing `multipart/form-data` requests with [`Multipart`] | No
 //! `original-uri` | Enables capturing of every request's original URI and the [`OriginalUri`] extractor | Yes
+//! `tls-rustls` | Enables `axum::serve::serve_tls` for serving over TLS with rustls | No
+//! `test` | Enables the in-process [`test::TestClient`] | No
 //! `tokio` | Enables `tokio` as a dependency and `axum::serve`, `SSE` and `extract::connect_info` types. | Yes
 //! `tower-log` | Enables `tower`'s `log` feature | Yes
 //! `tracing` | Log rejections from built-in extractors | No
@@ -445,6 +447,8 @@ pub mod routing;
 #[cfg(feature = "tokio")]
 pub mod serve;
 
+#[cfg(feature = "test")]
+pub mod test;
 #[cfg(test)]
 mod test_helpers;
 
@@ -66,3 +66,23 @@ macro_rules! all_the_tuples {
         $name!([T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15], T16);
     };
 }
+
+#[rustfmt::skip]
+macro_rules! trace {
+    ($($tt:tt)*) => {
+        #[cfg(feature = "tracing")]
+        {
+            tracing::trace!($($tt)*)
+        }
+    }
+}
+
+#[rustfmt::skip]
+macro_rules! error {
+    ($($tt:tt)*) => {
+        #[cfg(feature = "tracing")]
+        {
+            tracing::error!($($tt)*)
+        }
+    }
+}
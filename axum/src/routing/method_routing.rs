@@ -1226,9 +1226,12 @@ where
 // for `axum::serve(listener, router)`
 #[cfg(feature = "tokio")]
 const _: () = {
-    use crate::serve::IncomingStream;
+    use crate::serve::{IncomingStream, Listener};
 
-    impl Service<IncomingStream<'_>> for MethodRouter<()> {
+    impl<L> Service<IncomingStream<'_, L>> for MethodRouter<()>
+    where
+        L: Listener,
+    {
         type Response = Self;
         type Error = Infallible;
         type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
@@ -1237,7 +1240,7 @@ const _: () = {
             Poll::Ready(Ok(()))
         }
 
-        fn call(&mut self, _req: IncomingStream<'_>) -> Self::Future {
+        fn call(&mut self, _req: IncomingStream<'_, L>) -> Self::Future {
             std::future::ready(Ok(self.clone()))
         }
     }
@@ -0,0 +1,533 @@
+//! Driving a single accepted connection with hyper.
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use axum_core::{extract::Request, response::Response};
+use futures_util::{future::Fuse, FutureExt};
+use hyper1::{
+    rt::{Sleep, Timer},
+    server::conn::http1,
+};
+use pin_project_lite::pin_project;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::watch,
+};
+use tower_service::Service;
+
+use super::TowerToHyperService;
+
+#[cfg(feature = "http2")]
+use self::http2_support::{read_preface, TokioExecutor};
+#[cfg(feature = "http2")]
+use hyper1::server::conn::http2;
+
+/// Limits applied to every connection, set through the `with_*` methods on [`Serve`].
+///
+/// `None` means unlimited.
+///
+/// [`Serve`]: super::Serve
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct Limits {
+    pub(super) header_read_timeout: Option<Duration>,
+    pub(super) idle_timeout: Option<Duration>,
+    pub(super) max_header_size: Option<usize>,
+    pub(super) max_requests: Option<usize>,
+}
+
+/// Serves HTTP on `io` until the client goes away, the connection is shut down gracefully after
+/// `signal` completes, or it is dropped outright once `aborted` completes.
+///
+/// With the `http2` feature the protocol is picked per connection: clients that open with the
+/// HTTP/2 connection preface (h2c with prior knowledge) get HTTP/2, everyone else HTTP/1.1.
+pub(super) async fn serve_connection<I, S, F1, F2>(
+    io: I,
+    service: TowerToHyperService<S>,
+    limits: Limits,
+    signal: F1,
+    aborted: F2,
+) where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Service<Request, Response = Response, Error = Infallible> + Send + 'static,
+    S::Future: Send + 'static,
+    F1: Future<Output = ()>,
+    F2: Future<Output = ()>,
+{
+    let signal = signal.fuse();
+    futures_util::pin_mut!(signal);
+
+    let aborted = aborted.fuse();
+    futures_util::pin_mut!(aborted);
+
+    let (counts_tx, counts_rx) = watch::channel(RequestCounts::default());
+    let service = Tracked {
+        inner: service,
+        counts: Arc::new(counts_tx),
+        max_requests: limits.max_requests,
+    };
+
+    #[cfg(feature = "http2")]
+    {
+        // A client that never sends anything mustn't hold on to the connection forever.
+        let first_bytes_timeout = match (limits.header_read_timeout, limits.idle_timeout) {
+            (Some(header_read), Some(idle)) => Some(header_read.min(idle)),
+            (header_read, idle) => header_read.or(idle),
+        };
+
+        // Nothing has been served yet, so there's nothing to finish if we're told to stop while
+        // waiting for the client to say something.
+        let (io, is_h2) = tokio::select! {
+            result = with_timeout(first_bytes_timeout, read_preface(io)) => match result {
+                Some(Ok(detected)) => detected,
+                Some(Err(_err)) => {
+                    trace!("failed to read from connection: {_err:#}");
+                    return;
+                }
+                None => {
+                    trace!("client didn't send anything in time, closing connection");
+                    return;
+                }
+            },
+            _ = &mut signal => return,
+            _ = &mut aborted => return,
+        };
+
+        if is_h2 {
+            let conn = http2_builder(&limits).serve_connection(io, service);
+            drive(
+                conn,
+                |conn| conn.graceful_shutdown(),
+                counts_rx,
+                limits,
+                signal,
+                aborted,
+            )
+            .await;
+        } else {
+            let conn = http1_builder(&limits)
+                .serve_connection(io, service)
+                // for websockets
+                .with_upgrades();
+            drive(
+                conn,
+                |conn| conn.graceful_shutdown(),
+                counts_rx,
+                limits,
+                signal,
+                aborted,
+            )
+            .await;
+        }
+    }
+
+    #[cfg(not(feature = "http2"))]
+    {
+        let conn = http1_builder(&limits)
+            .serve_connection(io, service)
+            // for websockets
+            .with_upgrades();
+        drive(
+            conn,
+            |conn| conn.graceful_shutdown(),
+            counts_rx,
+            limits,
+            signal,
+            aborted,
+        )
+        .await;
+    }
+}
+
+fn http1_builder(limits: &Limits) -> http1::Builder {
+    let mut builder = http1::Builder::new();
+    builder.timer(TokioTimer);
+
+    if let Some(timeout) = limits.header_read_timeout {
+        builder.header_read_timeout(timeout);
+    }
+
+    // hyper refuses requests whose head doesn't fit into the read buffer
+    if let Some(max) = limits.max_header_size {
+        builder.max_buf_size(max);
+    }
+
+    builder
+}
+
+#[cfg(feature = "http2")]
+fn http2_builder(limits: &Limits) -> http2::Builder<TokioExecutor> {
+    let mut builder = http2::Builder::new(TokioExecutor);
+    builder.timer(TokioTimer);
+
+    if let Some(max) = limits.max_header_size {
+        builder.max_header_list_size(u32::try_from(max).unwrap_or(u32::MAX));
+    }
+
+    builder
+}
+
+#[cfg(feature = "http2")]
+async fn with_timeout<F>(timeout: Option<Duration>, future: F) -> Option<F::Output>
+where
+    F: Future,
+{
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// Polls `conn` to completion, starting a graceful shutdown when `signal` completes, when the
+/// connection has been idle for too long or has served its last request, and giving up on it
+/// when `aborted` completes.
+///
+/// A graceful shutdown never cuts off requests that are in flight, hyper lets them finish
+/// first.
+///
+/// `graceful_shutdown` calls the connection's method of that name. hyper's HTTP/1 and HTTP/2
+/// connections don't share a trait for it, and the HTTP/1 one can't be named outside of hyper.
+async fn drive<C, G, F1, F2>(
+    conn: C,
+    graceful_shutdown: G,
+    mut counts: watch::Receiver<RequestCounts>,
+    limits: Limits,
+    mut signal: Pin<&mut Fuse<F1>>,
+    mut aborted: Pin<&mut Fuse<F2>>,
+) where
+    C: Future<Output = Result<(), hyper1::Error>>,
+    G: Fn(Pin<&mut C>),
+    F1: Future<Output = ()>,
+    F2: Future<Output = ()>,
+{
+    futures_util::pin_mut!(conn);
+
+    // Armed whenever no request is in flight, which includes the time before the first one.
+    let idle = tokio::time::sleep(limits.idle_timeout.unwrap_or_default());
+    futures_util::pin_mut!(idle);
+    let mut idle_armed = limits.idle_timeout.is_some();
+
+    let mut closing = false;
+    let mut watching_counts = true;
+
+    loop {
+        tokio::select! {
+            result = conn.as_mut() => {
+                if let Err(_err) = result {
+                    // This mostly happens when the client closes the connection without
+                    // sending a request.
+                    trace!("failed to serve connection: {_err:#}");
+                }
+                break;
+            }
+            _ = &mut signal, if !closing => {
+                trace!("signal received in task, starting graceful shutdown");
+                closing = true;
+                graceful_shutdown(conn.as_mut());
+            }
+            _ = &mut aborted => {
+                trace!("shutdown timeout elapsed, closing connection");
+                break;
+            }
+            changed = counts.changed(), if watching_counts && !closing => {
+                if changed.is_err() {
+                    watching_counts = false;
+                    continue;
+                }
+
+                let RequestCounts { started, in_flight } = *counts.borrow();
+
+                // `Tracked` already told HTTP/1 clients, this is for HTTP/2
+                if limits.max_requests.map_or(false, |max| started >= max) {
+                    trace!("connection served {started} request(s), closing it");
+                    closing = true;
+                    graceful_shutdown(conn.as_mut());
+                    continue;
+                }
+
+                if let Some(timeout) = limits.idle_timeout {
+                    idle_armed = in_flight == 0;
+                    if idle_armed {
+                        idle.as_mut().reset(tokio::time::Instant::now() + timeout);
+                    }
+                }
+            }
+            _ = idle.as_mut(), if idle_armed && !closing => {
+                trace!("connection idle for too long, closing it");
+                closing = true;
+                graceful_shutdown(conn.as_mut());
+            }
+        }
+    }
+}
+
+/// How many requests a connection has received, and how many of those are still being handled.
+#[derive(Debug, Clone, Copy, Default)]
+struct RequestCounts {
+    started: usize,
+    in_flight: usize,
+}
+
+/// Wraps the connection's service to keep its [`RequestCounts`] up to date.
+///
+/// It also marks the response to an HTTP/1 connection's last request with `connection: close`.
+/// Waiting for [`drive`] to notice would be racy, hyper may have written the response by then.
+struct Tracked<S> {
+    inner: S,
+    counts: Arc<watch::Sender<RequestCounts>>,
+    max_requests: Option<usize>,
+}
+
+impl<S, B, ResBody> hyper1::service::Service<http::Request<B>> for Tracked<S>
+where
+    S: hyper1::service::Service<http::Request<B>, Response = http::Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = TrackedFuture<S::Future>;
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let mut is_last = false;
+        self.counts.send_modify(|counts| {
+            counts.started += 1;
+            counts.in_flight += 1;
+            is_last = self.max_requests == Some(counts.started);
+        });
+
+        TrackedFuture {
+            close: is_last && req.version() < http::Version::HTTP_2,
+            inner: self.inner.call(req),
+            _in_flight: InFlight(Arc::clone(&self.counts)),
+        }
+    }
+}
+
+pin_project! {
+    struct TrackedFuture<F> {
+        #[pin]
+        inner: F,
+        close: bool,
+        _in_flight: InFlight,
+    }
+}
+
+impl<F, B, E> Future for TrackedFuture<F>
+where
+    F: Future<Output = Result<http::Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut result = futures_util::ready!(this.inner.poll(cx));
+
+        if *this.close {
+            if let Ok(res) = &mut result {
+                res.headers_mut().insert(
+                    http::header::CONNECTION,
+                    http::HeaderValue::from_static("close"),
+                );
+            }
+        }
+
+        Poll::Ready(result)
+    }
+}
+
+/// Marks a request as no longer in flight when dropped, which hyper does once the response
+/// head is ready.
+struct InFlight(Arc<watch::Sender<RequestCounts>>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.send_modify(|counts| counts.in_flight -= 1);
+    }
+}
+
+/// hyper's timeouts need a timer, this one uses tokio's.
+#[derive(Debug, Clone, Copy)]
+struct TokioTimer;
+
+impl Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+        Box::pin(TokioSleep(Box::pin(tokio::time::sleep(duration))))
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Sleep>> {
+        Box::pin(TokioSleep(Box::pin(tokio::time::sleep_until(
+            deadline.into(),
+        ))))
+    }
+}
+
+struct TokioSleep(Pin<Box<tokio::time::Sleep>>);
+
+impl Future for TokioSleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.0.as_mut().poll(cx)
+    }
+}
+
+impl Sleep for TokioSleep {}
+
+#[cfg(feature = "http2")]
+mod http2_support {
+    use std::{
+        future::Future,
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use bytes::{Buf, Bytes};
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+    /// What every HTTP/2 client sends first, see RFC 9113 section 3.4.
+    const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+    /// Runs the futures hyper spawns for HTTP/2 streams on tokio.
+    #[derive(Debug, Clone, Copy)]
+    pub(super) struct TokioExecutor;
+
+    impl<F> hyper1::rt::Executor<F> for TokioExecutor
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        fn execute(&self, fut: F) {
+            tokio::spawn(fut);
+        }
+    }
+
+    /// Reads just enough of `io` to tell whether the client speaks HTTP/2.
+    ///
+    /// The bytes read are handed back through the returned [`Rewind`], so the protocol
+    /// implementation still sees the connection from the start.
+    pub(super) async fn read_preface<I>(mut io: I) -> io::Result<(Rewind<I>, bool)>
+    where
+        I: AsyncRead + Unpin,
+    {
+        let mut buf = [0; H2_PREFACE.len()];
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            let n = io.read(&mut buf[filled..]).await?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+
+            // stop as soon as it can't be HTTP/2, an HTTP/1 request may well be shorter than the
+            // preface
+            if buf[..filled] != H2_PREFACE[..filled] {
+                break;
+            }
+        }
+
+        let is_h2 = buf[..filled] == *H2_PREFACE;
+        let prefix = Bytes::copy_from_slice(&buf[..filled]);
+
+        Ok((Rewind { prefix, inner: io }, is_h2))
+    }
+
+    /// An IO that replays `prefix` before reading from `inner` again.
+    #[derive(Debug)]
+    pub(super) struct Rewind<I> {
+        prefix: Bytes,
+        inner: I,
+    }
+
+    impl<I> AsyncRead for Rewind<I>
+    where
+        I: AsyncRead + Unpin,
+    {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            if !self.prefix.is_empty() {
+                let n = self.prefix.len().min(buf.remaining());
+                buf.put_slice(&self.prefix[..n]);
+                self.prefix.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl<I> AsyncWrite for Rewind<I>
+    where
+        I: AsyncWrite + Unpin,
+    {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.inner).poll_write(cx, buf)
+        }
+
+        fn poll_write_vectored(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            bufs: &[io::IoSlice<'_>],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            self.inner.is_write_vectored()
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use tokio::io::AsyncWriteExt;
+
+        #[crate::test]
+        async fn detects_the_preface_and_replays_it() {
+            let (mut client, server) = tokio::io::duplex(64);
+            client.write_all(H2_PREFACE).await.unwrap();
+
+            let (mut io, is_h2) = read_preface(server).await.unwrap();
+            assert!(is_h2);
+
+            let mut replayed = vec![0; H2_PREFACE.len()];
+            io.read_exact(&mut replayed).await.unwrap();
+            assert_eq!(replayed, H2_PREFACE);
+        }
+
+        #[crate::test]
+        async fn short_http1_requests_arent_mistaken_for_http2() {
+            let (mut client, server) = tokio::io::duplex(64);
+            client.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+
+            // the request is shorter than the preface, so this must not wait for more bytes
+            let (mut io, is_h2) = read_preface(server).await.unwrap();
+            assert!(!is_h2);
+
+            let mut replayed = vec![0; 18];
+            io.read_exact(&mut replayed).await.unwrap();
+            assert_eq!(replayed, b"GET / HTTP/1.1\r\n\r\n");
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::io;
+
+use async_trait::async_trait;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+};
+
+/// Types that can listen for connections.
+///
+/// [`serve`] works with anything implementing this trait. axum implements it for tokio's
+/// `TcpListener` and, on unix, `UnixListener`.
+///
+/// # Socket activation
+///
+/// Sockets handed over by systemd, or any other inherited file descriptor, can be used by
+/// turning them into a tokio listener first:
+///
+/// ```
+/// # #[cfg(unix)]
+/// # async fn listener_from_systemd() -> std::io::Result<tokio::net::TcpListener> {
+/// use std::os::unix::io::FromRawFd;
+///
+/// // systemd passes the first socket as fd 3
+/// let listener = unsafe { std::net::TcpListener::from_raw_fd(3) };
+/// listener.set_nonblocking(true)?;
+/// tokio::net::TcpListener::from_std(listener)
+/// # }
+/// ```
+///
+/// # Custom listeners
+///
+/// ```
+/// use axum::serve::Listener;
+/// use tokio::net::{TcpListener, TcpStream};
+/// use std::{io, net::SocketAddr};
+///
+/// /// Only lets in clients from the local network.
+/// struct LocalOnly(TcpListener);
+///
+/// #[axum::async_trait]
+/// impl Listener for LocalOnly {
+///     type Io = TcpStream;
+///     type Addr = SocketAddr;
+///
+///     async fn accept(&mut self) -> io::Result<(Self::Io, Self::Addr)> {
+///         loop {
+///             let (stream, addr) = self.0.accept().await?;
+///             if addr.ip().is_loopback() {
+///                 return Ok((stream, addr));
+///             }
+///         }
+///     }
+///
+///     fn local_addr(&self) -> io::Result<Self::Addr> {
+///         self.0.local_addr()
+///     }
+/// }
+/// ```
+///
+/// [`serve`]: super::serve
+#[async_trait]
+pub trait Listener: Send + 'static {
+    /// The listener's IO type.
+    type Io: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// The listener's address type.
+    type Addr: Send + 'static;
+
+    /// Accept a new incoming connection to this listener.
+    ///
+    /// This must be cancel safe: if the returned future is dropped before it completes, no
+    /// connection may be lost.
+    async fn accept(&mut self) -> io::Result<(Self::Io, Self::Addr)>;
+
+    /// Returns the local address that this listener is bound to.
+    fn local_addr(&self) -> io::Result<Self::Addr>;
+}
+
+#[async_trait]
+impl Listener for TcpListener {
+    type Io = tokio::net::TcpStream;
+    type Addr = std::net::SocketAddr;
+
+    async fn accept(&mut self) -> io::Result<(Self::Io, Self::Addr)> {
+        Self::accept(self).await
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Self::local_addr(self)
+    }
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl Listener for tokio::net::UnixListener {
+    type Io = tokio::net::UnixStream;
+    type Addr = tokio::net::unix::SocketAddr;
+
+    async fn accept(&mut self) -> io::Result<(Self::Io, Self::Addr)> {
+        Self::accept(self).await
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Self::local_addr(self)
+    }
+}
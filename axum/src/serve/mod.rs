@@ -0,0 +1,1250 @@
+//! Serve services.
+
+use std::{
+    convert::Infallible,
+    fmt::{self, Debug},
+    future::{self, Future, IntoFuture},
+    io,
+    marker::PhantomData,
+    sync::Arc,
+    time::Duration,
+};
+
+use axum_core::{body::Body, extract::Request, response::Response};
+use futures_util::{
+    future::{poll_fn, ready, Either, Map, Ready},
+    FutureExt,
+};
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+use tower_hyper_http_body_compat::{HttpBody04ToHttpBody1, HttpBody1ToHttpBody04};
+use tower_service::Service;
+
+mod connection;
+
+use self::connection::Limits;
+mod listener;
+#[cfg(feature = "tls-rustls")]
+mod tls;
+
+pub use self::listener::Listener;
+#[cfg(feature = "tls-rustls")]
+pub use self::tls::{ReloadingCertResolver, RustlsConfig, TlsConnectInfo, TlsListener};
+
+/// Serve the service with the supplied listener.
+///
+/// The listener can be a `TcpListener`, a `UnixListener`, or anything else implementing
+/// [`Listener`].
+///
+/// This method of running a service is intentionally simple. Besides graceful shutdown, through
+/// [`Serve::with_graceful_shutdown`], it only supports a handful of limits that protect the server
+/// from misbehaving clients, such as [`Serve::with_max_connections`] and
+/// [`Serve::with_header_read_timeout`]. Use hyper or hyper-util if you need more configuration.
+///
+/// Errors accepting connections don't stop the server. Running out of file descriptors, for
+/// example, makes it wait a second before trying again.
+///
+/// HTTP/1.1 is always supported. With the `http2` feature enabled the server also speaks HTTP/2
+/// over cleartext to clients that use prior knowledge, picking the protocol for each connection
+/// from the first bytes the client sends.
+///
+/// # Examples
+///
+/// Serving a [`Router`]:
+///
+/// ```
+/// use axum::{Router, routing::get};
+///
+/// # async {
+/// let router = Router::new().route("/", get(|| async { "Hello, World!" }));
+///
+/// let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+/// axum::serve(listener, router).await.unwrap();
+/// # };
+/// ```
+///
+/// See also [`Router::into_make_service_with_connect_info`].
+///
+/// Serving a [`MethodRouter`]:
+///
+/// ```
+/// use axum::routing::get;
+///
+/// # async {
+/// let router = get(|| async { "Hello, World!" });
+///
+/// let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+/// axum::serve(listener, router).await.unwrap();
+/// # };
+/// ```
+///
+/// See also [`MethodRouter::into_make_service_with_connect_info`].
+///
+/// Serving a [`Handler`]:
+///
+/// ```
+/// use axum::handler::HandlerWithoutStateExt;
+///
+/// # async {
+/// async fn handler() -> &'static str {
+///     "Hello, World!"
+/// }
+///
+/// let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+/// axum::serve(listener, handler.into_make_service()).await.unwrap();
+/// # };
+/// ```
+///
+/// See also [`HandlerWithoutStateExt::into_make_service_with_connect_info`] and
+/// [`HandlerService::into_make_service_with_connect_info`].
+///
+/// [`Router`]: crate::Router
+/// [`Router::into_make_service_with_connect_info`]: crate::Router::into_make_service_with_connect_info
+/// [`MethodRouter`]: crate::routing::MethodRouter
+/// [`MethodRouter::into_make_service_with_connect_info`]: crate::routing::MethodRouter::into_make_service_with_connect_info
+/// [`Handler`]: crate::handler::Handler
+/// [`HandlerWithoutStateExt::into_make_service_with_connect_info`]: crate::handler::HandlerWithoutStateExt::into_make_service_with_connect_info
+/// [`HandlerService::into_make_service_with_connect_info`]: crate::handler::HandlerService::into_make_service_with_connect_info
+#[cfg(feature = "tokio")]
+pub fn serve<L, M, S>(listener: L, make_service: M) -> Serve<L, M, S>
+where
+    L: Listener,
+    M: for<'a> Service<IncomingStream<'a, L>, Error = Infallible, Response = S>,
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    Serve {
+        listener,
+        make_service,
+        max_connections: None,
+        limits: Limits::default(),
+        _marker: PhantomData,
+    }
+}
+
+/// Serve the service over TLS with the supplied listener.
+///
+/// Works like [`serve`], except that every connection starts with a TLS handshake using `config`,
+/// see [`TlsListener`].
+/// Handshakes run concurrently and time out after ten seconds. ALPN advertises `h2` (with the
+/// `http2` feature) and `http/1.1` unless the rustls config sets its own protocols.
+///
+/// Use [`TlsConnectInfo`] as the connect info type to see the client's certificates.
+///
+/// # Example
+///
+/// ```
+/// use axum::{Router, routing::get, serve::RustlsConfig};
+///
+/// # async {
+/// let router = Router::new().route("/", get(|| async { "Hello, World!" }));
+///
+/// // reloaded whenever the files change
+/// let config = RustlsConfig::from_pem_file("cert.pem", "key.pem").unwrap();
+///
+/// let listener = tokio::net::TcpListener::bind("0.0.0.0:3443").await.unwrap();
+/// axum::serve::serve_tls(listener, config, router).await.unwrap();
+/// # };
+/// ```
+#[cfg(all(feature = "tokio", feature = "tls-rustls"))]
+pub fn serve_tls<L, M, S>(
+    listener: L,
+    config: RustlsConfig,
+    make_service: M,
+) -> Serve<TlsListener<L>, M, S>
+where
+    L: Listener,
+    M: for<'a> Service<IncomingStream<'a, TlsListener<L>>, Error = Infallible, Response = S>,
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    Serve {
+        listener: TlsListener::new(listener, config),
+        make_service,
+        max_connections: None,
+        limits: Limits::default(),
+        _marker: PhantomData,
+    }
+}
+
+/// Future returned by [`serve`].
+#[cfg(feature = "tokio")]
+#[must_use = "futures must be awaited or polled"]
+pub struct Serve<L, M, S> {
+    listener: L,
+    make_service: M,
+    max_connections: Option<usize>,
+    limits: Limits,
+    _marker: PhantomData<S>,
+}
+
+#[cfg(feature = "tokio")]
+impl<L, M, S> Serve<L, M, S>
+where
+    L: Listener,
+{
+    /// Returns the local address this server is bound to.
+    pub fn local_addr(&self) -> io::Result<L::Addr> {
+        self.listener.local_addr()
+    }
+
+    /// Limit how many connections are served at the same time.
+    ///
+    /// Once the limit is reached the server stops accepting connections until one of them
+    /// closes. Clients that connect in the meantime wait in the listener's backlog. By default
+    /// there is no limit.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use axum::{Router, routing::get};
+    /// use std::time::Duration;
+    ///
+    /// # async {
+    /// let router = Router::new().route("/", get(|| async { "Hello, World!" }));
+    ///
+    /// let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+    /// axum::serve(listener, router)
+    ///     .with_max_connections(10_000)
+    ///     .with_header_read_timeout(Duration::from_secs(10))
+    ///     .with_idle_timeout(Duration::from_secs(60))
+    ///     .await
+    ///     .unwrap();
+    /// # };
+    /// ```
+    pub fn with_max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Limit how long an HTTP/1 client may take to send the head of a request.
+    ///
+    /// The timer starts with the first byte of each request, connections whose client takes
+    /// longer are closed. This is what stops slowloris attacks, which keep connections open by
+    /// trickling in headers. By default there is no timeout.
+    ///
+    /// With the `http2` feature it also limits how long a new connection may wait before sending
+    /// anything, since that's needed to tell HTTP/1 and HTTP/2 apart.
+    pub fn with_header_read_timeout(mut self, timeout: Duration) -> Self {
+        self.limits.header_read_timeout = Some(timeout);
+        self
+    }
+
+    /// Close connections that haven't had a request in flight for `timeout`.
+    ///
+    /// This covers keep-alive connections waiting for their next request, as well as new
+    /// connections that never send one. The connection is shut down gracefully, so a response
+    /// that is still being streamed is finished first. By default idle connections are kept
+    /// open until the client closes them.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.limits.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit the size of a request's head, in bytes.
+    ///
+    /// HTTP/1 connections that send a bigger request line and headers are closed. For HTTP/2 this
+    /// sets the `SETTINGS_MAX_HEADER_LIST_SIZE` the server advertises. hyper's default is about
+    /// 400 KiB for HTTP/1 and 16 MiB for HTTP/2.
+    ///
+    /// # Panics
+    ///
+    /// If `max` is less than 8192, the smallest read buffer hyper supports.
+    pub fn with_max_header_size(mut self, max: usize) -> Self {
+        assert!(
+            max >= MIN_HEADER_SIZE,
+            "max header size must be at least {MIN_HEADER_SIZE} bytes"
+        );
+        self.limits.max_header_size = Some(max);
+        self
+    }
+
+    /// Close connections after they've received `max` requests.
+    ///
+    /// The last request is answered normally, with `connection: close` for HTTP/1 and a `GOAWAY`
+    /// frame for HTTP/2, so clients will open a new connection for the next one. By default
+    /// there is no limit.
+    ///
+    /// # Panics
+    ///
+    /// If `max` is zero.
+    pub fn with_max_requests_per_connection(mut self, max: usize) -> Self {
+        assert!(max > 0, "connections must be allowed at least one request");
+        self.limits.max_requests = Some(max);
+        self
+    }
+
+    /// Prepares a server to handle graceful shutdown when the provided future completes.
+    ///
+    /// Once `signal` completes the server stops accepting new connections, tells every
+    /// in-flight connection to finish the request it is handling and close, and waits for
+    /// them before returning. Use [`WithGracefulShutdown::with_shutdown_timeout`] to bound how
+    /// long that wait may take.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use axum::{Router, routing::get};
+    ///
+    /// # async {
+    /// let router = Router::new().route("/", get(|| async { "Hello, World!" }));
+    ///
+    /// let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+    /// axum::serve(listener, router)
+    ///     .with_graceful_shutdown(shutdown_signal())
+    ///     .await
+    ///     .unwrap();
+    /// # };
+    ///
+    /// async fn shutdown_signal() {
+    ///     // ...
+    /// }
+    /// ```
+    pub fn with_graceful_shutdown<F>(self, signal: F) -> WithGracefulShutdown<L, M, S, F>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        WithGracefulShutdown {
+            listener: self.listener,
+            make_service: self.make_service,
+            max_connections: self.max_connections,
+            limits: self.limits,
+            signal,
+            shutdown_timeout: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<L, M, S> Debug for Serve<L, M, S>
+where
+    L: Debug,
+    M: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            listener,
+            make_service,
+            max_connections,
+            limits,
+            _marker: _,
+        } = self;
+
+        f.debug_struct("Serve")
+            .field("listener", listener)
+            .field("make_service", make_service)
+            .field("max_connections", max_connections)
+            .field("limits", limits)
+            .finish()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<L, M, S> IntoFuture for Serve<L, M, S>
+where
+    L: Listener,
+    M: for<'a> Service<IncomingStream<'a, L>, Error = Infallible, Response = S> + Send + 'static,
+    for<'a> <M as Service<IncomingStream<'a, L>>>::Future: Send,
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Output = io::Result<()>;
+    type IntoFuture = private::ServeFuture;
+
+    fn into_future(self) -> Self::IntoFuture {
+        private::ServeFuture(Box::pin(async move {
+            let Self {
+                mut listener,
+                mut make_service,
+                max_connections,
+                limits,
+                _marker: _,
+            } = self;
+
+            let connections = max_connections.map(|max| Arc::new(Semaphore::new(max)));
+
+            loop {
+                let (permit, io, remote_addr) =
+                    next_connection(&mut listener, connections.as_ref()).await;
+
+                poll_fn(|cx| make_service.poll_ready(cx))
+                    .await
+                    .unwrap_or_else(|err| match err {});
+
+                // `io` is only borrowed for the call, so it doesn't have to be `Sync`
+                let service = make_service
+                    .call(IncomingStream {
+                        io: &io,
+                        remote_addr,
+                    })
+                    .await
+                    .unwrap_or_else(|err| match err {});
+                let service = TowerToHyperService { service };
+
+                tokio::spawn(async move {
+                    connection::serve_connection(
+                        io,
+                        service,
+                        limits,
+                        future::pending::<()>(),
+                        future::pending::<()>(),
+                    )
+                    .await;
+
+                    drop(permit);
+                });
+            }
+        }))
+    }
+}
+
+/// Serve future with graceful shutdown enabled.
+#[cfg(feature = "tokio")]
+#[must_use = "futures must be awaited or polled"]
+pub struct WithGracefulShutdown<L, M, S, F> {
+    listener: L,
+    make_service: M,
+    max_connections: Option<usize>,
+    limits: Limits,
+    signal: F,
+    shutdown_timeout: Option<Duration>,
+    _marker: PhantomData<S>,
+}
+
+#[cfg(feature = "tokio")]
+impl<L, M, S, F> WithGracefulShutdown<L, M, S, F>
+where
+    L: Listener,
+{
+    /// Returns the local address this server is bound to.
+    pub fn local_addr(&self) -> io::Result<L::Addr> {
+        self.listener.local_addr()
+    }
+
+    /// Limit how long the server waits for in-flight connections after the shutdown signal.
+    ///
+    /// Connections still open when the timeout elapses are closed without finishing their
+    /// requests. By default the server waits for as long as it takes.
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(timeout);
+        self
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<L, M, S, F> Debug for WithGracefulShutdown<L, M, S, F>
+where
+    L: Debug,
+    M: Debug,
+    F: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            listener,
+            make_service,
+            max_connections,
+            limits,
+            signal,
+            shutdown_timeout,
+            _marker: _,
+        } = self;
+
+        f.debug_struct("WithGracefulShutdown")
+            .field("listener", listener)
+            .field("make_service", make_service)
+            .field("max_connections", max_connections)
+            .field("limits", limits)
+            .field("signal", signal)
+            .field("shutdown_timeout", shutdown_timeout)
+            .finish()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<L, M, S, F> IntoFuture for WithGracefulShutdown<L, M, S, F>
+where
+    L: Listener,
+    M: for<'a> Service<IncomingStream<'a, L>, Error = Infallible, Response = S> + Send + 'static,
+    for<'a> <M as Service<IncomingStream<'a, L>>>::Future: Send,
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+    F: Future<Output = ()> + Send + 'static,
+{
+    type Output = io::Result<()>;
+    type IntoFuture = private::ServeFuture;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self {
+            mut listener,
+            mut make_service,
+            max_connections,
+            limits,
+            signal,
+            shutdown_timeout,
+            _marker: _,
+        } = self;
+
+        let connections = max_connections.map(|max| Arc::new(Semaphore::new(max)));
+
+        // Connection tasks watch `signal_tx.closed()`, which resolves once the signal fires.
+        let (signal_tx, signal_rx) = watch::channel(());
+        let signal_tx = Arc::new(signal_tx);
+        tokio::spawn(async move {
+            signal.await;
+            trace!("received graceful shutdown signal. Telling tasks to shutdown");
+            drop(signal_rx);
+        });
+
+        // Dropping `abort_rx` tells connections that outlived the shutdown timeout to close.
+        let (abort_tx, abort_rx) = watch::channel(());
+        let abort_tx = Arc::new(abort_tx);
+
+        // Every connection task holds a `close_rx`, so `close_tx.closed()` resolves when
+        // they've all finished.
+        let (close_tx, close_rx) = watch::channel(());
+
+        private::ServeFuture(Box::pin(async move {
+            loop {
+                let (permit, io, remote_addr) = tokio::select! {
+                    conn = next_connection(&mut listener, connections.as_ref()) => conn,
+                    _ = signal_tx.closed() => {
+                        trace!("signal received, not accepting new connections");
+                        break;
+                    }
+                };
+
+                poll_fn(|cx| make_service.poll_ready(cx))
+                    .await
+                    .unwrap_or_else(|err| match err {});
+
+                let service = make_service
+                    .call(IncomingStream {
+                        io: &io,
+                        remote_addr,
+                    })
+                    .await
+                    .unwrap_or_else(|err| match err {});
+                let service = TowerToHyperService { service };
+
+                let signal_tx = Arc::clone(&signal_tx);
+                let abort_tx = Arc::clone(&abort_tx);
+                let close_rx = close_rx.clone();
+
+                tokio::spawn(async move {
+                    connection::serve_connection(
+                        io,
+                        service,
+                        limits,
+                        signal_tx.closed(),
+                        abort_tx.closed(),
+                    )
+                    .await;
+
+                    trace!("connection closed");
+
+                    drop(permit);
+                    drop(close_rx);
+                });
+            }
+
+            drop(close_rx);
+            drop(listener);
+
+            trace!(
+                "waiting for {} task(s) to finish",
+                close_tx.receiver_count()
+            );
+
+            match shutdown_timeout {
+                Some(timeout) => {
+                    if tokio::time::timeout(timeout, close_tx.closed())
+                        .await
+                        .is_err()
+                    {
+                        trace!(
+                            "shutdown timeout elapsed with {} task(s) still running",
+                            close_tx.receiver_count()
+                        );
+                        drop(abort_rx);
+                        close_tx.closed().await;
+                    }
+                }
+                None => close_tx.closed().await,
+            }
+
+            Ok(())
+        }))
+    }
+}
+
+/// hyper panics if its read buffer is any smaller.
+const MIN_HEADER_SIZE: usize = 8192;
+
+/// How long to wait before accepting again after an error that isn't specific to one connection,
+/// such as running out of file descriptors.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Accepts the next connection, first waiting for a free slot if the number of connections is
+/// limited.
+///
+/// Errors are logged rather than returned, a server shouldn't stop because one client reset its
+/// connection or because it briefly ran out of file descriptors.
+async fn next_connection<L>(
+    listener: &mut L,
+    connections: Option<&Arc<Semaphore>>,
+) -> (Option<OwnedSemaphorePermit>, L::Io, L::Addr)
+where
+    L: Listener,
+{
+    let permit = match connections {
+        Some(connections) => Some(
+            Arc::clone(connections)
+                .acquire_owned()
+                .await
+                .expect("semaphore is never closed"),
+        ),
+        None => None,
+    };
+
+    loop {
+        match listener.accept().await {
+            Ok((io, remote_addr)) => return (permit, io, remote_addr),
+            Err(_err) if is_connection_error(&_err) => {
+                trace!("failed to accept connection: {_err:#}");
+            }
+            Err(_err) => {
+                error!(
+                    "failed to accept connection, retrying in {ACCEPT_ERROR_BACKOFF:?}: {_err:#}"
+                );
+                tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+            }
+        }
+    }
+}
+
+/// Errors that only affect the connection being accepted.
+fn is_connection_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// Serve a single connection that isn't backed by a socket, such as an in-memory pipe.
+#[cfg(all(feature = "test", feature = "ws"))]
+pub(crate) async fn serve_in_memory<I, S>(io: I, service: S)
+where
+    I: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    S: Service<Request, Response = Response, Error = Infallible> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    connection::serve_connection(
+        io,
+        TowerToHyperService { service },
+        Limits::default(),
+        future::pending::<()>(),
+        future::pending::<()>(),
+    )
+    .await;
+}
+
+type HyperResponse = Response<HttpBody04ToHttpBody1<Body>>;
+
+/// Adapts a tower service to hyper 1.0's `Service` trait.
+#[derive(Debug, Clone)]
+struct TowerToHyperService<S> {
+    service: S,
+}
+
+impl<S> hyper1::service::Service<Request<hyper1::body::Incoming>> for TowerToHyperService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible>,
+{
+    type Response = HyperResponse;
+    type Error = Infallible;
+    type Future = Either<
+        Ready<Result<HyperResponse, Infallible>>,
+        Map<S::Future, fn(Result<Response, Infallible>) -> Result<HyperResponse, Infallible>>,
+    >;
+
+    fn call(&mut self, req: Request<hyper1::body::Incoming>) -> Self::Future {
+        let req = req.map(|body| {
+            // wont need this when axum uses http-body 1.0
+            let http_body_04 = HttpBody1ToHttpBody04::new(body);
+            Body::new(http_body_04)
+        });
+
+        // doing this saves cloning the service just to await the service being ready
+        //
+        // services like `Router` are always ready, so assume the service
+        // we're running here is also always ready...
+        match poll_fn(|cx| self.service.poll_ready(cx)).now_or_never() {
+            Some(Ok(())) => {}
+            Some(Err(err)) => match err {},
+            None => {
+                // ...otherwise load shed
+                let mut res = Response::new(HttpBody04ToHttpBody1::new(Body::empty()));
+                *res.status_mut() = http::StatusCode::SERVICE_UNAVAILABLE;
+                return Either::Left(ready(Ok(res)));
+            }
+        }
+
+        Either::Right(
+            self.service
+                .call(req)
+                .map(into_hyper_response as fn(_) -> _),
+        )
+    }
+}
+
+fn into_hyper_response(result: Result<Response, Infallible>) -> Result<HyperResponse, Infallible> {
+    let response = result
+        .unwrap_or_else(|err| match err {})
+        // wont need this when axum uses http-body 1.0
+        .map(HttpBody04ToHttpBody1::new);
+
+    Ok(response)
+}
+
+/// An incoming stream.
+///
+/// Used with [`serve`] and [`IntoMakeServiceWithConnectInfo`].
+///
+/// [`IntoMakeServiceWithConnectInfo`]: crate::extract::connect_info::IntoMakeServiceWithConnectInfo
+pub struct IncomingStream<'a, L>
+where
+    L: Listener,
+{
+    io: &'a L::Io,
+    remote_addr: L::Addr,
+}
+
+impl<L> IncomingStream<'_, L>
+where
+    L: Listener,
+{
+    /// Get a reference to the inner IO type.
+    pub fn io(&self) -> &L::Io {
+        self.io
+    }
+
+    /// Returns the remote address that this stream is bound to.
+    pub fn remote_addr(&self) -> &L::Addr {
+        &self.remote_addr
+    }
+}
+
+impl IncomingStream<'_, tokio::net::TcpListener> {
+    /// Returns the local address that this stream is bound to.
+    pub fn local_addr(&self) -> io::Result<std::net::SocketAddr> {
+        self.io.local_addr()
+    }
+}
+
+impl<L> Debug for IncomingStream<'_, L>
+where
+    L: Listener,
+    L::Io: Debug,
+    L::Addr: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IncomingStream")
+            .field("io", &self.io)
+            .field("remote_addr", &self.remote_addr)
+            .finish()
+    }
+}
+
+mod private {
+    use std::{
+        future::Future,
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    pub struct ServeFuture(pub(super) futures_util::future::BoxFuture<'static, io::Result<()>>);
+
+    impl Future for ServeFuture {
+        type Output = io::Result<()>;
+
+        #[inline]
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            self.0.as_mut().poll(cx)
+        }
+    }
+
+    impl std::fmt::Debug for ServeFuture {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("ServeFuture").finish_non_exhaustive()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        handler::{Handler, HandlerWithoutStateExt},
+        routing::get,
+        Router,
+    };
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+
+    #[allow(dead_code, unused_must_use)]
+    async fn if_it_compiles_it_works() {
+        let router: Router = Router::new();
+
+        let addr = "0.0.0.0:0";
+
+        // router
+        serve(TcpListener::bind(addr).await.unwrap(), router.clone());
+        serve(
+            TcpListener::bind(addr).await.unwrap(),
+            router.clone().into_make_service(),
+        );
+        serve(
+            TcpListener::bind(addr).await.unwrap(),
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        );
+
+        // method router
+        serve(TcpListener::bind(addr).await.unwrap(), get(handler));
+        serve(
+            TcpListener::bind(addr).await.unwrap(),
+            get(handler).into_make_service(),
+        );
+        serve(
+            TcpListener::bind(addr).await.unwrap(),
+            get(handler).into_make_service_with_connect_info::<SocketAddr>(),
+        );
+
+        // handler
+        serve(
+            TcpListener::bind(addr).await.unwrap(),
+            handler.into_service(),
+        );
+        serve(
+            TcpListener::bind(addr).await.unwrap(),
+            handler.with_state(()),
+        );
+        serve(
+            TcpListener::bind(addr).await.unwrap(),
+            handler.into_make_service(),
+        );
+        serve(
+            TcpListener::bind(addr).await.unwrap(),
+            handler.into_make_service_with_connect_info::<SocketAddr>(),
+        );
+    }
+
+    async fn handler() {}
+
+    #[crate::test]
+    async fn graceful_shutdown_waits_for_in_flight_requests() {
+        let started = Arc::new(tokio::sync::Notify::new());
+
+        let router = Router::new().route(
+            "/",
+            get({
+                let started = Arc::clone(&started);
+                move || async move {
+                    started.notify_one();
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    "done"
+                }
+            }),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (signal_tx, signal_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            serve(listener, router)
+                .with_graceful_shutdown(async {
+                    signal_rx.await.ok();
+                })
+                .await
+        });
+
+        let request = tokio::spawn(async move {
+            reqwest::get(format!("http://{addr}/"))
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap()
+        });
+
+        started.notified().await;
+        signal_tx.send(()).unwrap();
+
+        assert_eq!(request.await.unwrap(), "done");
+        server.await.unwrap().unwrap();
+
+        // the listener is closed once the server has shut down
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+
+    #[crate::test]
+    async fn graceful_shutdown_timeout_closes_slow_connections() {
+        let started = Arc::new(tokio::sync::Notify::new());
+
+        let router = Router::new().route(
+            "/",
+            get({
+                let started = Arc::clone(&started);
+                move || async move {
+                    started.notify_one();
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                }
+            }),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (signal_tx, signal_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            serve(listener, router)
+                .with_graceful_shutdown(async {
+                    signal_rx.await.ok();
+                })
+                .with_shutdown_timeout(Duration::from_millis(50))
+                .await
+        });
+
+        let request = tokio::spawn(reqwest::get(format!("http://{addr}/")));
+
+        started.notified().await;
+        signal_tx.send(()).unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("server didn't shut down")
+            .unwrap()
+            .unwrap();
+
+        assert!(request.await.unwrap().is_err());
+    }
+
+    #[cfg(feature = "http2")]
+    #[crate::test]
+    async fn serves_http1_and_http2_on_the_same_listener() {
+        let router = Router::new().route(
+            "/",
+            get(|req: Request| async move { format!("{:?}", req.version()) }),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, router).into_future());
+
+        let res = reqwest::get(format!("http://{addr}/")).await.unwrap();
+        assert_eq!(res.version(), http::Version::HTTP_11);
+        assert_eq!(res.text().await.unwrap(), "HTTP/1.1");
+
+        let client = reqwest::Client::builder()
+            .http2_prior_knowledge()
+            .build()
+            .unwrap();
+        let res = client.get(format!("http://{addr}/")).send().await.unwrap();
+        assert_eq!(res.version(), http::Version::HTTP_2);
+        assert_eq!(res.text().await.unwrap(), "HTTP/2.0");
+    }
+
+    #[cfg(feature = "http2")]
+    #[crate::test]
+    async fn graceful_shutdown_waits_for_in_flight_http2_requests() {
+        let started = Arc::new(tokio::sync::Notify::new());
+
+        let router = Router::new().route(
+            "/",
+            get({
+                let started = Arc::clone(&started);
+                move || async move {
+                    started.notify_one();
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    "done"
+                }
+            }),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (signal_tx, signal_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            serve(listener, router)
+                .with_graceful_shutdown(async {
+                    signal_rx.await.ok();
+                })
+                .await
+        });
+
+        let client = reqwest::Client::builder()
+            .http2_prior_knowledge()
+            .build()
+            .unwrap();
+        let request = tokio::spawn(async move {
+            let res = client.get(format!("http://{addr}/")).send().await.unwrap();
+            assert_eq!(res.version(), http::Version::HTTP_2);
+            res.text().await.unwrap()
+        });
+
+        started.notified().await;
+        signal_tx.send(()).unwrap();
+
+        assert_eq!(request.await.unwrap(), "done");
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("server didn't shut down")
+            .unwrap()
+            .unwrap();
+    }
+
+    const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n";
+
+    /// Sends a raw HTTP/1.1 request and reads the response, whose body must be "ok".
+    async fn send_request(stream: &mut tokio::net::TcpStream) -> String {
+        use tokio::io::AsyncWriteExt;
+
+        stream.write_all(REQUEST).await.unwrap();
+        read_response(stream).await
+    }
+
+    async fn read_response(stream: &mut tokio::net::TcpStream) -> String {
+        use tokio::io::AsyncReadExt;
+
+        let mut response = Vec::new();
+        let mut buf = [0; 1024];
+        while !response.ends_with(b"\r\n\r\nok") {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert_ne!(n, 0, "connection closed early");
+            response.extend_from_slice(&buf[..n]);
+        }
+
+        String::from_utf8(response).unwrap()
+    }
+
+    /// Waits for the server to close `stream`.
+    async fn assert_closed(stream: &mut tokio::net::TcpStream) {
+        use tokio::io::AsyncReadExt;
+
+        let mut rest = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut rest))
+            .await
+            .expect("connection wasn't closed")
+            .ok();
+    }
+
+    #[crate::test]
+    async fn max_connections_makes_new_connections_wait() {
+        use tokio::io::AsyncWriteExt;
+
+        let router = Router::new().route("/", get(|| async { "ok" }));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            serve(listener, router)
+                .with_max_connections(1)
+                .into_future(),
+        );
+
+        let mut first = tokio::net::TcpStream::connect(addr).await.unwrap();
+        send_request(&mut first).await;
+
+        // the first connection is kept alive, so the second one isn't served yet
+        let mut second = tokio::net::TcpStream::connect(addr).await.unwrap();
+        second.write_all(REQUEST).await.unwrap();
+        let waiting = tokio::time::timeout(Duration::from_millis(200), second.readable()).await;
+        assert!(waiting.is_err());
+
+        drop(first);
+
+        let response = read_response(&mut second).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    }
+
+    #[crate::test]
+    async fn header_read_timeout_closes_slow_clients() {
+        use tokio::io::AsyncWriteExt;
+
+        let router = Router::new().route("/", get(|| async { "ok" }));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            serve(listener, router)
+                .with_header_read_timeout(Duration::from_millis(100))
+                .into_future(),
+        );
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nhost: loc")
+            .await
+            .unwrap();
+
+        assert_closed(&mut stream).await;
+    }
+
+    #[crate::test]
+    async fn idle_timeout_closes_keep_alive_connections() {
+        let router = Router::new().route("/", get(|| async { "ok" }));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            serve(listener, router)
+                .with_idle_timeout(Duration::from_millis(100))
+                .into_future(),
+        );
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        send_request(&mut stream).await;
+        assert_closed(&mut stream).await;
+
+        // connections that never send a request are closed too
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        assert_closed(&mut stream).await;
+    }
+
+    #[crate::test]
+    async fn idle_timeout_doesnt_cut_off_slow_requests() {
+        let router = Router::new().route(
+            "/",
+            get(|| async {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                "ok"
+            }),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            serve(listener, router)
+                .with_idle_timeout(Duration::from_millis(100))
+                .into_future(),
+        );
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let response = send_request(&mut stream).await;
+        assert!(!response.contains("connection: close"), "{response}");
+    }
+
+    #[crate::test]
+    async fn max_requests_per_connection_closes_the_connection() {
+        let router = Router::new().route("/", get(|| async { "ok" }));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            serve(listener, router)
+                .with_max_requests_per_connection(2)
+                .into_future(),
+        );
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let response = send_request(&mut stream).await;
+        assert!(!response.contains("connection: close"), "{response}");
+
+        let response = send_request(&mut stream).await;
+        assert!(response.contains("connection: close"), "{response}");
+        assert_closed(&mut stream).await;
+    }
+
+    #[crate::test]
+    #[should_panic(expected = "max header size must be at least 8192 bytes")]
+    async fn max_header_size_must_fit_hypers_buffer() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _ = serve(listener, get(handler)).with_max_header_size(1024);
+    }
+
+    #[crate::test]
+    async fn accept_errors_dont_stop_the_server() {
+        /// Fails the first accept like a process that ran out of file descriptors.
+        struct Flaky {
+            inner: TcpListener,
+            failed: bool,
+        }
+
+        #[async_trait::async_trait]
+        impl Listener for Flaky {
+            type Io = tokio::net::TcpStream;
+            type Addr = SocketAddr;
+
+            async fn accept(&mut self) -> io::Result<(Self::Io, Self::Addr)> {
+                if !self.failed {
+                    self.failed = true;
+                    return Err(io::Error::new(io::ErrorKind::Other, "too many open files"));
+                }
+                self.inner.accept().await
+            }
+
+            fn local_addr(&self) -> io::Result<Self::Addr> {
+                self.inner.local_addr()
+            }
+        }
+
+        let router = Router::new().route("/", get(|| async { "ok" }));
+
+        let listener = Flaky {
+            inner: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+            failed: false,
+        };
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve(listener, router).into_future());
+
+        let res = reqwest::get(format!("http://{addr}/")).await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "ok");
+        assert!(!server.is_finished());
+    }
+
+    #[cfg(unix)]
+    #[crate::test]
+    async fn serves_unix_domain_sockets() {
+        use crate::extract::ConnectInfo;
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::{unix, UnixListener, UnixStream},
+        };
+
+        let path = std::env::temp_dir().join(format!("axum-{}.sock", uuid::Uuid::new_v4()));
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let router = Router::new().route(
+            "/",
+            get(
+                |ConnectInfo(addr): ConnectInfo<unix::SocketAddr>| async move {
+                    format!("unnamed client: {}", addr.is_unnamed())
+                },
+            ),
+        );
+        tokio::spawn(
+            serve(
+                listener,
+                router.into_make_service_with_connect_info::<unix::SocketAddr>(),
+            )
+            .into_future(),
+        );
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.ends_with("unnamed client: true"), "{response}");
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,558 @@
+//! TLS termination with rustls.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    Certificate, PrivateKey, ServerConfig,
+};
+use tokio::{net::TcpListener, task::JoinSet};
+use tokio_rustls::server::TlsStream;
+
+use super::{IncomingStream, Listener};
+use crate::extract::connect_info::Connected;
+
+/// How long a client gets to complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often [`ReloadingCertResolver`] looks at the files' modification times.
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// TLS settings for [`serve_tls`] and [`TlsListener`].
+///
+/// [`serve_tls`]: super::serve_tls
+#[derive(Debug, Clone)]
+pub struct RustlsConfig {
+    server_config: Arc<ServerConfig>,
+}
+
+impl RustlsConfig {
+    /// Use a rustls `ServerConfig` as is.
+    ///
+    /// If it doesn't list any ALPN protocols, `http/1.1` is advertised, preceded by `h2` when the
+    /// `http2` feature is enabled.
+    pub fn new(mut server_config: ServerConfig) -> Self {
+        if server_config.alpn_protocols.is_empty() {
+            server_config.alpn_protocols = alpn_protocols();
+        }
+
+        Self {
+            server_config: Arc::new(server_config),
+        }
+    }
+
+    /// Load a certificate chain and its private key from PEM files.
+    ///
+    /// The files are reloaded whenever they change on disk, see [`ReloadingCertResolver`].
+    /// Clients aren't asked for certificates. For mutual TLS build a `ServerConfig` with a client
+    /// certificate verifier and a [`ReloadingCertResolver`], and pass it to
+    /// [`RustlsConfig::new`].
+    pub fn from_pem_file(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> io::Result<Self> {
+        let resolver = ReloadingCertResolver::from_pem_file(cert, key)?;
+
+        let server_config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(resolver));
+
+        Ok(Self::new(server_config))
+    }
+
+    /// The rustls config used for handshakes.
+    pub fn server_config(&self) -> &Arc<ServerConfig> {
+        &self.server_config
+    }
+}
+
+fn alpn_protocols() -> Vec<Vec<u8>> {
+    let mut protocols = Vec::new();
+    #[cfg(feature = "http2")]
+    protocols.push(b"h2".to_vec());
+    protocols.push(b"http/1.1".to_vec());
+    protocols
+}
+
+/// Serves a certificate chain and key read from PEM files, picking up new versions of the files
+/// without a restart.
+///
+/// At most once every second, on the next handshake, the files' modification times are checked
+/// and the certificate is reloaded if they've changed. If the new files can't be loaded, for
+/// example because only one of them has been written so far, the previous certificate stays in
+/// use and loading is retried on a later handshake.
+pub struct ReloadingCertResolver {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    state: Mutex<ReloadState>,
+}
+
+struct ReloadState {
+    certified_key: Arc<CertifiedKey>,
+    modified: (Option<SystemTime>, Option<SystemTime>),
+    checked_at: Instant,
+}
+
+impl ReloadingCertResolver {
+    /// Load the certificate chain in `cert` and the private key in `key`.
+    ///
+    /// The key may be PKCS#8, PKCS#1 (RSA) or SEC1 (EC) encoded.
+    pub fn from_pem_file(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> io::Result<Self> {
+        let cert_path = cert.as_ref().to_path_buf();
+        let key_path = key.as_ref().to_path_buf();
+
+        let modified = modified_times(&cert_path, &key_path);
+        let certified_key = load_certified_key(&cert_path, &key_path)?;
+
+        Ok(Self {
+            cert_path,
+            key_path,
+            state: Mutex::new(ReloadState {
+                certified_key: Arc::new(certified_key),
+                modified,
+                checked_at: Instant::now(),
+            }),
+        })
+    }
+
+    /// Reload the files now, whether or not they've changed.
+    pub fn reload(&self) -> io::Result<()> {
+        let modified = modified_times(&self.cert_path, &self.key_path);
+        let certified_key = load_certified_key(&self.cert_path, &self.key_path)?;
+
+        let mut state = self.state.lock().unwrap();
+        state.certified_key = Arc::new(certified_key);
+        state.modified = modified;
+        state.checked_at = Instant::now();
+
+        Ok(())
+    }
+
+    fn current(&self) -> Arc<CertifiedKey> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.checked_at.elapsed() >= RELOAD_CHECK_INTERVAL {
+            state.checked_at = Instant::now();
+
+            let modified = modified_times(&self.cert_path, &self.key_path);
+            if modified != state.modified {
+                match load_certified_key(&self.cert_path, &self.key_path) {
+                    Ok(certified_key) => {
+                        trace!("reloaded TLS certificate from {}", self.cert_path.display());
+                        state.certified_key = Arc::new(certified_key);
+                        state.modified = modified;
+                    }
+                    Err(_err) => {
+                        trace!("failed to reload TLS certificate, keeping the old one: {_err}");
+                    }
+                }
+            }
+        }
+
+        Arc::clone(&state.certified_key)
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+impl fmt::Debug for ReloadingCertResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadingCertResolver")
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .finish_non_exhaustive()
+    }
+}
+
+fn modified_times(cert: &Path, key: &Path) -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = |path: &Path| path.metadata().and_then(|meta| meta.modified()).ok();
+    (modified(cert), modified(key))
+}
+
+fn load_certified_key(cert: &Path, key: &Path) -> io::Result<CertifiedKey> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(cert)?))?;
+    if certs.is_empty() {
+        return Err(invalid_data(format!(
+            "no certificates found in {}",
+            cert.display()
+        )));
+    }
+
+    let key_der = rustls_pemfile::read_all(&mut BufReader::new(File::open(key)?))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(der)
+            | rustls_pemfile::Item::RSAKey(der)
+            | rustls_pemfile::Item::ECKey(der) => Some(der),
+            _ => None,
+        })
+        .ok_or_else(|| invalid_data(format!("no private key found in {}", key.display())))?;
+
+    let signing_key = rustls::sign::any_supported_type(&PrivateKey(key_der))
+        .map_err(|_| invalid_data(format!("unsupported private key in {}", key.display())))?;
+
+    Ok(CertifiedKey::new(
+        certs.into_iter().map(Certificate).collect(),
+        signing_key,
+    ))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A [`Listener`] that performs a TLS handshake on every connection accepted by `L`, handing
+/// out connections once their handshake is done.
+///
+/// Handshakes run concurrently, so a slow client doesn't hold up everyone else, and time out
+/// after ten seconds. [`serve_tls`] wraps its listener in this.
+///
+/// [`serve_tls`]: super::serve_tls
+#[derive(Debug)]
+pub struct TlsListener<L>
+where
+    L: Listener,
+{
+    inner: L,
+    config: RustlsConfig,
+    handshakes: JoinSet<Option<(TlsStream<L::Io>, L::Addr)>>,
+}
+
+impl<L> TlsListener<L>
+where
+    L: Listener,
+{
+    /// Wrap `inner` so its connections are served over TLS using `config`.
+    pub fn new(inner: L, config: RustlsConfig) -> Self {
+        Self {
+            inner,
+            config,
+            handshakes: JoinSet::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl<L> Listener for TlsListener<L>
+where
+    L: Listener,
+{
+    type Io = TlsStream<L::Io>;
+    type Addr = L::Addr;
+
+    async fn accept(&mut self) -> io::Result<(Self::Io, Self::Addr)> {
+        loop {
+            tokio::select! {
+                result = self.inner.accept() => {
+                    let (io, remote_addr) = result?;
+                    let acceptor =
+                        tokio_rustls::TlsAcceptor::from(Arc::clone(&self.config.server_config));
+
+                    self.handshakes.spawn(async move {
+                        match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(io)).await {
+                            Ok(Ok(tls_stream)) => Some((tls_stream, remote_addr)),
+                            Ok(Err(_err)) => {
+                                trace!("TLS handshake failed: {_err}");
+                                None
+                            }
+                            Err(_) => {
+                                trace!("TLS handshake timed out");
+                                None
+                            }
+                        }
+                    });
+                }
+                Some(result) = self.handshakes.join_next() => {
+                    if let Ok(Some(accepted)) = result {
+                        return Ok(accepted);
+                    }
+                }
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        self.inner.local_addr()
+    }
+}
+
+impl<L> IncomingStream<'_, TlsListener<L>>
+where
+    L: Listener,
+{
+    /// Returns the certificate chain the client presented during the TLS handshake, if any.
+    pub fn peer_certificates(&self) -> Option<&[Certificate]> {
+        self.io.get_ref().1.peer_certificates()
+    }
+}
+
+/// Connection information for servers started with [`serve_tls`] on a `TcpListener`.
+///
+/// It also works with plain [`serve`], where there are never any peer certificates, so the same
+/// handlers can be used with and without TLS. Use it with [`Router::into_make_service_with_connect_info`] and extract it with
+/// [`ConnectInfo`] to get at the client's certificates when using mutual TLS.
+///
+/// ```
+/// use axum::{extract::ConnectInfo, routing::get, serve::TlsConnectInfo, Router};
+///
+/// async fn handler(ConnectInfo(info): ConnectInfo<TlsConnectInfo>) -> String {
+///     match info.peer_certificates() {
+///         Some(chain) => format!("client sent {} certificate(s)", chain.len()),
+///         None => "anonymous client".to_owned(),
+///     }
+/// }
+///
+/// # async {
+/// let app = Router::new().route("/", get(handler));
+///
+/// let config = axum::serve::RustlsConfig::from_pem_file("cert.pem", "key.pem").unwrap();
+/// let listener = tokio::net::TcpListener::bind("0.0.0.0:3443").await.unwrap();
+/// axum::serve::serve_tls(
+///     listener,
+///     config,
+///     app.into_make_service_with_connect_info::<TlsConnectInfo>(),
+/// )
+/// .await
+/// .unwrap();
+/// # };
+/// ```
+///
+/// [`serve_tls`]: super::serve_tls
+/// [`serve`]: super::serve
+/// [`Router::into_make_service_with_connect_info`]: crate::Router::into_make_service_with_connect_info
+/// [`ConnectInfo`]: crate::extract::ConnectInfo
+#[derive(Debug, Clone)]
+pub struct TlsConnectInfo {
+    remote_addr: SocketAddr,
+    peer_certificates: Option<Arc<[Certificate]>>,
+}
+
+impl TlsConnectInfo {
+    /// The client's address.
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+
+    /// The certificate chain the client presented, end entity first.
+    ///
+    /// `None` for plain connections and clients that didn't send a certificate.
+    pub fn peer_certificates(&self) -> Option<&[Certificate]> {
+        self.peer_certificates.as_deref()
+    }
+}
+
+/// A plain connection from `remote_addr`, e.g. for
+/// [`MockConnectInfo`](crate::extract::connect_info::MockConnectInfo) in tests.
+impl From<SocketAddr> for TlsConnectInfo {
+    fn from(remote_addr: SocketAddr) -> Self {
+        Self {
+            remote_addr,
+            peer_certificates: None,
+        }
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener<TcpListener>>> for TlsConnectInfo {
+    fn connect_info(target: IncomingStream<'_, TlsListener<TcpListener>>) -> Self {
+        Self {
+            remote_addr: *target.remote_addr(),
+            peer_certificates: target.peer_certificates().map(Into::into),
+        }
+    }
+}
+
+impl Connected<IncomingStream<'_, TcpListener>> for TlsConnectInfo {
+    fn connect_info(target: IncomingStream<'_, TcpListener>) -> Self {
+        Self {
+            remote_addr: *target.remote_addr(),
+            peer_certificates: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{extract::ConnectInfo, routing::get, serve::serve_tls, Router};
+    use std::future::IntoFuture;
+
+    struct TestCert {
+        cert_pem: String,
+        key_pem: String,
+    }
+
+    fn self_signed() -> TestCert {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        TestCert {
+            cert_pem: cert.serialize_pem().unwrap(),
+            key_pem: cert.serialize_private_key_pem(),
+        }
+    }
+
+    fn write_pem_files(dir: &Path, cert: &TestCert) -> (PathBuf, PathBuf) {
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+        std::fs::write(&cert_path, &cert.cert_pem).unwrap();
+        std::fs::write(&key_path, &cert.key_pem).unwrap();
+        (cert_path, key_path)
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("axum-tls-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn client_trusting(addr: SocketAddr, cert: &TestCert) -> reqwest::ClientBuilder {
+        reqwest::Client::builder()
+            .use_rustls_tls()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(reqwest::Certificate::from_pem(cert.cert_pem.as_bytes()).unwrap())
+            .resolve("localhost", addr)
+    }
+
+    async fn spawn_tls_server(config: RustlsConfig) -> SocketAddr {
+        let router = Router::new().route(
+            "/",
+            get(
+                |ConnectInfo(info): ConnectInfo<TlsConnectInfo>| async move {
+                    match info.peer_certificates() {
+                        Some(chain) => format!("{} client certificate(s)", chain.len()),
+                        None => "anonymous".to_owned(),
+                    }
+                },
+            ),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            serve_tls(
+                listener,
+                config,
+                router.into_make_service_with_connect_info::<TlsConnectInfo>(),
+            )
+            .into_future(),
+        );
+        addr
+    }
+
+    #[crate::test]
+    async fn serves_https() {
+        let cert = self_signed();
+        let dir = temp_dir();
+        let (cert_path, key_path) = write_pem_files(&dir, &cert);
+
+        let addr =
+            spawn_tls_server(RustlsConfig::from_pem_file(cert_path, key_path).unwrap()).await;
+
+        let client = client_trusting(addr, &cert).http1_only().build().unwrap();
+        let res = client
+            .get(format!("https://localhost:{}/", addr.port()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.version(), http::Version::HTTP_11);
+        assert_eq!(res.text().await.unwrap(), "anonymous");
+    }
+
+    #[cfg(feature = "http2")]
+    #[crate::test]
+    async fn negotiates_http2_with_alpn() {
+        let cert = self_signed();
+        let dir = temp_dir();
+        let (cert_path, key_path) = write_pem_files(&dir, &cert);
+
+        let addr =
+            spawn_tls_server(RustlsConfig::from_pem_file(cert_path, key_path).unwrap()).await;
+
+        let client = client_trusting(addr, &cert).build().unwrap();
+        let res = client
+            .get(format!("https://localhost:{}/", addr.port()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.version(), http::Version::HTTP_2);
+    }
+
+    #[crate::test]
+    async fn reloads_certificates_when_the_files_change() {
+        let old = self_signed();
+        let dir = temp_dir();
+        let (cert_path, key_path) = write_pem_files(&dir, &old);
+
+        let addr =
+            spawn_tls_server(RustlsConfig::from_pem_file(cert_path, key_path).unwrap()).await;
+        let url = format!("https://localhost:{}/", addr.port());
+
+        let trusts_old = client_trusting(addr, &old).build().unwrap();
+        trusts_old.get(&url).send().await.unwrap();
+
+        let new = self_signed();
+        write_pem_files(&dir, &new);
+        tokio::time::sleep(RELOAD_CHECK_INTERVAL + Duration::from_millis(100)).await;
+
+        let trusts_new = client_trusting(addr, &new).build().unwrap();
+        trusts_new.get(&url).send().await.unwrap();
+
+        let trusts_old = client_trusting(addr, &old).build().unwrap();
+        assert!(trusts_old.get(&url).send().await.is_err());
+    }
+
+    #[crate::test]
+    async fn exposes_client_certificates() {
+        let server_cert = self_signed();
+        let dir = temp_dir();
+        let (cert_path, key_path) = write_pem_files(&dir, &server_cert);
+
+        let mut ca_params = rcgen::CertificateParams::new(Vec::new());
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+
+        let client_cert = rcgen::generate_simple_self_signed(vec!["client".to_owned()]).unwrap();
+        let identity = format!(
+            "{}{}",
+            client_cert.serialize_pem_with_signer(&ca).unwrap(),
+            client_cert.serialize_private_key_pem()
+        );
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(&Certificate(ca.serialize_der().unwrap()))
+            .unwrap();
+        let verifier = rustls::server::AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed();
+
+        let server_config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(verifier)
+            .with_cert_resolver(Arc::new(
+                ReloadingCertResolver::from_pem_file(cert_path, key_path).unwrap(),
+            ));
+
+        let addr = spawn_tls_server(RustlsConfig::new(server_config)).await;
+        let url = format!("https://localhost:{}/", addr.port());
+
+        let client = client_trusting(addr, &server_cert)
+            .identity(reqwest::Identity::from_pem(identity.as_bytes()).unwrap())
+            .build()
+            .unwrap();
+        let res = client.get(&url).send().await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "1 client certificate(s)");
+
+        let anonymous = client_trusting(addr, &server_cert).build().unwrap();
+        let res = anonymous.get(&url).send().await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "anonymous");
+    }
+}
@@ -0,0 +1,107 @@
+use http::{
+    header::{COOKIE, SET_COOKIE},
+    HeaderMap, HeaderValue,
+};
+use std::collections::BTreeMap;
+
+/// The cookies of a [`TestClient`](super::TestClient).
+///
+/// Deliberately simple: cookies are keyed by name only, `Domain`, `Path` and `Secure` are ignored
+/// since every request goes to the same service, and `Max-Age=0` is what removes a cookie.
+#[derive(Debug, Default)]
+pub(super) struct CookieStore {
+    cookies: BTreeMap<String, String>,
+}
+
+impl CookieStore {
+    pub(super) fn get(&self, name: &str) -> Option<String> {
+        self.cookies.get(name).cloned()
+    }
+
+    pub(super) fn set(&mut self, name: &str, value: &str) {
+        self.cookies.insert(name.to_owned(), value.to_owned());
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.cookies.clear();
+    }
+
+    /// Add a `Cookie` header to an outgoing request, unless it already has one.
+    pub(super) fn apply(&self, headers: &mut HeaderMap) {
+        if self.cookies.is_empty() || headers.contains_key(COOKIE) {
+            return;
+        }
+
+        let cookie = self
+            .cookies
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        if let Ok(value) = HeaderValue::from_str(&cookie) {
+            headers.insert(COOKIE, value);
+        }
+    }
+
+    /// Apply the `Set-Cookie` headers of a response.
+    pub(super) fn update(&mut self, headers: &HeaderMap) {
+        for value in headers.get_all(SET_COOKIE) {
+            let Some(set_cookie) = value.to_str().ok().and_then(parse_set_cookie) else {
+                continue;
+            };
+
+            if set_cookie.removed {
+                self.cookies.remove(set_cookie.name);
+            } else {
+                self.set(set_cookie.name, set_cookie.value);
+            }
+        }
+    }
+}
+
+struct SetCookie<'a> {
+    name: &'a str,
+    value: &'a str,
+    removed: bool,
+}
+
+fn parse_set_cookie(header: &str) -> Option<SetCookie<'_>> {
+    let mut parts = header.split(';');
+
+    let (name, value) = parts.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    let removed = parts.any(|attribute| {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        key.trim().eq_ignore_ascii_case("max-age")
+            && value.trim().parse::<i64>().map_or(false, |age| age <= 0)
+    });
+
+    Some(SetCookie {
+        name,
+        value: value.trim().trim_matches('"'),
+        removed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_set_cookie() {
+        let cookie =
+            parse_set_cookie("id=a3fWa; Expires=Thu, 21 Oct 2021 07:28:00 GMT; Secure").unwrap();
+        assert_eq!(cookie.name, "id");
+        assert_eq!(cookie.value, "a3fWa");
+        assert!(!cookie.removed);
+
+        assert!(parse_set_cookie("id=; Max-Age=0").unwrap().removed);
+        assert!(parse_set_cookie("garbage").is_none());
+        assert!(parse_set_cookie("=value").is_none());
+    }
+}
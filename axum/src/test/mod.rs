@@ -0,0 +1,499 @@
+//! An in-process client for testing applications.
+//!
+//! [`TestClient`] sends requests straight to a [`Router`], or any other service, by calling it
+//! through [`tower::Service`]. No socket is bound, so tests are quick and can run in parallel.
+//! Cookies set by responses are sent back with later requests, the way a browser would.
+//!
+//! ```rust
+//! use axum::{routing::get, test::TestClient, Router};
+//!
+//! # async {
+//! let app = Router::new().route("/", get(|| async { "Hello, World!" }));
+//! let client = TestClient::new(app);
+//!
+//! let res = client.get("/").send().await;
+//! assert_eq!(res.status(), 200);
+//! assert_eq!(res.text().await, "Hello, World!");
+//! # };
+//! ```
+//!
+//! Besides plain requests there are helpers for [JSON](TestRequest::json),
+//! [forms](TestRequest::form), [multipart bodies](MultipartForm),
+//! [server-sent events](TestResponse::into_sse) and [WebSockets](TestRequest::websocket).
+//!
+//! Everything in here panics instead of returning errors, a failure is a failed test.
+//!
+//! [`Router`]: crate::Router
+
+use crate::{body::Body, extract::Request, response::Response};
+use bytes::Bytes;
+use http::{
+    header::{self, HeaderName, HeaderValue},
+    HeaderMap, Method, StatusCode,
+};
+use std::{
+    convert::Infallible,
+    fmt,
+    sync::{Arc, Mutex},
+};
+use tower::{util::BoxCloneService, ServiceExt};
+use tower_service::Service;
+
+mod cookies;
+mod multipart;
+mod sse;
+#[cfg(feature = "ws")]
+mod ws;
+
+use self::cookies::CookieStore;
+
+pub use self::{
+    multipart::MultipartForm,
+    sse::{SseEvent, SseStream},
+};
+
+#[cfg(feature = "ws")]
+pub use self::ws::TestWebSocket;
+
+/// A client that calls a service in process.
+///
+/// Clones share their cookies.
+///
+/// See the [module docs](self) for an example.
+#[derive(Clone)]
+pub struct TestClient {
+    // `BoxCloneService` isn't `Sync`, the mutex makes the client shareable between tasks
+    service: Arc<Mutex<BoxCloneService<Request, Response, Infallible>>>,
+    cookies: Arc<Mutex<CookieStore>>,
+}
+
+impl TestClient {
+    /// Create a client for the given service, usually a [`Router`](crate::Router).
+    pub fn new<S>(service: S) -> Self
+    where
+        S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+        S::Future: Send + 'static,
+    {
+        Self {
+            service: Arc::new(Mutex::new(BoxCloneService::new(service))),
+            cookies: Default::default(),
+        }
+    }
+
+    /// Start a `GET` request.
+    pub fn get(&self, uri: &str) -> TestRequest {
+        self.request(Method::GET, uri)
+    }
+
+    /// Start a `HEAD` request.
+    pub fn head(&self, uri: &str) -> TestRequest {
+        self.request(Method::HEAD, uri)
+    }
+
+    /// Start a `POST` request.
+    pub fn post(&self, uri: &str) -> TestRequest {
+        self.request(Method::POST, uri)
+    }
+
+    /// Start a `PUT` request.
+    pub fn put(&self, uri: &str) -> TestRequest {
+        self.request(Method::PUT, uri)
+    }
+
+    /// Start a `PATCH` request.
+    pub fn patch(&self, uri: &str) -> TestRequest {
+        self.request(Method::PATCH, uri)
+    }
+
+    /// Start a `DELETE` request.
+    pub fn delete(&self, uri: &str) -> TestRequest {
+        self.request(Method::DELETE, uri)
+    }
+
+    /// Start a request with any method. `uri` is the path and query, e.g. `/users?page=2`.
+    pub fn request(&self, method: Method, uri: &str) -> TestRequest {
+        TestRequest {
+            client: self.clone(),
+            builder: http::Request::builder().method(method).uri(uri),
+            body: Body::empty(),
+        }
+    }
+
+    /// The value of a stored cookie.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies.lock().unwrap().get(name)
+    }
+
+    /// Store a cookie, as if a response had set it.
+    pub fn set_cookie(&self, name: &str, value: &str) {
+        self.cookies.lock().unwrap().set(name, value);
+    }
+
+    /// Forget every stored cookie.
+    pub fn clear_cookies(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+
+    fn service(&self) -> BoxCloneService<Request, Response, Infallible> {
+        self.service.lock().unwrap().clone()
+    }
+}
+
+impl fmt::Debug for TestClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestClient")
+            .field("cookies", &self.cookies)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A request being built, created with the methods on [`TestClient`].
+#[must_use]
+pub struct TestRequest {
+    client: TestClient,
+    builder: http::request::Builder,
+    body: Body,
+}
+
+impl TestRequest {
+    /// Add a header.
+    pub fn header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<http::Error>,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<http::Error>,
+    {
+        self.builder = self.builder.header(key, value);
+        self
+    }
+
+    /// Add an `Authorization: Bearer <token>` header.
+    pub fn bearer_auth(self, token: impl fmt::Display) -> Self {
+        self.header(header::AUTHORIZATION, format!("Bearer {token}"))
+    }
+
+    /// Set the body.
+    pub fn body(mut self, body: impl Into<Body>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Set a JSON body and `Content-Type: application/json`.
+    #[cfg(feature = "json")]
+    pub fn json<T>(self, value: &T) -> Self
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        let body = serde_json::to_vec(value).expect("failed to serialize the JSON body");
+
+        self.header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(body)
+    }
+
+    /// Set a `application/x-www-form-urlencoded` body.
+    #[cfg(feature = "form")]
+    pub fn form<T>(self, value: &T) -> Self
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        let body = serde_urlencoded::to_string(value).expect("failed to serialize the form body");
+
+        self.header(
+            header::CONTENT_TYPE,
+            mime::APPLICATION_WWW_FORM_URLENCODED.as_ref(),
+        )
+        .body(body)
+    }
+
+    /// Set a `multipart/form-data` body.
+    pub fn multipart(self, form: MultipartForm) -> Self {
+        let (content_type, body) = form.encode();
+
+        self.header(header::CONTENT_TYPE, content_type).body(body)
+    }
+
+    /// Send the request and wait for the response head. The body can be read from the
+    /// [`TestResponse`] afterwards.
+    ///
+    /// Stored cookies are sent along unless a `Cookie` header was set explicitly.
+    pub async fn send(self) -> TestResponse {
+        let Self {
+            client,
+            builder,
+            body,
+        } = self;
+
+        let mut req = builder.body(body).expect("invalid test request");
+        client.cookies.lock().unwrap().apply(req.headers_mut());
+
+        let res = client
+            .service()
+            .oneshot(req)
+            .await
+            .unwrap_or_else(|err| match err {});
+
+        client.cookies.lock().unwrap().update(res.headers());
+
+        TestResponse { response: res }
+    }
+}
+
+impl fmt::Debug for TestRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestRequest")
+            .field("builder", &self.builder)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The response to a [`TestRequest`].
+#[derive(Debug)]
+pub struct TestResponse {
+    response: Response,
+}
+
+impl TestResponse {
+    /// The status code.
+    pub fn status(&self) -> StatusCode {
+        self.response.status()
+    }
+
+    /// The headers.
+    pub fn headers(&self) -> &HeaderMap {
+        self.response.headers()
+    }
+
+    /// Read the whole body.
+    pub async fn bytes(self) -> Bytes {
+        hyper::body::to_bytes(self.response.into_body())
+            .await
+            .expect("failed to read the response body")
+    }
+
+    /// Read the whole body as UTF-8 text.
+    pub async fn text(self) -> String {
+        String::from_utf8(self.bytes().await.to_vec()).expect("response body isn't UTF-8")
+    }
+
+    /// Read the whole body as JSON.
+    #[cfg(feature = "json")]
+    pub async fn json<T>(self) -> T
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        serde_json::from_slice(&self.bytes().await).expect("response body isn't the expected JSON")
+    }
+
+    /// Read the next chunk of a streaming body, `None` once it has ended.
+    pub async fn chunk(&mut self) -> Option<Bytes> {
+        use http_body::Body as _;
+
+        self.response
+            .body_mut()
+            .data()
+            .await
+            .map(|chunk| chunk.expect("failed to read the response body"))
+    }
+
+    /// Read the next chunk of a streaming body as UTF-8 text.
+    pub async fn chunk_text(&mut self) -> Option<String> {
+        let chunk = self.chunk().await?;
+        Some(String::from_utf8(chunk.to_vec()).expect("response chunk isn't UTF-8"))
+    }
+
+    /// Read the body as a stream of [server-sent events](crate::response::sse).
+    pub fn into_sse(self) -> SseStream {
+        SseStream::new(self.response.into_body())
+    }
+
+    /// The underlying response.
+    pub fn into_inner(self) -> Response {
+        self.response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        http::header::{COOKIE, SET_COOKIE},
+        response::{
+            sse::{Event, Sse},
+            AppendHeaders, IntoResponse,
+        },
+        routing::{get, post},
+        Router,
+    };
+    use futures_util::stream;
+
+    #[crate::test]
+    async fn sends_requests_without_a_socket() {
+        let app = Router::new().route("/echo", post(|body: String| async move { body }));
+        let client = TestClient::new(app);
+
+        let res = client.post("/echo").body("hello").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.text().await, "hello");
+
+        let res = client.get("/missing").send().await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[cfg(feature = "json")]
+    #[crate::test]
+    async fn json_round_trip() {
+        use crate::Json;
+        use serde_json::{json, Value};
+
+        let app = Router::new().route(
+            "/",
+            post(|Json(value): Json<Value>| async move { Json(json!({ "got": value })) }),
+        );
+        let client = TestClient::new(app);
+
+        let body: Value = client
+            .post("/")
+            .json(&json!({ "a": 1 }))
+            .send()
+            .await
+            .json()
+            .await;
+        assert_eq!(body, json!({ "got": { "a": 1 } }));
+    }
+
+    #[cfg(feature = "form")]
+    #[crate::test]
+    async fn form_body() {
+        let app = Router::new().route(
+            "/",
+            post(
+                |crate::Form(form): crate::Form<Vec<(String, String)>>| async move {
+                    format!("{form:?}")
+                },
+            ),
+        );
+        let client = TestClient::new(app);
+
+        let res = client.post("/").form(&[("name", "a b")]).send().await;
+        assert_eq!(res.text().await, r#"[("name", "a b")]"#);
+    }
+
+    #[crate::test]
+    async fn keeps_cookies_between_requests() {
+        let app = Router::new()
+            .route(
+                "/login",
+                post(|| async {
+                    AppendHeaders([
+                        (SET_COOKIE, "session=abc; HttpOnly; Path=/"),
+                        (SET_COOKIE, "theme=dark"),
+                    ])
+                }),
+            )
+            .route(
+                "/logout",
+                post(|| async { AppendHeaders([(SET_COOKIE, "session=; Max-Age=0")]) }),
+            )
+            .route(
+                "/whoami",
+                get(|headers: HeaderMap| async move {
+                    headers
+                        .get(COOKIE)
+                        .map(|value| value.to_str().unwrap().to_owned())
+                        .unwrap_or_default()
+                }),
+            );
+        let client = TestClient::new(app);
+
+        client.post("/login").send().await;
+        assert_eq!(client.cookie("session").as_deref(), Some("abc"));
+        assert_eq!(
+            client.get("/whoami").send().await.text().await,
+            "session=abc; theme=dark"
+        );
+
+        // an explicit header wins
+        let res = client.get("/whoami").header(COOKIE, "other=1").send().await;
+        assert_eq!(res.text().await, "other=1");
+
+        client.post("/logout").send().await;
+        assert_eq!(client.cookie("session"), None);
+        assert_eq!(
+            client.get("/whoami").send().await.text().await,
+            "theme=dark"
+        );
+    }
+
+    #[cfg(feature = "multipart")]
+    #[crate::test]
+    async fn multipart_body() {
+        use crate::extract::Multipart;
+
+        let app = Router::new().route(
+            "/upload",
+            post(|mut multipart: Multipart| async move {
+                let mut parts = Vec::new();
+                while let Some(field) = multipart.next_field().await.unwrap() {
+                    let name = field.name().unwrap().to_owned();
+                    let file_name = field.file_name().map(ToOwned::to_owned);
+                    let content_type = field.content_type().map(ToOwned::to_owned);
+                    let data = field.bytes().await.unwrap();
+                    parts.push(format!(
+                        "{name} {file_name:?} {content_type:?} {}",
+                        data.len()
+                    ));
+                }
+                parts.join("\n")
+            }),
+        );
+        let client = TestClient::new(app);
+
+        let form = MultipartForm::new().text("title", "me").file(
+            "avatar",
+            "me.png",
+            "image/png",
+            vec![0_u8; 300],
+        );
+        let res = client.post("/upload").multipart(form).send().await;
+
+        assert_eq!(
+            res.text().await,
+            "title None None 2\navatar Some(\"me.png\") Some(\"image/png\") 300"
+        );
+    }
+
+    #[crate::test]
+    async fn reads_server_sent_events() {
+        let app = Router::new().route(
+            "/events",
+            get(|| async {
+                let events = stream::iter(vec![
+                    Ok::<_, Infallible>(Event::default().data("one")),
+                    Ok(Event::default().event("update").id("2").data("two\nlines")),
+                ]);
+                Sse::new(events).into_response()
+            }),
+        );
+        let client = TestClient::new(app);
+
+        let mut events = client.get("/events").send().await.into_sse();
+
+        let first = events.next_event().await.unwrap();
+        assert_eq!(first.data, "one");
+        assert_eq!(first.event, None);
+
+        let second = events.next_event().await.unwrap();
+        assert_eq!(second.event.as_deref(), Some("update"));
+        assert_eq!(second.id.as_deref(), Some("2"));
+        assert_eq!(second.data, "two\nlines");
+
+        assert_eq!(events.next_event().await, None);
+    }
+
+    #[test]
+    fn client_is_send_and_sync() {
+        crate::test_helpers::assert_send::<TestClient>();
+        crate::test_helpers::assert_sync::<TestClient>();
+    }
+}
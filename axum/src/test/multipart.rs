@@ -0,0 +1,127 @@
+use bytes::Bytes;
+
+/// A `multipart/form-data` body for [`TestRequest::multipart`](super::TestRequest::multipart).
+///
+/// ```rust
+/// use axum::test::MultipartForm;
+///
+/// let form = MultipartForm::new()
+///     .text("title", "Holiday")
+///     .file("photo", "beach.jpg", "image/jpeg", vec![0xff, 0xd8, 0xff]);
+/// ```
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct MultipartForm {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+struct Part {
+    name: String,
+    file_name: Option<String>,
+    content_type: Option<String>,
+    data: Bytes,
+}
+
+impl MultipartForm {
+    /// Create an empty form.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a text field.
+    pub fn text(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.parts.push(Part {
+            name: name.into(),
+            file_name: None,
+            content_type: None,
+            data: Bytes::from(value.into()),
+        });
+        self
+    }
+
+    /// Add a file field.
+    pub fn file(
+        mut self,
+        name: impl Into<String>,
+        file_name: impl Into<String>,
+        content_type: impl Into<String>,
+        data: impl Into<Bytes>,
+    ) -> Self {
+        self.parts.push(Part {
+            name: name.into(),
+            file_name: Some(file_name.into()),
+            content_type: Some(content_type.into()),
+            data: data.into(),
+        });
+        self
+    }
+
+    /// The `Content-Type` header value and the encoded body.
+    pub(super) fn encode(&self) -> (String, Vec<u8>) {
+        let boundary = self.boundary();
+        let mut body = Vec::new();
+
+        for part in &self.parts {
+            body.extend_from_slice(format!("--{boundary}\r\n").as_bytes());
+            body.extend_from_slice(
+                format!(
+                    "Content-Disposition: form-data; name=\"{}\"",
+                    escape(&part.name)
+                )
+                .as_bytes(),
+            );
+            if let Some(file_name) = &part.file_name {
+                body.extend_from_slice(format!("; filename=\"{}\"", escape(file_name)).as_bytes());
+            }
+            body.extend_from_slice(b"\r\n");
+            if let Some(content_type) = &part.content_type {
+                body.extend_from_slice(format!("Content-Type: {content_type}\r\n").as_bytes());
+            }
+            body.extend_from_slice(b"\r\n");
+            body.extend_from_slice(&part.data);
+            body.extend_from_slice(b"\r\n");
+        }
+
+        body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+
+        (format!("multipart/form-data; boundary={boundary}"), body)
+    }
+
+    /// A boundary that doesn't occur in any of the parts.
+    fn boundary(&self) -> String {
+        (0_u64..)
+            .map(|n| format!("axum-test-boundary-{n}"))
+            .find(|boundary| {
+                self.parts
+                    .iter()
+                    .all(|part| memchr::memmem::find(&part.data, boundary.as_bytes()).is_none())
+            })
+            .unwrap()
+    }
+}
+
+/// Quotes in names are percent-encoded, like browsers do.
+fn escape(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_a_boundary_not_in_the_data() {
+        let form = MultipartForm::new().text("a", "--axum-test-boundary-0\r\n");
+        let (content_type, body) = form.encode();
+
+        assert_eq!(
+            content_type,
+            "multipart/form-data; boundary=axum-test-boundary-1"
+        );
+        assert!(body.ends_with(b"--axum-test-boundary-1--\r\n"));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::body::Body;
+
+/// A stream of server-sent events, see [`TestResponse::into_sse`](super::TestResponse::into_sse).
+#[derive(Debug)]
+pub struct SseStream {
+    body: Body,
+    buffer: Vec<u8>,
+}
+
+/// A single server-sent event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SseEvent {
+    /// The `event:` field.
+    pub event: Option<String>,
+    /// The `data:` lines, joined with newlines.
+    pub data: String,
+    /// The `id:` field.
+    pub id: Option<String>,
+    /// The `retry:` field, in milliseconds.
+    pub retry: Option<u64>,
+}
+
+impl SseStream {
+    pub(super) fn new(body: Body) -> Self {
+        Self {
+            body,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Wait for the next event, `None` once the stream has ended.
+    ///
+    /// Comments, such as keep-alive messages, are skipped.
+    pub async fn next_event(&mut self) -> Option<SseEvent> {
+        use http_body::Body as _;
+
+        loop {
+            while let Some(end) = find_blank_line(&self.buffer) {
+                let block = self.buffer.drain(..end.end).collect::<Vec<_>>();
+                let block = String::from_utf8(block[..end.start].to_vec())
+                    .expect("server-sent event isn't UTF-8");
+
+                if let Some(event) = parse_event(&block) {
+                    return Some(event);
+                }
+            }
+
+            let chunk = self.body.data().await?;
+            let chunk = chunk.expect("failed to read the response body");
+            self.buffer.extend_from_slice(&chunk);
+        }
+    }
+}
+
+/// Where the first blank line is, as the range of the event before it to the end of the line.
+fn find_blank_line(buffer: &[u8]) -> Option<std::ops::Range<usize>> {
+    let lf = memchr::memmem::find(buffer, b"\n\n").map(|at| at..at + 2);
+    let crlf = memchr::memmem::find(buffer, b"\r\n\r\n").map(|at| at..at + 4);
+
+    match (lf, crlf) {
+        (Some(lf), Some(crlf)) => Some(if lf.start < crlf.start { lf } else { crlf }),
+        (lf, crlf) => lf.or(crlf),
+    }
+}
+
+/// `None` for blocks that only hold comments.
+fn parse_event(block: &str) -> Option<SseEvent> {
+    let mut event = SseEvent::default();
+    let mut data = Vec::new();
+    let mut has_fields = false;
+
+    for line in block.lines() {
+        if line.starts_with(':') {
+            continue;
+        }
+
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        has_fields = true;
+
+        match field {
+            "event" => event.event = Some(value.to_owned()),
+            "data" => data.push(value),
+            "id" => event.id = Some(value.to_owned()),
+            "retry" => event.retry = value.parse().ok(),
+            _ => {}
+        }
+    }
+
+    event.data = data.join("\n");
+
+    has_fields.then_some(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[crate::test]
+    async fn skips_comments_and_joins_data() {
+        let body = Body::from(": keep-alive\n\ndata: a\ndata: b\nretry: 10\n\n");
+        let mut stream = SseStream::new(body);
+
+        let event = stream.next_event().await.unwrap();
+        assert_eq!(event.data, "a\nb");
+        assert_eq!(event.retry, Some(10));
+        assert_eq!(stream.next_event().await, None);
+    }
+}
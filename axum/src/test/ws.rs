@@ -0,0 +1,155 @@
+use super::{TestClient, TestRequest};
+use crate::extract::ws::Message;
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::DuplexStream;
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, Error},
+    WebSocketStream,
+};
+
+/// How much the in-memory connection buffers in each direction.
+const DUPLEX_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The client side of a WebSocket connection, see [`TestRequest::websocket`].
+#[derive(Debug)]
+pub struct TestWebSocket {
+    inner: WebSocketStream<DuplexStream>,
+}
+
+impl TestRequest {
+    /// Open a WebSocket connection, panicking if the service doesn't accept the upgrade.
+    ///
+    /// Upgrades need a real connection, so this serves HTTP over an in-memory pipe instead of
+    /// calling the service directly. The method and body of the request are ignored.
+    pub async fn websocket(self) -> TestWebSocket {
+        let Self {
+            client, builder, ..
+        } = self;
+
+        let template = builder.body(()).expect("invalid test request");
+
+        let mut req = format!("ws://localhost{}", template.uri())
+            .into_client_request()
+            .expect("invalid WebSocket URI");
+        req.headers_mut().extend(template.headers().clone());
+        client.cookies.lock().unwrap().apply(req.headers_mut());
+
+        let (client_io, server_io) = tokio::io::duplex(DUPLEX_BUFFER_SIZE);
+        tokio::spawn(crate::serve::serve_in_memory(server_io, client.service()));
+
+        let (inner, res) = match tokio_tungstenite::client_async(req, client_io).await {
+            Ok(connected) => connected,
+            Err(Error::Http(res)) => panic!("WebSocket upgrade refused with {}", res.status()),
+            Err(err) => panic!("WebSocket handshake failed: {err}"),
+        };
+
+        client.cookies.lock().unwrap().update(res.headers());
+
+        TestWebSocket { inner }
+    }
+}
+
+impl TestWebSocket {
+    /// Send a message.
+    pub async fn send(&mut self, message: Message) {
+        self.inner
+            .send(message.into_tungstenite())
+            .await
+            .expect("failed to send WebSocket message");
+    }
+
+    /// Send a text message.
+    pub async fn send_text(&mut self, text: impl Into<String>) {
+        self.send(Message::Text(text.into())).await;
+    }
+
+    /// Send a value as a JSON text message.
+    #[cfg(feature = "json")]
+    pub async fn send_json<T>(&mut self, value: &T)
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        let text = serde_json::to_string(value).expect("failed to serialize the message");
+        self.send_text(text).await;
+    }
+
+    /// Wait for the next message, `None` once the connection is closed.
+    pub async fn recv(&mut self) -> Option<Message> {
+        loop {
+            let message = self.inner.next().await?;
+            let message = message.expect("failed to receive WebSocket message");
+
+            if let Some(message) = Message::from_tungstenite(message) {
+                return Some(message);
+            }
+        }
+    }
+
+    /// Wait for the next message, panicking unless it's text.
+    pub async fn recv_text(&mut self) -> String {
+        match self.recv().await {
+            Some(Message::Text(text)) => text,
+            other => panic!("expected a text message, got {other:?}"),
+        }
+    }
+
+    /// Wait for the next message and parse it as JSON.
+    #[cfg(feature = "json")]
+    pub async fn recv_json<T>(&mut self) -> T
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let text = self.recv_text().await;
+        serde_json::from_str(&text).expect("message isn't the expected JSON")
+    }
+
+    /// Close the connection.
+    pub async fn close(mut self) {
+        // the server may already be gone
+        let _ = self.inner.close(None).await;
+    }
+}
+
+impl TestClient {
+    /// Start a WebSocket request, shorthand for `client.get(uri).websocket()`.
+    pub async fn websocket(&self, uri: &str) -> TestWebSocket {
+        self.get(uri).websocket().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{extract::ws::WebSocketUpgrade, response::Response, routing::get, Router};
+
+    #[crate::test]
+    async fn echoes_messages() {
+        async fn handler(ws: WebSocketUpgrade) -> Response {
+            ws.on_upgrade(|mut socket| async move {
+                while let Some(Ok(message)) = socket.recv().await {
+                    if socket.send(message).await.is_err() {
+                        break;
+                    }
+                }
+            })
+        }
+
+        let client = TestClient::new(Router::new().route("/ws", get(handler)));
+        let mut socket = client.websocket("/ws").await;
+
+        socket.send_text("hello").await;
+        assert_eq!(socket.recv_text().await, "hello");
+
+        socket.send(Message::Binary(vec![1, 2, 3])).await;
+        assert_eq!(socket.recv().await, Some(Message::Binary(vec![1, 2, 3])));
+
+        socket.close().await;
+    }
+
+    #[crate::test]
+    #[should_panic(expected = "WebSocket upgrade refused with 404 Not Found")]
+    async fn panics_when_the_upgrade_is_refused() {
+        let client = TestClient::new(Router::new());
+        client.websocket("/ws").await;
+    }
+}
@@ -0,0 +1,35 @@
+use std::process::Command;
+
+/// Exposes the commit being built as `GIT_SHA` for `/version`.
+///
+/// A `GIT_SHA` set in the environment wins, for builds without the `.git` directory (e.g. in a
+/// container). Falls back to `unknown`.
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+
+    if let Ok(head) = std::fs::read_to_string(".git/HEAD") {
+        if let Some(reference) = head.trim().strip_prefix("ref: ") {
+            println!("cargo:rerun-if-changed=.git/{}", reference);
+        }
+    }
+
+    let sha = match std::env::var("GIT_SHA") {
+        Ok(sha) if !sha.is_empty() => sha,
+        _ => git_sha().unwrap_or_else(|| "unknown".to_string())
+    };
+
+    println!("cargo:rustc-env=GIT_SHA={}", sha);
+}
+
+fn git_sha() -> Option<String> {
+    let output = Command::new("git").args(["rev-parse", "--short=12", "HEAD"]).output().ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let sha = String::from_utf8(output.stdout).ok()?;
+
+    return Some(sha.trim().to_string());
+}
@@ -0,0 +1,22 @@
+-- Usernames are unique, sessions belong to a user and go away with it.
+
+-- Keep the oldest row for any username that was registered twice, like on SQLite. There's no
+-- creation time yet, but users rows have only ever been inserted up to here, so the age of the
+-- transaction that wrote each one (xmin) tells which is oldest, with the id breaking ties.
+DELETE FROM users WHERE id IN (
+  SELECT id FROM (
+    SELECT id, ROW_NUMBER() OVER (PARTITION BY username ORDER BY age(xmin) DESC, id) AS position
+    FROM users
+  ) ranked
+  WHERE position > 1
+);
+
+ALTER TABLE users ADD CONSTRAINT users_username_key UNIQUE (username);
+
+DELETE FROM sessions WHERE user_id NOT IN (SELECT id FROM users);
+
+ALTER TABLE sessions ADD CONSTRAINT sessions_user_id_fkey
+  FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
+
+CREATE UNIQUE INDEX sessions_token_idx ON sessions (token);
+CREATE INDEX sessions_user_id_idx ON sessions (user_id);
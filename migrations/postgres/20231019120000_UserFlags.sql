@@ -0,0 +1,3 @@
+-- Accounts can be disabled without deleting them, and admins are flagged per user.
+ALTER TABLE users ADD COLUMN disabled BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN is_admin BIGINT NOT NULL DEFAULT 0;
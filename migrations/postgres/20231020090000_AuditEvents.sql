@@ -0,0 +1,28 @@
+-- Security-relevant events, see src/audit.rs. There's no foreign key on the user ids so the
+-- history outlives the accounts it's about.
+CREATE TABLE audit_events (
+  id BIGSERIAL PRIMARY KEY,
+  occurred_at TIMESTAMPTZ NOT NULL,
+  kind VARCHAR(64) NOT NULL,
+  actor_id VARCHAR(256),
+  target_id VARCHAR(256),
+  ip VARCHAR(64),
+  user_agent VARCHAR(512),
+  request_id VARCHAR(256),
+  details TEXT NOT NULL
+);
+
+CREATE INDEX audit_events_kind_idx ON audit_events (kind);
+CREATE INDEX audit_events_actor_id_idx ON audit_events (actor_id);
+CREATE INDEX audit_events_target_id_idx ON audit_events (target_id);
+CREATE INDEX audit_events_occurred_at_idx ON audit_events (occurred_at);
+
+-- The log is append-only.
+CREATE FUNCTION audit_events_append_only() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only BEFORE UPDATE OR DELETE ON audit_events
+  FOR EACH ROW EXECUTE FUNCTION audit_events_append_only();
@@ -0,0 +1,12 @@
+-- Profile fields users edit themselves. `version` goes up with every profile change and backs the
+-- ETag of GET /me, so concurrent edits can be detected.
+ALTER TABLE users ADD COLUMN display_name VARCHAR(256);
+ALTER TABLE users ADD COLUMN email VARCHAR(256);
+ALTER TABLE users ADD COLUMN locale VARCHAR(64);
+ALTER TABLE users ADD COLUMN timezone VARCHAR(64);
+ALTER TABLE users ADD COLUMN avatar_key VARCHAR(512);
+ALTER TABLE users ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE users ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE users ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE UNIQUE INDEX users_email_idx ON users (email);
@@ -0,0 +1,16 @@
+-- Where a session was started from, see src/client.rs. A session with a bound_range only works
+-- from addresses in that range.
+ALTER TABLE sessions ADD COLUMN user_agent VARCHAR(512);
+ALTER TABLE sessions ADD COLUMN ip VARCHAR(64);
+ALTER TABLE sessions ADD COLUMN device_id VARCHAR(64);
+ALTER TABLE sessions ADD COLUMN bound_range VARCHAR(64);
+
+-- The devices each user has logged in from, so a login from another one can be pointed out.
+-- Unlike sessions these aren't purged.
+CREATE TABLE known_devices (
+  user_id VARCHAR(256) NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  device_id VARCHAR(64) NOT NULL,
+  first_seen_at TIMESTAMPTZ NOT NULL,
+  last_seen_at TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (user_id, device_id)
+);
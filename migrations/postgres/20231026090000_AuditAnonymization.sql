@@ -0,0 +1,12 @@
+-- The log stays append-only, except that the address and browser of an account purged for good
+-- are forgotten, see src/jobs/deletion.rs. An update may only clear those two.
+CREATE OR REPLACE FUNCTION audit_events_append_only() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP = 'UPDATE' AND NEW.ip IS NULL AND NEW.user_agent IS NULL
+    AND to_jsonb(NEW) - 'ip' - 'user_agent' = to_jsonb(OLD) - 'ip' - 'user_agent' THEN
+    RETURN NEW;
+  END IF;
+
+  RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
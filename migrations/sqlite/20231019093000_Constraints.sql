@@ -0,0 +1,33 @@
+-- Usernames are unique, sessions belong to a user and go away with it.
+-- SQLite can't add constraints to an existing table so both tables are rebuilt.
+CREATE TABLE users_new (
+  id VARCHAR(256) PRIMARY KEY NOT NULL,
+  username VARCHAR(256) NOT NULL UNIQUE,
+  password_hash VARCHAR(256) NOT NULL
+);
+
+-- Keep the oldest row for any username that was registered twice.
+INSERT INTO users_new (id, username, password_hash)
+  SELECT id, username, password_hash FROM users
+  WHERE rowid IN (SELECT MIN(rowid) FROM users GROUP BY username);
+
+DROP TABLE users;
+ALTER TABLE users_new RENAME TO users;
+
+CREATE TABLE sessions_new (
+  id VARCHAR(256) PRIMARY KEY NOT NULL,
+  token VARCHAR(256) NOT NULL,
+  user_id VARCHAR(256) NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  valid_to DATETIME NOT NULL,
+  disabled INTEGER NOT NULL
+);
+
+INSERT INTO sessions_new (id, token, user_id, valid_to, disabled)
+  SELECT id, token, user_id, valid_to, disabled FROM sessions
+  WHERE user_id IN (SELECT id FROM users);
+
+DROP TABLE sessions;
+ALTER TABLE sessions_new RENAME TO sessions;
+
+CREATE UNIQUE INDEX sessions_token_idx ON sessions (token);
+CREATE INDEX sessions_user_id_idx ON sessions (user_id);
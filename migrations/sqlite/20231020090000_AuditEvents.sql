@@ -0,0 +1,29 @@
+-- Security-relevant events, see src/audit.rs. There's no foreign key on the user ids so the
+-- history outlives the accounts it's about.
+CREATE TABLE audit_events (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  occurred_at DATETIME NOT NULL,
+  kind VARCHAR(64) NOT NULL,
+  actor_id VARCHAR(256),
+  target_id VARCHAR(256),
+  ip VARCHAR(64),
+  user_agent VARCHAR(512),
+  request_id VARCHAR(256),
+  details TEXT NOT NULL
+);
+
+CREATE INDEX audit_events_kind_idx ON audit_events (kind);
+CREATE INDEX audit_events_actor_id_idx ON audit_events (actor_id);
+CREATE INDEX audit_events_target_id_idx ON audit_events (target_id);
+CREATE INDEX audit_events_occurred_at_idx ON audit_events (occurred_at);
+
+-- The log is append-only.
+CREATE TRIGGER audit_events_no_update BEFORE UPDATE ON audit_events
+BEGIN
+  SELECT RAISE(ABORT, 'audit_events is append-only');
+END;
+
+CREATE TRIGGER audit_events_no_delete BEFORE DELETE ON audit_events
+BEGIN
+  SELECT RAISE(ABORT, 'audit_events is append-only');
+END;
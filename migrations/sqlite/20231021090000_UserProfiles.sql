@@ -0,0 +1,14 @@
+-- Profile fields users edit themselves. `version` goes up with every profile change and backs the
+-- ETag of GET /me, so concurrent edits can be detected.
+ALTER TABLE users ADD COLUMN display_name VARCHAR(256);
+ALTER TABLE users ADD COLUMN email VARCHAR(256);
+ALTER TABLE users ADD COLUMN locale VARCHAR(64);
+ALTER TABLE users ADD COLUMN timezone VARCHAR(64);
+ALTER TABLE users ADD COLUMN avatar_key VARCHAR(512);
+ALTER TABLE users ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+-- SQLite only allows constant defaults here, existing accounts get the time of the migration.
+ALTER TABLE users ADD COLUMN created_at DATETIME NOT NULL DEFAULT '1970-01-01 00:00:00';
+ALTER TABLE users ADD COLUMN updated_at DATETIME NOT NULL DEFAULT '1970-01-01 00:00:00';
+UPDATE users SET created_at = datetime('now'), updated_at = datetime('now');
+
+CREATE UNIQUE INDEX users_email_idx ON users (email);
@@ -0,0 +1,5 @@
+-- Accounts their owner deleted. They are kept, locked, until the grace period is over and the
+-- deletion job removes them for good, taking their sessions along.
+ALTER TABLE users ADD COLUMN deleted_at DATETIME;
+
+CREATE INDEX users_deleted_at_idx ON users (deleted_at);
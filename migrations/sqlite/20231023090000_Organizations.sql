@@ -0,0 +1,40 @@
+-- Organizations group users, who each have a role per organization they belong to. Invitations
+-- are handed out as signed links, see src/org/invitation.rs.
+CREATE TABLE organizations (
+  id VARCHAR(256) PRIMARY KEY NOT NULL,
+  name VARCHAR(256) NOT NULL,
+  created_at DATETIME NOT NULL
+);
+
+CREATE TABLE memberships (
+  org_id VARCHAR(256) NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+  user_id VARCHAR(256) NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  role VARCHAR(32) NOT NULL,
+  created_at DATETIME NOT NULL,
+  PRIMARY KEY (org_id, user_id)
+);
+
+CREATE INDEX memberships_user_id_idx ON memberships (user_id);
+
+CREATE TABLE invitations (
+  id VARCHAR(256) PRIMARY KEY NOT NULL,
+  org_id VARCHAR(256) NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+  role VARCHAR(32) NOT NULL,
+  -- Only the account with this email can accept, anyone with the link if it's NULL.
+  email VARCHAR(256),
+  created_by VARCHAR(256) NOT NULL,
+  created_at DATETIME NOT NULL,
+  expires_at DATETIME NOT NULL,
+  accepted_by VARCHAR(256),
+  accepted_at DATETIME
+);
+
+CREATE INDEX invitations_org_id_idx ON invitations (org_id);
+
+-- The organization the session acts in.
+ALTER TABLE sessions ADD COLUMN org_id VARCHAR(256) REFERENCES organizations (id) ON DELETE SET NULL;
+
+-- Lets organization admins read the events of their own organization only.
+ALTER TABLE audit_events ADD COLUMN org_id VARCHAR(256);
+
+CREATE INDEX audit_events_org_id_idx ON audit_events (org_id);
@@ -0,0 +1,11 @@
+-- Admins with this permission can act as another user for a while, see src/impersonation.rs.
+ALTER TABLE users ADD COLUMN can_impersonate INTEGER NOT NULL DEFAULT 0;
+
+-- The admin acting as the session's user, NULL for the user's own sessions. The session goes
+-- with the admin's account.
+ALTER TABLE sessions ADD COLUMN impersonator_id VARCHAR(256) REFERENCES users (id) ON DELETE CASCADE;
+
+-- Set on everything recorded during an impersonation.
+ALTER TABLE audit_events ADD COLUMN impersonator_id VARCHAR(256);
+
+CREATE INDEX audit_events_impersonator_id_idx ON audit_events (impersonator_id);
@@ -0,0 +1,20 @@
+-- The log stays append-only, except that the address and browser of an account purged for good
+-- are forgotten, see src/jobs/deletion.rs. An update may only clear those two.
+DROP TRIGGER audit_events_no_update;
+
+CREATE TRIGGER audit_events_no_update BEFORE UPDATE ON audit_events
+WHEN NOT (
+  NEW.ip IS NULL AND NEW.user_agent IS NULL
+  AND NEW.id IS OLD.id
+  AND NEW.occurred_at IS OLD.occurred_at
+  AND NEW.kind IS OLD.kind
+  AND NEW.actor_id IS OLD.actor_id
+  AND NEW.target_id IS OLD.target_id
+  AND NEW.org_id IS OLD.org_id
+  AND NEW.impersonator_id IS OLD.impersonator_id
+  AND NEW.request_id IS OLD.request_id
+  AND NEW.details IS OLD.details
+)
+BEGIN
+  SELECT RAISE(ABORT, 'audit_events is append-only');
+END;
@@ -0,0 +1,247 @@
+//! The caller's account as a whole: `GET /me/export` hands out everything stored about them,
+//! `DELETE /me` deletes the account.
+//!
+//! Deleted accounts are locked right away but only purged after `deletion_grace_days`, see
+//! [`crate::jobs::AccountDeleter`]. Until then an admin can restore them with `user restore`.
+
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    extract::{Query, State},
+    http::{header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE}, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    BoxError, Json
+};
+use axum_extra::extract::cookie::{CookieJar, PrivateCookieJar};
+use chrono::{DateTime, Duration, Utc};
+use futures_util::{future, stream, Stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::{io, sync::Arc};
+
+use crate::{
+    audit::{self, AuditContext, AuditEvent, AuditFilter, AuditKind},
+    auth::AccountOwner,
+    blob::DynBlobStore,
+    config::Config,
+    cookies,
+    dto::{AccountDeletion, DeleteAccountRequest, SessionSummary, UserProfile},
+    org::{self, OrgRole},
+    state::AppState,
+    store::DynStore,
+    telemetry::AUTH,
+    user::{self, UpdateUserResult, User},
+    zip::ZipWriter
+};
+
+/// Audit events read from the store at a time while exporting.
+const EXPORT_PAGE_SIZE: u32 = 500;
+
+/// `DELETE /me` and `GET /me/export`.
+pub fn router() -> Router<AppState> {
+    return Router::new()
+        .route("/me", delete(delete_me))
+        .route("/me/export", get(export));
+}
+
+/// Deletes the account after checking the password, so a session left open somewhere isn't
+/// enough. Revokes every session and clears the session cookies. Not open to impersonation.
+///
+/// The only owner of an organization gets `409 Conflict`, somebody else has to be made an owner
+/// first, like for leaving it.
+async fn delete_me(State(db): State<DynStore>, State(config): State<Arc<Config>>, audit: AuditContext, AccountOwner(user): AccountOwner, private: PrivateCookieJar, plain: CookieJar, Json(request): Json<DeleteAccountRequest>) -> Response {
+    if !user::verify_password(&user, &request.password).await {
+        tracing::warn!(target: AUTH, event = "password_confirmation_failed", user_id = %user.id, action = "delete_account");
+        return (StatusCode::FORBIDDEN, "wrong password").into_response();
+    }
+
+    let orgs = match db.list_user_orgs(&user.id).await {
+        Ok(orgs) => orgs,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    };
+    for owned in orgs.iter().filter(|user_org| user_org.role == OrgRole::Owner) {
+        match org::routes::owner_count(&db, &owned.org.id).await {
+            Ok(count) if count <= 1 => return (StatusCode::CONFLICT, format!("you're the only owner of {}, make somebody else an owner first", owned.org.name)).into_response(),
+            Ok(_) => {},
+            Err(status) => return status.into_response()
+        }
+    }
+
+    let deleted_at = Utc::now();
+    match user::schedule_deletion(&user, deleted_at, &*db).await {
+        UpdateUserResult::Success => {},
+        UpdateUserResult::NotFound => return StatusCode::NOT_FOUND.into_response(),
+        UpdateUserResult::DatabaseError => return StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    }
+
+    let purge_after = deleted_at + Duration::days(config.deletion_grace_days as i64);
+
+    audit.event(AuditKind::AccountDeleted)
+        .actor(&user.id)
+        .target(&user.id)
+        .detail("purge_after", purge_after.to_rfc3339())
+        .record(&*db)
+        .await;
+
+    return (StatusCode::ACCEPTED, cookies::clear(private, plain), Json(AccountDeletion { deleted_at, purge_after })).into_response();
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One JSON document.
+    #[default]
+    Json,
+    /// A ZIP archive with a JSON file per kind of data, and the avatar.
+    Zip
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct ExportOptions {
+    format: ExportFormat
+}
+
+/// Everything in the export except the audit events, which are streamed after it.
+#[derive(Serialize)]
+struct ExportHead<'a> {
+    exported_at: DateTime<Utc>,
+    profile: UserProfile,
+    sessions: &'a [SessionSummary],
+    /// Always empty, there are no API keys yet. Listed so the export says so rather than leave
+    /// it to be guessed.
+    api_keys: [(); 0]
+}
+
+/// `GET /me/export?format=json|zip`, the profile, the sessions without their tokens, the API keys
+/// and the audit events about the account, newest first. The audit events can be many, so
+/// they're streamed from the store page by page. Not open to impersonation: the data is the
+/// user's to take, not the support staff's.
+async fn export(State(db): State<DynStore>, State(blobs): State<DynBlobStore>, audit: AuditContext, AccountOwner(user): AccountOwner, Query(options): Query<ExportOptions>) -> Response {
+    // Everything that can fail is read before the response starts, so a failure is still a 500.
+    let sessions: Vec<SessionSummary> = match db.list_user_sessions(&user.id).await {
+        Ok(sessions) => sessions.iter().map(SessionSummary::from).collect(),
+        Err(err) => {
+            tracing::error!(user_id = %user.id, error = %err, "failed to list sessions for the export");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let avatar = match (&user.avatar_key, options.format) {
+        (Some(key), ExportFormat::Zip) => match blobs.get(key).await {
+            Ok(data) => data.map(|data| (key.rsplit('.').next().unwrap_or("bin").to_string(), data)),
+            Err(err) => {
+                tracing::error!(user_id = %user.id, key, error = %err, "failed to read avatar for the export");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        },
+        _ => None
+    };
+
+    audit.event(AuditKind::DataExported)
+        .actor(&user.id)
+        .target(&user.id)
+        .detail("format", match options.format { ExportFormat::Json => "json", ExportFormat::Zip => "zip" })
+        .record(&*db)
+        .await;
+
+    let exported_at = Utc::now();
+    let head = ExportHead { exported_at, profile: UserProfile::from(&user), sessions: &sessions, api_keys: [] };
+    let events = audit_events(db, &user);
+    let file_name = format!("account-export-{}", exported_at.format("%Y-%m-%d"));
+
+    let (content_type, extension, body) = match options.format {
+        ExportFormat::Json => ("application/json", "json", Body::from_stream(json_bundle(&head, events))),
+        ExportFormat::Zip => ("application/zip", "zip", Body::from_stream(zip_bundle(&head, avatar, events)))
+    };
+
+    return ([
+        (CONTENT_TYPE, content_type.to_string()),
+        (CONTENT_DISPOSITION, format!("attachment; filename=\"{}.{}\"", file_name, extension)),
+        (CACHE_CONTROL, "no-store".to_string())
+    ], body).into_response();
+}
+
+/// The events the user did or that were done to their account. Where somebody else did it,
+/// including an admin impersonating the user, the address and browser are that person's data,
+/// not the user's, and are left out.
+fn audit_events(db: DynStore, user: &User) -> impl Stream<Item = Result<AuditEvent, sqlx::Error>> {
+    let user_id = user.id.clone();
+    let filter = AuditFilter { user_id: Some(user_id.clone()), ..AuditFilter::default() };
+
+    return audit::stream_events(db, filter, EXPORT_PAGE_SIZE).map_ok(move |mut event| {
+        if event.actor_id.as_ref() != Some(&user_id) || event.impersonator_id.is_some() {
+            event.ip = None;
+            event.user_agent = None;
+        }
+        event
+    });
+}
+
+/// The events as the elements of a JSON array, each but the first with a leading comma, followed
+/// by `None` once there are no more.
+fn array_elements(events: impl Stream<Item = Result<AuditEvent, sqlx::Error>>) -> impl Stream<Item = Result<Option<Vec<u8>>, BoxError>> {
+    let mut first = true;
+
+    return events
+        .map_ok(Some)
+        .chain(stream::once(future::ready(Ok(None))))
+        .map(move |event| {
+            let event = match event? {
+                Some(event) => event,
+                None => return Ok(None)
+            };
+
+            let mut element = if std::mem::take(&mut first) { Vec::new() } else { vec![b','] };
+            serde_json::to_writer(&mut element, &event)?;
+            return Ok(Some(element));
+        });
+}
+
+/// `{"exported_at": …, "profile": …, "sessions": […], "api_keys": [], "audit_events": […]}`.
+fn json_bundle(head: &ExportHead, events: impl Stream<Item = Result<AuditEvent, sqlx::Error>> + Send + 'static) -> impl Stream<Item = Result<Bytes, BoxError>> + Send + 'static {
+    // The head without its closing brace, the events go in before it.
+    let mut start = serde_json::to_vec(head).expect("export head is serializable");
+    start.pop();
+    start.extend_from_slice(b",\"audit_events\":[");
+
+    let elements = array_elements(events).map_ok(|element| match element {
+        Some(element) => Bytes::from(element),
+        None => Bytes::from_static(b"]}")
+    });
+
+    return stream::once(future::ready(Ok(Bytes::from(start)))).chain(elements);
+}
+
+/// `profile.json`, `sessions.json`, `api_keys.json`, `audit_events.json` and, if there is one,
+/// `avatar.<ext>`. Going past the limits of [`ZipWriter`] ends the download with an error.
+fn zip_bundle(head: &ExportHead, avatar: Option<(String, Bytes)>, events: impl Stream<Item = Result<AuditEvent, sqlx::Error>> + Send + 'static) -> impl Stream<Item = Result<Bytes, BoxError>> + Send + 'static {
+    let mut zip = ZipWriter::new(head.exported_at);
+    let start = zip_head(&mut zip, head, avatar).map(|()| zip.take()).map_err(BoxError::from);
+
+    let elements = array_elements(events).map(move |element| {
+        match element? {
+            Some(element) => zip.write(&element)?,
+            None => {
+                zip.write(b"]")?;
+                zip.finish()?;
+            }
+        }
+        return Ok(zip.take());
+    });
+
+    return stream::once(future::ready(start)).chain(elements);
+}
+
+/// Everything in the archive up to the opening bracket of `audit_events.json`.
+fn zip_head(zip: &mut ZipWriter, head: &ExportHead, avatar: Option<(String, Bytes)>) -> io::Result<()> {
+    zip.file("profile.json", &serde_json::to_vec_pretty(&head.profile)?)?;
+    zip.file("sessions.json", &serde_json::to_vec_pretty(head.sessions)?)?;
+    zip.file("api_keys.json", &serde_json::to_vec_pretty(&head.api_keys)?)?;
+    if let Some((extension, data)) = avatar {
+        zip.file(&format!("avatar.{}", extension), &data)?;
+    }
+    zip.start_file("audit_events.json")?;
+
+    return zip.write(b"[");
+}
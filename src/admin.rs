@@ -0,0 +1,107 @@
+//! Endpoints under `/admin`, only open to admins.
+//!
+//! An admin sees the organization their session acts in, see [`AdminUser`]: its events, and the
+//! events of its members that belong to no organization, such as logins. Other organizations stay
+//! out of sight, switching to one needs a membership like for anybody else. A session acting in no
+//! organization sees the rest, the events no organization can, see [`AuditScope::Unowned`].
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router
+};
+use axum_extra::json_lines::JsonLines;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{self, AuditContext, AuditEvent, AuditFilter, AuditKind, AuditScope},
+    auth::AdminUser,
+    state::AppState,
+    store::DynStore
+};
+
+/// Events per page of `/admin/audit` when `limit` isn't given.
+const DEFAULT_PAGE_SIZE: u32 = 50;
+
+const MAX_PAGE_SIZE: u32 = 500;
+
+pub fn router() -> Router<AppState> {
+    return Router::new()
+        .route("/audit", get(list_audit_events))
+        .route("/audit/export", get(export_audit_events));
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Page {
+    pub limit: Option<u32>
+}
+
+#[derive(Serialize)]
+pub struct AuditPage {
+    pub events: Vec<AuditEvent>,
+    /// Pass as `before` to get the next page, `None` on the last one.
+    pub next_before: Option<i64>
+}
+
+/// `GET /admin/audit`, newest first. Takes the [`AuditFilter`] fields and `limit` as query parameters.
+async fn list_audit_events(
+    admin: AdminUser,
+    State(db): State<DynStore>,
+    Query(mut filter): Query<AuditFilter>,
+    Query(page): Query<Page>
+) -> Result<Json<AuditPage>, StatusCode> {
+    filter.scope = admin_scope(&admin);
+
+    return audit_page(&db, &filter, page).await;
+}
+
+/// The events an admin may see, see the module documentation.
+fn admin_scope(admin: &AdminUser) -> AuditScope {
+    return match &admin.org {
+        Some(org) => AuditScope::Tenant(org.id.clone()),
+        None => AuditScope::Unowned
+    };
+}
+
+/// One page of the events matching `filter`, also behind the audit log of an organization.
+pub async fn audit_page(db: &DynStore, filter: &AuditFilter, page: Page) -> Result<Json<AuditPage>, StatusCode> {
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let events = match db.list_audit_events(filter, limit).await {
+        Ok(events) => events,
+        Err(err) => {
+            tracing::error!(error = %err, "failed to list audit events");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    // A full page may or may not be the last one, the next request tells.
+    let next_before = if events.len() == limit as usize { events.last().map(|event| event.id) } else { None };
+
+    return Ok(Json(AuditPage { events, next_before }));
+}
+
+/// `GET /admin/audit/export`, every matching event as JSON Lines, newest first. The export itself
+/// is audited.
+async fn export_audit_events(
+    admin: AdminUser,
+    State(db): State<DynStore>,
+    audit: AuditContext,
+    Query(mut filter): Query<AuditFilter>
+) -> Response {
+    let mut event = audit.event(AuditKind::AuditExported)
+        .actor(&admin.user.id)
+        .detail("filter", serde_json::to_value(&filter).unwrap_or_default());
+    if let Some(org) = &admin.org {
+        event = event.org(&org.id);
+    }
+    event.record(&*db).await;
+
+    filter.scope = admin_scope(&admin);
+
+    let events = audit::stream_events(db, filter, MAX_PAGE_SIZE);
+
+    return ([(header::CONTENT_TYPE, "application/x-ndjson")], JsonLines::new(events)).into_response();
+}
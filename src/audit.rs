@@ -0,0 +1,308 @@
+//! The audit log: an append-only record of security-relevant events, kept in `audit_events`.
+//!
+//! Unlike the `auth` tracing events these are stored with the rest of the data, so they survive
+//! log rotation and can be queried by admins through `/admin/audit`.
+
+use std::{convert::Infallible, sync::Arc};
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts
+};
+use chrono::{DateTime, Utc};
+use futures_util::{stream, Stream, TryStreamExt};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{Map, Value};
+use sqlx::FromRow;
+
+use crate::{auth::AuthSession, client::ClientInfo, config::Config, store::{DynStore, Store}, telemetry::RequestId};
+
+/// What happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditKind {
+    UserRegistered,
+    /// An admin created the account.
+    UserCreated,
+    LoginSucceeded,
+    LoginFailed,
+    PasswordChanged,
+    SessionsRevoked,
+    AccountDisabled,
+    /// An admin permission was granted or taken away.
+    PermissionChanged,
+    AuditExported,
+    /// The user changed their profile or avatar.
+    ProfileUpdated,
+    /// The user downloaded their data.
+    DataExported,
+    /// The user deleted their account, which is removed for good after the grace period.
+    AccountDeleted,
+    /// The account was brought back during the grace period.
+    AccountRestored,
+    /// The deletion job removed the account for good.
+    AccountPurged,
+    OrgCreated,
+    InvitationCreated,
+    InvitationRevoked,
+    /// Somebody joined an organization through an invitation.
+    MemberAdded,
+    MemberRoleChanged,
+    /// A member was removed from an organization or left it.
+    MemberRemoved,
+    /// An admin started acting as another user.
+    ImpersonationStarted,
+    ImpersonationEnded
+}
+
+impl AuditKind {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            AuditKind::UserRegistered => "user_registered",
+            AuditKind::UserCreated => "user_created",
+            AuditKind::LoginSucceeded => "login_succeeded",
+            AuditKind::LoginFailed => "login_failed",
+            AuditKind::PasswordChanged => "password_changed",
+            AuditKind::SessionsRevoked => "sessions_revoked",
+            AuditKind::AccountDisabled => "account_disabled",
+            AuditKind::PermissionChanged => "permission_changed",
+            AuditKind::AuditExported => "audit_exported",
+            AuditKind::ProfileUpdated => "profile_updated",
+            AuditKind::DataExported => "data_exported",
+            AuditKind::AccountDeleted => "account_deleted",
+            AuditKind::AccountRestored => "account_restored",
+            AuditKind::AccountPurged => "account_purged",
+            AuditKind::OrgCreated => "org_created",
+            AuditKind::InvitationCreated => "invitation_created",
+            AuditKind::InvitationRevoked => "invitation_revoked",
+            AuditKind::MemberAdded => "member_added",
+            AuditKind::MemberRoleChanged => "member_role_changed",
+            AuditKind::MemberRemoved => "member_removed",
+            AuditKind::ImpersonationStarted => "impersonation_started",
+            AuditKind::ImpersonationEnded => "impersonation_ended"
+        };
+    }
+}
+
+/// A stored event.
+#[derive(FromRow, Debug, Clone, Serialize)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub occurred_at: DateTime<Utc>,
+    pub kind: String,
+    /// Who did it, `None` for the CLI and for anonymous requests such as failed logins.
+    pub actor_id: Option<String>,
+    /// Whose account it was done to.
+    pub target_id: Option<String>,
+    /// The organization it happened in, if any.
+    pub org_id: Option<String>,
+    /// The admin acting as the user when it happened, see [`crate::impersonation`].
+    pub impersonator_id: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    /// A JSON object with event specific fields, e.g. the reason a login failed.
+    #[serde(serialize_with = "raw_json")]
+    pub details: String
+}
+
+/// An event about to be recorded, see [`AuditContext::event`].
+#[derive(Debug, Clone)]
+pub struct NewAuditEvent {
+    pub occurred_at: DateTime<Utc>,
+    pub kind: AuditKind,
+    pub actor_id: Option<String>,
+    pub target_id: Option<String>,
+    pub org_id: Option<String>,
+    pub impersonator_id: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    pub details: Map<String, Value>
+}
+
+impl NewAuditEvent {
+    pub fn actor(mut self, user_id: &str) -> Self {
+        self.actor_id = Some(user_id.to_string());
+        return self;
+    }
+
+    pub fn target(mut self, user_id: &str) -> Self {
+        self.target_id = Some(user_id.to_string());
+        return self;
+    }
+
+    /// Files the event under the organization, so its admins can see it.
+    pub fn org(mut self, org_id: &str) -> Self {
+        self.org_id = Some(org_id.to_string());
+        return self;
+    }
+
+    pub fn detail(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.details.insert(key.to_string(), value.into());
+        return self;
+    }
+
+    /// Stores the event. A failure is logged rather than returned, the action it describes has
+    /// already happened by the time it's recorded.
+    pub async fn record(self, db: &dyn Store) {
+        if let Err(err) = db.add_audit_event(&self).await {
+            tracing::error!(kind = self.kind.as_str(), error = %err, "failed to record audit event");
+        }
+    }
+}
+
+/// Where a request came from, attached to every event recorded while handling it.
+#[derive(Debug, Clone, Default)]
+pub struct AuditContext {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    /// Set when the request comes from an impersonation session, so nothing done through one
+    /// passes for the user's own doing.
+    pub impersonator_id: Option<String>
+}
+
+impl AuditContext {
+    /// For actions taken through the command line, which have no request.
+    pub fn cli() -> Self {
+        return Self::default();
+    }
+
+    pub fn event(&self, kind: AuditKind) -> NewAuditEvent {
+        return NewAuditEvent {
+            occurred_at: Utc::now(),
+            kind,
+            actor_id: None,
+            target_id: None,
+            org_id: None,
+            impersonator_id: self.impersonator_id.clone(),
+            ip: self.ip.clone(),
+            user_agent: self.user_agent.clone(),
+            request_id: self.request_id.clone(),
+            details: Map::new()
+        };
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuditContext
+where
+    Arc<Config>: FromRef<S>,
+    S: Send + Sync
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let RequestId(request_id) = RequestId::from_request_parts(parts, state).await?;
+
+        let ClientInfo { ip, user_agent, .. } = ClientInfo::from_request_parts(parts, state).await?;
+        let ip = ip.map(|ip| ip.to_string());
+
+        // The session was loaded ahead of the handler by `auth::load_session`, if there is one.
+        let impersonator_id = parts.extensions
+            .get::<AuthSession>()
+            .and_then(|auth| auth.session.impersonator_id.clone());
+
+        return Ok(Self { ip, user_agent, request_id: Some(request_id), impersonator_id });
+    }
+}
+
+/// Which events to list. Every field that is set has to match.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditFilter {
+    pub kind: Option<String>,
+    pub actor_id: Option<String>,
+    pub target_id: Option<String>,
+    /// Events the user either did or that were done to their account.
+    pub user_id: Option<String>,
+    pub org_id: Option<String>,
+    /// Whose events an admin sees. Set by the server for `/admin`, it can't be given as a query
+    /// parameter.
+    #[serde(skip)]
+    pub scope: AuditScope,
+    /// Events recorded while this admin was impersonating somebody.
+    pub impersonator_id: Option<String>,
+    pub ip: Option<String>,
+    /// Events at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Events before this time.
+    pub until: Option<DateTime<Utc>>,
+    /// Events with a smaller id, the cursor for the next page.
+    pub before: Option<i64>
+}
+
+/// The part of the log an admin sees, see [`crate::admin`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum AuditScope {
+    /// Every event. For callers that narrow the filter down themselves, such as the account export.
+    #[default]
+    All,
+    /// Everything an organization's tenant can see: its own events, and events of no organization
+    /// that a current member did or that were done to one.
+    Tenant(String),
+    /// The events no tenant can see: of no organization, and neither done by nor to anybody in
+    /// one. Failed logins for unknown usernames, say, or events about users without memberships.
+    Unowned
+}
+
+impl AuditFilter {
+    /// Whether the event matches, for stores that filter in memory. `members` are the users of the
+    /// organization for [`AuditScope::Tenant`], of any organization for [`AuditScope::Unowned`].
+    pub fn matches(&self, event: &AuditEvent, members: &[String]) -> bool {
+        let involves_member = || [&event.actor_id, &event.target_id].into_iter().flatten().any(|id| members.contains(id));
+        let in_scope = match &self.scope {
+            AuditScope::All => true,
+            AuditScope::Tenant(org_id) => match &event.org_id {
+                Some(event_org_id) => event_org_id == org_id,
+                None => involves_member()
+            },
+            AuditScope::Unowned => event.org_id.is_none() && !involves_member()
+        };
+
+        return self.kind.as_ref().map_or(true, |kind| &event.kind == kind)
+            && self.actor_id.as_ref().map_or(true, |id| event.actor_id.as_ref() == Some(id))
+            && self.target_id.as_ref().map_or(true, |id| event.target_id.as_ref() == Some(id))
+            && self.user_id.as_ref().map_or(true, |id| event.actor_id.as_ref() == Some(id) || event.target_id.as_ref() == Some(id))
+            && self.org_id.as_ref().map_or(true, |id| event.org_id.as_ref() == Some(id))
+            && in_scope
+            && self.impersonator_id.as_ref().map_or(true, |id| event.impersonator_id.as_ref() == Some(id))
+            && self.ip.as_ref().map_or(true, |ip| event.ip.as_ref() == Some(ip))
+            && self.since.map_or(true, |since| event.occurred_at >= since)
+            && self.until.map_or(true, |until| event.occurred_at < until)
+            && self.before.map_or(true, |before| event.id < before);
+    }
+}
+
+/// Every event matching `filter`, newest first. They're read `page_size` at a time so a large log
+/// isn't held in memory.
+pub fn stream_events(db: DynStore, filter: AuditFilter, page_size: u32) -> impl Stream<Item = Result<AuditEvent, sqlx::Error>> {
+    return stream::try_unfold(Some(filter), move |filter| {
+        let db = db.clone();
+
+        async move {
+            let mut filter = match filter {
+                Some(filter) => filter,
+                None => return Ok(None)
+            };
+
+            let events = db.list_audit_events(&filter, page_size).await?;
+
+            let next = if events.len() == page_size as usize {
+                filter.before = events.last().map(|event| event.id);
+                Some(filter)
+            } else {
+                None
+            };
+
+            return Ok::<_, sqlx::Error>(Some((stream::iter(events.into_iter().map(Ok::<_, sqlx::Error>)), next)));
+        }
+    }).try_flatten();
+}
+
+/// Writes the stored JSON text as JSON rather than as a string.
+fn raw_json<S: Serializer>(details: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    return match serde_json::from_str::<Value>(details) {
+        Ok(value) => value.serialize(serializer),
+        Err(_) => serializer.serialize_str(details)
+    };
+}
@@ -0,0 +1,283 @@
+use std::sync::Arc;
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Request},
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap, StatusCode},
+    middleware::Next,
+    response::Response
+};
+
+use axum_extra::extract::cookie::{Key, PrivateCookieJar};
+
+use crate::{client::{ClientInfo, IpRange}, config::Config, cookies::SessionCookie, org::{OrgRole, Organization}, store::DynStore, telemetry::AUTH, user::{GetTokenSessionResult, Session, User}};
+
+/// The session token from the `Authorization: Bearer <token>` header, or from the session cookie
+/// if there is no such header. The token isn't checked.
+///
+/// Rejects with `401 Unauthorized` when there is neither.
+#[derive(Debug, Clone)]
+pub struct SessionToken(pub String);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for SessionToken
+where
+    Key: FromRef<S>,
+    S: Send + Sync
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(token) = bearer_token(&parts.headers) {
+            return Ok(SessionToken(token));
+        }
+
+        let jar = PrivateCookieJar::from_headers(&parts.headers, Key::from_ref(state));
+
+        return match SessionCookie::from_jar(&jar) {
+            Some(cookie) => Ok(SessionToken(cookie.token)),
+            None => Err(StatusCode::UNAUTHORIZED)
+        };
+    }
+}
+
+/// The valid session behind the [`SessionToken`] and its user. During an impersonation, see
+/// [`crate::impersonation`], that's the user being impersonated.
+///
+/// Rejects with `401 Unauthorized` when there is no token or it isn't valid, which includes a
+/// session bound to a network the request doesn't come from.
+#[derive(Debug, Clone)]
+pub struct AuthSession {
+    pub session: Session,
+    pub user: User
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthSession
+where
+    DynStore: FromRef<S>,
+    Arc<Config>: FromRef<S>,
+    Key: FromRef<S>,
+    S: Send + Sync
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(auth) = parts.extensions.get::<AuthSession>() {
+            return Ok(auth.clone());
+        }
+
+        let SessionToken(token) = SessionToken::from_request_parts(parts, state).await?;
+
+        let db = DynStore::from_ref(state);
+
+        let (session, user) = match Session::get_token_session(&token, &*db).await {
+            GetTokenSessionResult::Success(session, user) => (session, user),
+            GetTokenSessionResult::NotFound | GetTokenSessionResult::Unauthorized => return Err(StatusCode::UNAUTHORIZED),
+            GetTokenSessionResult::DatabaseError => return Err(StatusCode::INTERNAL_SERVER_ERROR)
+        };
+
+        if session.bound_range.is_some() {
+            let Ok(client) = ClientInfo::from_request_parts(parts, state).await;
+            if !allows_client(&session, &client) {
+                return Err(StatusCode::UNAUTHORIZED);
+            }
+        }
+
+        return Ok(AuthSession { session, user });
+    }
+}
+
+/// Whether the session may be used by the client, which is always the case unless it was bound to
+/// a network with `bind_ip`. Every way of checking a token has to ask this.
+pub fn allows_client(session: &Session, client: &ClientInfo) -> bool {
+    let bound_range = match &session.bound_range {
+        Some(bound_range) => bound_range,
+        None => return true
+    };
+
+    // A range that doesn't parse can't be checked, so it lets nobody in.
+    let allowed = match (bound_range.parse::<IpRange>(), client.ip) {
+        (Ok(range), Some(ip)) => range.contains(ip),
+        _ => false
+    };
+
+    if !allowed {
+        let ip = client.ip.map(|ip| ip.to_string());
+        tracing::debug!(target: AUTH, event = "token_rejected", session_id = %session.id, user_id = %session.user_id, bound_range = %bound_range, ip = ?ip, reason = "ip_mismatch");
+    }
+
+    return allowed;
+}
+
+/// The user behind the [`SessionToken`].
+///
+/// Rejects with `401 Unauthorized` when there is no token or it isn't valid.
+#[derive(Debug, Clone)]
+pub struct AuthUser(pub User);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthUser
+where
+    DynStore: FromRef<S>,
+    Arc<Config>: FromRef<S>,
+    Key: FromRef<S>,
+    S: Send + Sync
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthSession { user, .. } = AuthSession::from_request_parts(parts, state).await?;
+
+        return Ok(AuthUser(user));
+    }
+}
+
+/// Like [`AuthUser`], but refuses impersonation sessions with `403 Forbidden`. For what only the
+/// owner of the account may do, such as changing its credentials or taking its data.
+#[derive(Debug, Clone)]
+pub struct AccountOwner(pub User);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AccountOwner
+where
+    DynStore: FromRef<S>,
+    Arc<Config>: FromRef<S>,
+    Key: FromRef<S>,
+    S: Send + Sync
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthSession { session, user } = AuthSession::from_request_parts(parts, state).await?;
+
+        if session.impersonator_id.is_some() {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        return Ok(AccountOwner(user));
+    }
+}
+
+/// An admin, and the organization their session acts in, if any. That organization is all they
+/// see under `/admin`, without one they only see what belongs to no organization at all.
+///
+/// Rejects with `403 Forbidden` when the user isn't an admin, and like [`CurrentOrg`] when the
+/// session names an organization the admin isn't a member of.
+#[derive(Debug, Clone)]
+pub struct AdminUser {
+    pub session: Session,
+    pub user: User,
+    pub org: Option<Organization>
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminUser
+where
+    DynStore: FromRef<S>,
+    Arc<Config>: FromRef<S>,
+    Key: FromRef<S>,
+    S: Send + Sync
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthSession { session, user } = AuthSession::from_request_parts(parts, state).await?;
+
+        if user.is_admin == 0 {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        let org = match session.org_id {
+            Some(_) => Some(active_org(&session, &user, &DynStore::from_ref(state)).await?.0),
+            None => None
+        };
+
+        return Ok(AdminUser { session, user, org });
+    }
+}
+
+/// The organization the session acts in, see [`crate::org`], and the caller's role in it.
+///
+/// Membership is checked on every request, so a removed member loses access right away. Rejects
+/// with `401 Unauthorized` like [`AuthUser`], and with `403 Forbidden` when the session has no
+/// organization or the caller isn't a member of it.
+#[derive(Debug, Clone)]
+pub struct CurrentOrg {
+    pub user: User,
+    pub org: Organization,
+    pub role: OrgRole
+}
+
+impl CurrentOrg {
+    /// Rejects with `403 Forbidden` unless the caller has at least `role`.
+    pub fn require(&self, role: OrgRole) -> Result<(), StatusCode> {
+        if self.role < role {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        return Ok(());
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CurrentOrg
+where
+    DynStore: FromRef<S>,
+    Arc<Config>: FromRef<S>,
+    Key: FromRef<S>,
+    S: Send + Sync
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthSession { session, user } = AuthSession::from_request_parts(parts, state).await?;
+
+        let (org, role) = active_org(&session, &user, &DynStore::from_ref(state)).await?;
+
+        return Ok(CurrentOrg { user, org, role });
+    }
+}
+
+/// The organization the session acts in and the user's role in it, `403 Forbidden` when there is
+/// none or the user isn't a member (any more).
+async fn active_org(session: &Session, user: &User, db: &DynStore) -> Result<(Organization, OrgRole), StatusCode> {
+    let org_id = match &session.org_id {
+        Some(org_id) => org_id,
+        None => return Err(StatusCode::FORBIDDEN)
+    };
+
+    let role = match db.get_membership(org_id, &user.id).await {
+        Ok(Some(membership)) => membership.role,
+        Ok(None) => return Err(StatusCode::FORBIDDEN),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+
+    return match db.get_org(org_id).await {
+        Ok(Some(org)) => Ok((org, role)),
+        Ok(None) => Err(StatusCode::FORBIDDEN),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+}
+
+/// Looks the caller's session up before the handler runs, so everything extracting it, including
+/// [`crate::audit::AuditContext`], sees the same one. Requests without a valid session go through
+/// untouched, it's up to the handler to reject them.
+pub async fn load_session(auth: Option<AuthSession>, mut request: Request, next: Next) -> Response {
+    if let Some(auth) = auth {
+        request.extensions_mut().insert(auth);
+    }
+
+    return next.run(request).await;
+}
+
+/// The token of an `Authorization: Bearer <token>` header, `None` for any other scheme.
+pub fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+
+    let (scheme, token) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") || token.trim().is_empty() {
+        return None;
+    }
+
+    return Some(token.trim().to_string());
+}
@@ -0,0 +1,124 @@
+//! Storage for uploaded files, such as avatars.
+//!
+//! Blobs are addressed by keys like `avatars/<user id>/<uuid>.png`. The filesystem store is the
+//! default; anything else (object storage, a CDN) can be plugged in by implementing [`BlobStore`].
+
+use std::{collections::HashMap, io, path::{Component, Path, PathBuf}, sync::{Arc, RwLock}};
+use async_trait::async_trait;
+use axum::body::Bytes;
+
+/// A place to put blobs.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Stores `data` under `key`, replacing whatever was there.
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()>;
+
+    /// The blob stored under `key`, `None` if there is none.
+    async fn get(&self, key: &str) -> io::Result<Option<Bytes>>;
+
+    /// Removes the blob under `key`. Removing a blob that doesn't exist isn't an error.
+    async fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+/// The blob store shared between handlers.
+pub type DynBlobStore = Arc<dyn BlobStore>;
+
+/// Keeps blobs as files under a directory, which is created when the first blob is stored.
+#[derive(Debug, Clone)]
+pub struct FsBlobStore {
+    root: PathBuf
+}
+
+impl FsBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        return Self { root: root.into() };
+    }
+
+    /// The file for `key`, refusing keys that would point outside of the root.
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        let relative = Path::new(key);
+        let is_plain = !key.is_empty() && relative.components().all(|component| matches!(component, Component::Normal(_)));
+
+        if !is_plain {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid blob key `{}`", key)));
+        }
+
+        return Ok(self.root.join(relative));
+    }
+}
+
+#[async_trait]
+impl BlobStore for FsBlobStore {
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Written next to the final path and renamed, so readers never see half a file.
+        let mut partial = path.clone().into_os_string();
+        partial.push(format!(".{}.partial", uuid::Uuid::new_v4()));
+
+        if let Err(err) = tokio::fs::write(&partial, &data).await {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(err);
+        }
+
+        return tokio::fs::rename(&partial, &path).await;
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Option<Bytes>> {
+        return match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Some(Bytes::from(data))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err)
+        };
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        return match tokio::fs::remove_file(self.path(key)?).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(())
+        };
+    }
+}
+
+/// Keeps blobs in process memory, for tests.
+#[derive(Debug, Default)]
+pub struct MemoryBlobStore {
+    blobs: RwLock<HashMap<String, Bytes>>
+}
+
+impl MemoryBlobStore {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// How many blobs are stored.
+    pub fn len(&self) -> usize {
+        return self.blobs.read().unwrap().len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+}
+
+#[async_trait]
+impl BlobStore for MemoryBlobStore {
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()> {
+        self.blobs.write().unwrap().insert(key.to_string(), data);
+
+        return Ok(());
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Option<Bytes>> {
+        return Ok(self.blobs.read().unwrap().get(key).cloned());
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        self.blobs.write().unwrap().remove(key);
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,288 @@
+use std::{error::Error, io::{self, BufRead, Write}};
+use chrono::Utc;
+use clap::{Parser, Subcommand};
+
+use crate::{
+    audit::{AuditContext, AuditKind},
+    config::{Config, ConfigArgs},
+    server,
+    store::{self, DynStore, Store},
+    telemetry::AUTH,
+    user::{self, AddUserResult, UpdateUserResult, User}
+};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "User accounts and sessions over HTTP")]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    /// Defaults to `serve`.
+    #[command(subcommand)]
+    pub command: Option<Command>
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the HTTP server.
+    Serve,
+    /// Manage user accounts.
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Manage login sessions.
+    #[command(subcommand)]
+    Session(SessionCommand),
+    /// Apply pending database migrations and exit.
+    Migrate
+}
+
+#[derive(Subcommand, Debug)]
+pub enum UserCommand {
+    /// Create a user. The password is read from stdin unless `--password` is given.
+    Create {
+        username: String,
+        #[arg(long)]
+        password: Option<String>,
+        /// Give the user admin rights.
+        #[arg(long)]
+        admin: bool
+    },
+    /// Set a new password and revoke the user's sessions.
+    Passwd {
+        username: String,
+        #[arg(long)]
+        password: Option<String>
+    },
+    /// Stop the user from logging in and revoke their sessions.
+    Disable {
+        username: String
+    },
+    /// Undo the owner's deletion of the account while it's still in its grace period.
+    Restore {
+        username: String
+    },
+    /// Let an admin impersonate other users, or stop them with `--revoke`.
+    Impersonation {
+        username: String,
+        #[arg(long)]
+        revoke: bool
+    },
+    /// List all users.
+    List
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SessionCommand {
+    /// Revoke one session by its token, or every session of a user.
+    Revoke {
+        #[arg(required_unless_present = "user", conflicts_with = "user")]
+        token: Option<String>,
+        #[arg(long)]
+        user: Option<String>
+    },
+    /// Delete sessions whose validity has run out.
+    PurgeExpired
+}
+
+/// Runs the command, defaulting to `serve`.
+pub async fn run(command: Option<Command>, config: Config) -> Result<(), Box<dyn Error>> {
+    let command = match command {
+        Some(Command::Serve) | None => return server::serve(config).await,
+        Some(command) => command
+    };
+
+    // Every other command only needs the store, and connecting applies the migrations.
+    let db = store::connect(&config.database_url).await?;
+
+    return match command {
+        Command::Serve => unreachable!(),
+        Command::Migrate => {
+            println!("Database is up to date");
+            Ok(())
+        },
+        Command::User(command) => run_user(command, &config, db).await,
+        Command::Session(command) => run_session(command, db).await
+    };
+}
+
+async fn run_user(command: UserCommand, config: &Config, db: DynStore) -> Result<(), Box<dyn Error>> {
+    match command {
+        UserCommand::Create { username, password, admin } => {
+            let password = password_or_prompt(password)?;
+
+            let mut user = User::with_cost(&username, &password, config.bcrypt_cost)?;
+            user.is_admin = admin as i64;
+
+            match user.add_to_database(&*db).await {
+                AddUserResult::Success => {
+                    AuditContext::cli().event(AuditKind::UserCreated)
+                        .target(&user.id)
+                        .detail("username", user.username.as_str())
+                        .detail("admin", admin)
+                        .detail("via", "cli")
+                        .record(&*db)
+                        .await;
+                    println!("Created user {} ({})", user.username, user.id);
+                },
+                AddUserResult::UsernameTaken => return Err(format!("username {} is already taken", username).into()),
+                AddUserResult::DatabaseError => return Err("failed to create the user".into())
+            };
+        },
+        UserCommand::Passwd { username, password } => {
+            let password = password_or_prompt(password)?;
+
+            match user::set_password(&username, &password, config.bcrypt_cost, &*db).await {
+                UpdateUserResult::Success => {
+                    audit_cli_action(&*db, AuditKind::PasswordChanged, &username).await;
+                    println!("Password changed for {}", username);
+                },
+                UpdateUserResult::NotFound => return Err(format!("no user named {}", username).into()),
+                UpdateUserResult::DatabaseError => return Err("failed to change the password".into())
+            };
+        },
+        UserCommand::Disable { username } => {
+            match user::disable_user(&username, &*db).await {
+                UpdateUserResult::Success => {
+                    audit_cli_action(&*db, AuditKind::AccountDisabled, &username).await;
+                    println!("Disabled {}", username);
+                },
+                UpdateUserResult::NotFound => return Err(format!("no user named {}", username).into()),
+                UpdateUserResult::DatabaseError => return Err("failed to disable the user".into())
+            };
+        },
+        UserCommand::Restore { username } => {
+            let user = match db.get_user_by_username(&username).await? {
+                Some(user) if user.deleted_at.is_some() => user,
+                Some(_) => return Err(format!("{} isn't deleted", username).into()),
+                None => return Err(format!("no user named {}, accounts can't be restored once the grace period is over", username).into())
+            };
+
+            db.mark_user_deleted(&user.id, None).await?;
+            tracing::info!(target: AUTH, event = "account_restored", user_id = %user.id, username = %username);
+            audit_cli_action(&*db, AuditKind::AccountRestored, &username).await;
+            println!("Restored {}, their old sessions stay revoked", username);
+        },
+        UserCommand::Impersonation { username, revoke } => {
+            let user = match db.get_user_by_username(&username).await? {
+                Some(user) if user.is_admin != 0 => user,
+                Some(_) => return Err(format!("{} isn't an admin", username).into()),
+                None => return Err(format!("no user named {}", username).into())
+            };
+
+            db.set_user_can_impersonate(&user.id, !revoke).await?;
+            tracing::info!(target: AUTH, event = "permission_changed", user_id = %user.id, username = %username, permission = "impersonate", granted = !revoke);
+            AuditContext::cli().event(AuditKind::PermissionChanged)
+                .target(&user.id)
+                .detail("permission", "impersonate")
+                .detail("granted", !revoke)
+                .detail("via", "cli")
+                .record(&*db)
+                .await;
+
+            if revoke {
+                println!("{} can no longer impersonate, their impersonation sessions stop working", username);
+            } else {
+                println!("{} can now impersonate other users", username);
+            }
+        },
+        UserCommand::List => {
+            for user in db.list_users().await? {
+                let mut flags = Vec::new();
+                if user.is_admin != 0 {
+                    flags.push("admin");
+                }
+                if user.can_impersonate != 0 {
+                    flags.push("impersonate");
+                }
+                if user.disabled != 0 {
+                    flags.push("disabled");
+                }
+                if user.deleted_at.is_some() {
+                    flags.push("deleted");
+                }
+
+                println!("{}\t{}\t{}", user.id, user.username, flags.join(","));
+            }
+        }
+    };
+
+    return Ok(());
+}
+
+async fn run_session(command: SessionCommand, db: DynStore) -> Result<(), Box<dyn Error>> {
+    match command {
+        SessionCommand::Revoke { token: Some(token), .. } => {
+            let session = match db.get_session_by_token(&token).await? {
+                Some(session) => session,
+                None => return Err("no session with that token".into())
+            };
+
+            db.disable_session(&token).await?;
+            tracing::info!(target: AUTH, event = "session_revoked", session_id = %session.id, user_id = %session.user_id, reason = "cli");
+            AuditContext::cli().event(AuditKind::SessionsRevoked)
+                .target(&session.user_id)
+                .detail("session_id", session.id.as_str())
+                .detail("count", 1)
+                .detail("via", "cli")
+                .record(&*db)
+                .await;
+            println!("Revoked session");
+        },
+        SessionCommand::Revoke { token: None, user } => {
+            let username = user.unwrap_or_default();
+            let user = match db.get_user_by_username(&username).await? {
+                Some(user) => user,
+                None => return Err(format!("no user named {}", username).into())
+            };
+
+            let count = db.disable_user_sessions(&user.id).await?;
+            tracing::info!(target: AUTH, event = "sessions_revoked", user_id = %user.id, count, reason = "cli");
+            AuditContext::cli().event(AuditKind::SessionsRevoked)
+                .target(&user.id)
+                .detail("count", count)
+                .detail("via", "cli")
+                .record(&*db)
+                .await;
+            println!("Revoked {} sessions of {}", count, username);
+        },
+        SessionCommand::PurgeExpired => {
+            let count = db.delete_expired_sessions(Utc::now()).await?;
+            println!("Deleted {} expired sessions", count);
+        }
+    };
+
+    return Ok(());
+}
+
+/// Records an action an admin took on the named user through the CLI.
+async fn audit_cli_action(db: &dyn Store, kind: AuditKind, username: &str) {
+    let mut event = AuditContext::cli().event(kind)
+        .detail("username", username)
+        .detail("via", "cli");
+
+    if let Ok(Some(user)) = db.get_user_by_username(username).await {
+        event = event.target(&user.id);
+    }
+
+    event.record(db).await;
+}
+
+/// Uses the password given on the command line, or reads one line from stdin.
+fn password_or_prompt(password: Option<String>) -> Result<String, Box<dyn Error>> {
+    if let Some(password) = password {
+        return Ok(password);
+    }
+
+    eprint!("Password: ");
+    io::stderr().flush()?;
+
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+
+    let password = line.trim_end_matches(['\r', '\n']).to_string();
+    if password.is_empty() {
+        return Err("the password must not be empty".into());
+    }
+
+    return Ok(password);
+}
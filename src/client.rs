@@ -0,0 +1,198 @@
+//! Where a request comes from: the client's address, its user agent and a fingerprint of the
+//! device.
+//!
+//! The address is the connection's peer, unless that's one of the `trusted_proxies`. Then it's
+//! the last address in the configured `forwarded_header` that wasn't added by a trusted proxy, see
+//! [`ForwardedFor`]. Headers from anybody else are ignored, so clients can't claim an address.
+
+use std::{convert::Infallible, fmt, net::IpAddr, str::FromStr, sync::Arc};
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, ForwardedFor, FromRef, FromRequestParts},
+    http::{header::USER_AGENT, request::Parts, HeaderMap},
+    serve::TlsConnectInfo
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+
+use crate::config::{Config, ForwardedHeader};
+
+/// Longest user agent that's stored, anything after it is cut off.
+pub const MAX_USER_AGENT_LEN: usize = 512;
+
+/// Sessions bound to their address work from the same /24 for IPv4, which survives a home
+/// router's new lease, and from the same /64 for IPv6, which is usually one network.
+const BOUND_PREFIX_V4: u8 = 24;
+const BOUND_PREFIX_V6: u8 = 64;
+
+/// Headers that describe the browser and stay the same between its requests. The address isn't
+/// part of the fingerprint, a laptop stays the same device on another network.
+const FINGERPRINT_HEADERS: [&str; 5] = ["user-agent", "accept-language", "sec-ch-ua", "sec-ch-ua-platform", "sec-ch-ua-mobile"];
+
+/// Keeps these hashes apart from anything else hashed the same way.
+const FINGERPRINT_CONTEXT: &[u8] = b"device-fingerprint\0";
+
+/// A network, e.g. `10.0.0.0/8`, or a single address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix: u8
+}
+
+impl IpRange {
+    /// The range of `address` with the first `prefix` bits, `None` if the prefix is too long for
+    /// the address family.
+    pub fn new(address: IpAddr, prefix: u8) -> Option<Self> {
+        let address = address.to_canonical();
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        if prefix > max {
+            return None;
+        }
+
+        return Some(Self { network: mask(address, prefix), prefix });
+    }
+
+    /// The range a session started from `address` is bound to.
+    pub fn around(address: IpAddr) -> Self {
+        let address = address.to_canonical();
+        let prefix = if address.is_ipv4() { BOUND_PREFIX_V4 } else { BOUND_PREFIX_V6 };
+
+        return Self { network: mask(address, prefix), prefix };
+    }
+
+    pub fn contains(&self, address: IpAddr) -> bool {
+        let address = address.to_canonical();
+
+        return address.is_ipv4() == self.network.is_ipv4() && mask(address, self.prefix) == self.network;
+    }
+}
+
+/// Keeps the first `prefix` bits of the address.
+fn mask(address: IpAddr, prefix: u8) -> IpAddr {
+    return match address {
+        IpAddr::V4(address) => {
+            let bits = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix as u32) };
+            IpAddr::V4((u32::from(address) & bits).into())
+        },
+        IpAddr::V6(address) => {
+            let bits = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix as u32) };
+            IpAddr::V6((u128::from(address) & bits).into())
+        }
+    };
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    /// `<address>/<prefix>`, or an address on its own for just that address.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match value.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value.trim(), None)
+        };
+
+        let address = match address.parse::<IpAddr>() {
+            Ok(address) => address.to_canonical(),
+            Err(_) => return Err(format!("`{}` is not an IP address", address))
+        };
+
+        let prefix = match prefix {
+            Some(prefix) => match prefix.parse::<u8>() {
+                Ok(prefix) => prefix,
+                Err(_) => return Err(format!("`{}` is not a prefix length", prefix))
+            },
+            None => if address.is_ipv4() { 32 } else { 128 }
+        };
+
+        return match Self::new(address, prefix) {
+            Some(range) => Ok(range),
+            None => Err(format!("/{} is too long for {}", prefix, address))
+        };
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}/{}", self.network, self.prefix);
+    }
+}
+
+/// The client behind the request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    /// `None` when the server doesn't know the peer, e.g. in tests that call the router directly.
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+    /// See [`device_id`].
+    pub device_id: String
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    Arc<Config>: FromRef<S>,
+    S: Send + Sync
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // Only there when served through `server::serve`, or with `MockConnectInfo` in tests.
+        let peer = ConnectInfo::<TlsConnectInfo>::from_request_parts(parts, state).await
+            .ok()
+            .map(|ConnectInfo(info)| info.remote_addr().ip());
+        let forwarded_for = ForwardedFor::from_request_parts(parts, state).await?;
+        let config = Arc::<Config>::from_ref(state);
+
+        let forwarded = match config.forwarded_header {
+            ForwardedHeader::XForwardedFor => forwarded_for.x_forwarded_for,
+            ForwardedHeader::Forwarded => forwarded_for.forwarded
+        };
+
+        let user_agent = parts.headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_LEN).collect());
+
+        return Ok(Self {
+            ip: client_ip(peer, &forwarded, &config.trusted_proxies),
+            user_agent,
+            device_id: device_id(&parts.headers)
+        });
+    }
+}
+
+/// Follows the forwarded addresses back from the peer for as long as they're trusted proxies.
+///
+/// The walk stops at a hop that isn't an address, e.g. `unknown`: nothing before it can be tied to
+/// the proxy that recorded it, so that proxy's address is the client's.
+pub fn client_ip(peer: Option<IpAddr>, forwarded: &[Option<IpAddr>], trusted_proxies: &[IpRange]) -> Option<IpAddr> {
+    let mut ip = peer?.to_canonical();
+
+    for hop in forwarded.iter().rev() {
+        if !trusted_proxies.iter().any(|proxy| proxy.contains(ip)) {
+            break;
+        }
+        ip = match hop {
+            Some(hop) => hop.to_canonical(),
+            None => break
+        };
+    }
+
+    return Some(ip);
+}
+
+/// Tells devices apart by the headers a browser sends with every request, so the same browser
+/// gets the same id on every login. It's a hash, the headers can't be read back from it.
+pub fn device_id(headers: &HeaderMap) -> String {
+    let mut hash = Sha256::new();
+    hash.update(FINGERPRINT_CONTEXT);
+
+    for name in FINGERPRINT_HEADERS {
+        let value = headers.get(name).map_or(&[][..], |value| value.as_bytes());
+        // Length-prefixed, so moving bytes between headers changes the hash.
+        hash.update((value.len() as u64).to_be_bytes());
+        hash.update(value);
+    }
+
+    return URL_SAFE_NO_PAD.encode(&hash.finalize()[..16]);
+}
@@ -0,0 +1,591 @@
+use std::{fmt, fs, io, net::SocketAddr, ops::RangeInclusive, path::{Path, PathBuf}};
+use clap::Args;
+use serde::Deserialize;
+
+use crate::client::IpRange;
+
+/// Environment variables are named `APP_<KEY>`, e.g. `APP_DATABASE_URL`.
+const ENV_PREFIX: &str = "APP_";
+
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Settings the server needs at startup, after every source has been merged and validated.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub database_url: String,
+    pub bind_addr: SocketAddr,
+    pub token_length: usize,
+    pub bcrypt_cost: u32,
+    pub purge_interval_secs: u64,
+    pub purge_batch_size: u32,
+    pub shutdown_timeout_secs: u64,
+    pub tls_cert_file: Option<PathBuf>,
+    pub tls_key_file: Option<PathBuf>,
+    pub tls_client_ca_file: Option<PathBuf>,
+    pub max_connections: usize,
+    pub header_read_timeout_secs: u64,
+    pub idle_timeout_secs: u64,
+    pub max_header_size: usize,
+    pub max_requests_per_connection: usize,
+    pub log_format: LogFormat,
+    /// Secret the session cookies are encrypted with, random per process when unset.
+    pub cookie_key: Option<String>,
+    pub blob_dir: PathBuf,
+    pub avatar_max_bytes: usize,
+    pub deletion_grace_days: u32,
+    pub invitation_ttl_hours: u32,
+    pub impersonation_minutes: u32,
+    /// Reverse proxies whose forwarded header is believed, see [`crate::client`].
+    pub trusted_proxies: Vec<IpRange>,
+    /// The header the `trusted_proxies` put the client address in.
+    pub forwarded_header: ForwardedHeader
+}
+
+/// Where the trusted proxies record the address they got a request from. Only this header is read,
+/// whatever a client sends in the other is ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForwardedHeader {
+    /// `X-Forwarded-For`, which most proxies set.
+    XForwardedFor,
+    /// The standard `Forwarded` header, RFC 7239.
+    Forwarded
+}
+
+/// How log lines are written to stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// One JSON object per line, for log collectors.
+    Json
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        return Self {
+            database_url: "users.db".to_string(),
+            bind_addr: SocketAddr::from(([127, 0, 0, 1], 3000)),
+            token_length: 32,
+            bcrypt_cost: bcrypt::DEFAULT_COST,
+            purge_interval_secs: 3600,
+            purge_batch_size: 500,
+            shutdown_timeout_secs: 30,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_client_ca_file: None,
+            max_connections: 10_000,
+            header_read_timeout_secs: 10,
+            idle_timeout_secs: 60,
+            max_header_size: 65_536,
+            max_requests_per_connection: 1000,
+            log_format: LogFormat::Text,
+            cookie_key: None,
+            blob_dir: PathBuf::from("blobs"),
+            avatar_max_bytes: 1_048_576,
+            deletion_grace_days: 30,
+            invitation_ttl_hours: 72,
+            impersonation_minutes: 30,
+            trusted_proxies: Vec::new(),
+            forwarded_header: ForwardedHeader::XForwardedFor
+        };
+    }
+}
+
+/// Command line flags, which take precedence over the environment and the config file.
+#[derive(Args, Debug, Default, Clone)]
+pub struct ConfigArgs {
+    /// Path to a TOML config file. Defaults to `config.toml` if it exists.
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    #[arg(long, global = true)]
+    pub database_url: Option<String>,
+
+    /// File holding the database url, for urls that contain a password.
+    #[arg(long, global = true)]
+    pub database_url_file: Option<PathBuf>,
+
+    #[arg(long, global = true)]
+    pub bind_addr: Option<String>,
+
+    #[arg(long, global = true)]
+    pub token_length: Option<usize>,
+
+    #[arg(long, global = true)]
+    pub bcrypt_cost: Option<u32>,
+
+    /// Seconds between runs of the expired session cleanup.
+    #[arg(long, global = true)]
+    pub purge_interval_secs: Option<u64>,
+
+    /// Rows deleted per statement by the session cleanup.
+    #[arg(long, global = true)]
+    pub purge_batch_size: Option<u32>,
+
+    /// Seconds to wait for in-flight requests when shutting down.
+    #[arg(long, global = true)]
+    pub shutdown_timeout_secs: Option<u64>,
+
+    /// PEM certificate chain to serve HTTPS with. Requires `tls_key_file`.
+    #[arg(long, global = true)]
+    pub tls_cert_file: Option<PathBuf>,
+
+    /// PEM private key for `tls_cert_file`.
+    #[arg(long, global = true)]
+    pub tls_key_file: Option<PathBuf>,
+
+    /// PEM CA certificates that client certificates are checked against, for mutual TLS.
+    #[arg(long, global = true)]
+    pub tls_client_ca_file: Option<PathBuf>,
+
+    /// Connections served at once, more wait until one closes.
+    #[arg(long, global = true)]
+    pub max_connections: Option<usize>,
+
+    /// Seconds a client gets to send a request's headers.
+    #[arg(long, global = true)]
+    pub header_read_timeout_secs: Option<u64>,
+
+    /// Seconds a connection may stay open without a request in flight.
+    #[arg(long, global = true)]
+    pub idle_timeout_secs: Option<u64>,
+
+    /// Largest request line and headers accepted, in bytes.
+    #[arg(long, global = true)]
+    pub max_header_size: Option<usize>,
+
+    /// Requests served on one connection before it is closed.
+    #[arg(long, global = true)]
+    pub max_requests_per_connection: Option<usize>,
+
+    /// Log output, `text` or `json`.
+    #[arg(long, global = true)]
+    pub log_format: Option<String>,
+
+    /// File holding the key session cookies are encrypted with, at least 64 bytes.
+    #[arg(long, global = true)]
+    pub cookie_key_file: Option<PathBuf>,
+
+    /// Directory uploaded files such as avatars are stored in.
+    #[arg(long, global = true)]
+    pub blob_dir: Option<PathBuf>,
+
+    /// Largest avatar upload accepted, in bytes.
+    #[arg(long, global = true)]
+    pub avatar_max_bytes: Option<usize>,
+
+    /// Days a deleted account is kept, and can be restored, before it is removed for good.
+    #[arg(long, global = true)]
+    pub deletion_grace_days: Option<u32>,
+
+    /// Hours an invitation link stays valid.
+    #[arg(long, global = true)]
+    pub invitation_ttl_hours: Option<u32>,
+
+    /// Minutes an admin's impersonation session stays valid.
+    #[arg(long, global = true)]
+    pub impersonation_minutes: Option<u32>,
+
+    /// Addresses or networks of reverse proxies whose forwarded headers are trusted, comma separated.
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub trusted_proxies: Option<Vec<String>>,
+
+    /// Header the trusted proxies put the client address in, `x-forwarded-for` or `forwarded`.
+    #[arg(long, global = true)]
+    pub forwarded_header: Option<String>
+}
+
+/// One source of settings. Every field is optional so layers can be stacked.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigLayer {
+    pub database_url: Option<String>,
+    pub database_url_file: Option<PathBuf>,
+    pub bind_addr: Option<String>,
+    pub token_length: Option<usize>,
+    pub bcrypt_cost: Option<u32>,
+    pub purge_interval_secs: Option<u64>,
+    pub purge_batch_size: Option<u32>,
+    pub shutdown_timeout_secs: Option<u64>,
+    pub tls_cert_file: Option<PathBuf>,
+    pub tls_key_file: Option<PathBuf>,
+    pub tls_client_ca_file: Option<PathBuf>,
+    pub max_connections: Option<usize>,
+    pub header_read_timeout_secs: Option<u64>,
+    pub idle_timeout_secs: Option<u64>,
+    pub max_header_size: Option<usize>,
+    pub max_requests_per_connection: Option<usize>,
+    pub log_format: Option<String>,
+    pub cookie_key_file: Option<PathBuf>,
+    pub blob_dir: Option<PathBuf>,
+    pub avatar_max_bytes: Option<usize>,
+    pub deletion_grace_days: Option<u32>,
+    pub invitation_ttl_hours: Option<u32>,
+    pub impersonation_minutes: Option<u32>,
+    pub trusted_proxies: Option<Vec<String>>,
+    pub forwarded_header: Option<String>
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file couldn't be read.
+    Read(PathBuf, io::Error),
+    /// The config file isn't valid TOML or has unknown keys.
+    Parse(PathBuf, toml::de::Error),
+    /// A secret file couldn't be read.
+    Secret(PathBuf, io::Error),
+    /// A setting has a value that isn't allowed.
+    Invalid { key: &'static str, message: String }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ConfigError::Read(path, err) => write!(f, "failed to read config file {}: {}", path.display(), err),
+            ConfigError::Parse(path, err) => write!(f, "failed to parse config file {}: {}", path.display(), err),
+            ConfigError::Secret(path, err) => write!(f, "failed to read secret file {}: {}", path.display(), err),
+            ConfigError::Invalid { key, message } => write!(f, "invalid value for `{}`: {}", key, message)
+        };
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl ConfigLayer {
+    /// Reads a layer from a TOML file.
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => return Err(ConfigError::Read(path.to_path_buf(), err))
+        };
+
+        return match toml::from_str(&contents) {
+            Ok(layer) => Ok(layer),
+            Err(err) => Err(ConfigError::Parse(path.to_path_buf(), err))
+        };
+    }
+
+    /// Reads a layer from `APP_*` environment variables.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        return Ok(Self {
+            database_url: env_var("DATABASE_URL"),
+            database_url_file: env_var("DATABASE_URL_FILE").map(PathBuf::from),
+            bind_addr: env_var("BIND_ADDR"),
+            token_length: parse_env("TOKEN_LENGTH", "token_length")?,
+            bcrypt_cost: parse_env("BCRYPT_COST", "bcrypt_cost")?,
+            purge_interval_secs: parse_env("PURGE_INTERVAL_SECS", "purge_interval_secs")?,
+            purge_batch_size: parse_env("PURGE_BATCH_SIZE", "purge_batch_size")?,
+            shutdown_timeout_secs: parse_env("SHUTDOWN_TIMEOUT_SECS", "shutdown_timeout_secs")?,
+            tls_cert_file: env_var("TLS_CERT_FILE").map(PathBuf::from),
+            tls_key_file: env_var("TLS_KEY_FILE").map(PathBuf::from),
+            tls_client_ca_file: env_var("TLS_CLIENT_CA_FILE").map(PathBuf::from),
+            max_connections: parse_env("MAX_CONNECTIONS", "max_connections")?,
+            header_read_timeout_secs: parse_env("HEADER_READ_TIMEOUT_SECS", "header_read_timeout_secs")?,
+            idle_timeout_secs: parse_env("IDLE_TIMEOUT_SECS", "idle_timeout_secs")?,
+            max_header_size: parse_env("MAX_HEADER_SIZE", "max_header_size")?,
+            max_requests_per_connection: parse_env("MAX_REQUESTS_PER_CONNECTION", "max_requests_per_connection")?,
+            log_format: env_var("LOG_FORMAT"),
+            cookie_key_file: env_var("COOKIE_KEY_FILE").map(PathBuf::from),
+            blob_dir: env_var("BLOB_DIR").map(PathBuf::from),
+            avatar_max_bytes: parse_env("AVATAR_MAX_BYTES", "avatar_max_bytes")?,
+            deletion_grace_days: parse_env("DELETION_GRACE_DAYS", "deletion_grace_days")?,
+            invitation_ttl_hours: parse_env("INVITATION_TTL_HOURS", "invitation_ttl_hours")?,
+            impersonation_minutes: parse_env("IMPERSONATION_MINUTES", "impersonation_minutes")?,
+            trusted_proxies: env_var("TRUSTED_PROXIES").map(|value| value.split(',').map(str::to_string).collect()),
+            forwarded_header: env_var("FORWARDED_HEADER")
+        });
+    }
+
+    pub fn from_args(args: &ConfigArgs) -> Self {
+        return Self {
+            database_url: args.database_url.clone(),
+            database_url_file: args.database_url_file.clone(),
+            bind_addr: args.bind_addr.clone(),
+            token_length: args.token_length,
+            bcrypt_cost: args.bcrypt_cost,
+            purge_interval_secs: args.purge_interval_secs,
+            purge_batch_size: args.purge_batch_size,
+            shutdown_timeout_secs: args.shutdown_timeout_secs,
+            tls_cert_file: args.tls_cert_file.clone(),
+            tls_key_file: args.tls_key_file.clone(),
+            tls_client_ca_file: args.tls_client_ca_file.clone(),
+            max_connections: args.max_connections,
+            header_read_timeout_secs: args.header_read_timeout_secs,
+            idle_timeout_secs: args.idle_timeout_secs,
+            max_header_size: args.max_header_size,
+            max_requests_per_connection: args.max_requests_per_connection,
+            log_format: args.log_format.clone(),
+            cookie_key_file: args.cookie_key_file.clone(),
+            blob_dir: args.blob_dir.clone(),
+            avatar_max_bytes: args.avatar_max_bytes,
+            deletion_grace_days: args.deletion_grace_days,
+            invitation_ttl_hours: args.invitation_ttl_hours,
+            impersonation_minutes: args.impersonation_minutes,
+            trusted_proxies: args.trusted_proxies.clone(),
+            forwarded_header: args.forwarded_header.clone()
+        };
+    }
+
+    /// Stacks `other` on top of `self`, so any value set in `other` wins.
+    pub fn merge(self, other: Self) -> Self {
+        // A url set directly in a higher layer beats a url file from a lower one, and vice versa.
+        let (database_url, database_url_file) = if other.database_url.is_some() || other.database_url_file.is_some() {
+            (other.database_url, other.database_url_file)
+        } else {
+            (self.database_url, self.database_url_file)
+        };
+
+        return Self {
+            database_url,
+            database_url_file,
+            bind_addr: other.bind_addr.or(self.bind_addr),
+            token_length: other.token_length.or(self.token_length),
+            bcrypt_cost: other.bcrypt_cost.or(self.bcrypt_cost),
+            purge_interval_secs: other.purge_interval_secs.or(self.purge_interval_secs),
+            purge_batch_size: other.purge_batch_size.or(self.purge_batch_size),
+            shutdown_timeout_secs: other.shutdown_timeout_secs.or(self.shutdown_timeout_secs),
+            tls_cert_file: other.tls_cert_file.or(self.tls_cert_file),
+            tls_key_file: other.tls_key_file.or(self.tls_key_file),
+            tls_client_ca_file: other.tls_client_ca_file.or(self.tls_client_ca_file),
+            max_connections: other.max_connections.or(self.max_connections),
+            header_read_timeout_secs: other.header_read_timeout_secs.or(self.header_read_timeout_secs),
+            idle_timeout_secs: other.idle_timeout_secs.or(self.idle_timeout_secs),
+            max_header_size: other.max_header_size.or(self.max_header_size),
+            max_requests_per_connection: other.max_requests_per_connection.or(self.max_requests_per_connection),
+            log_format: other.log_format.or(self.log_format),
+            cookie_key_file: other.cookie_key_file.or(self.cookie_key_file),
+            blob_dir: other.blob_dir.or(self.blob_dir),
+            avatar_max_bytes: other.avatar_max_bytes.or(self.avatar_max_bytes),
+            deletion_grace_days: other.deletion_grace_days.or(self.deletion_grace_days),
+            invitation_ttl_hours: other.invitation_ttl_hours.or(self.invitation_ttl_hours),
+            impersonation_minutes: other.impersonation_minutes.or(self.impersonation_minutes),
+            trusted_proxies: other.trusted_proxies.or(self.trusted_proxies),
+            forwarded_header: other.forwarded_header.or(self.forwarded_header)
+        };
+    }
+
+    /// Fills in defaults for anything unset, reads secret files and validates the result.
+    pub fn resolve(self) -> Result<Config, ConfigError> {
+        let defaults = Config::default();
+
+        let database_url = match (self.database_url, self.database_url_file) {
+            (Some(_), Some(_)) => return Err(ConfigError::Invalid {
+                key: "database_url",
+                message: "only one of `database_url` and `database_url_file` can be set".to_string()
+            }),
+            (Some(url), None) => url,
+            (None, Some(path)) => read_secret(&path)?,
+            (None, None) => defaults.database_url
+        };
+
+        if database_url.trim().is_empty() {
+            return Err(ConfigError::Invalid { key: "database_url", message: "must not be empty".to_string() });
+        }
+
+        let bind_addr = match self.bind_addr {
+            Some(addr) => match addr.parse::<SocketAddr>() {
+                Ok(addr) => addr,
+                Err(err) => return Err(ConfigError::Invalid {
+                    key: "bind_addr",
+                    message: format!("`{}` is not a socket address like 127.0.0.1:3000 ({})", addr, err)
+                })
+            },
+            None => defaults.bind_addr
+        };
+
+        let token_length = self.token_length.unwrap_or(defaults.token_length);
+        check_range("token_length", token_length, 16..=256)?;
+
+        // The range bcrypt itself accepts.
+        let bcrypt_cost = self.bcrypt_cost.unwrap_or(defaults.bcrypt_cost);
+        check_range("bcrypt_cost", bcrypt_cost, 4..=31)?;
+
+        let purge_interval_secs = self.purge_interval_secs.unwrap_or(defaults.purge_interval_secs);
+        check_range("purge_interval_secs", purge_interval_secs, 1..=86400)?;
+
+        let purge_batch_size = self.purge_batch_size.unwrap_or(defaults.purge_batch_size);
+        check_range("purge_batch_size", purge_batch_size, 1..=100_000)?;
+
+        let shutdown_timeout_secs = self.shutdown_timeout_secs.unwrap_or(defaults.shutdown_timeout_secs);
+        check_range("shutdown_timeout_secs", shutdown_timeout_secs, 0..=3600)?;
+
+        let (tls_cert_file, tls_key_file) = match (self.tls_cert_file, self.tls_key_file) {
+            (Some(cert), Some(key)) => (Some(cert), Some(key)),
+            (None, None) => (None, None),
+            _ => return Err(ConfigError::Invalid {
+                key: "tls_cert_file",
+                message: "`tls_cert_file` and `tls_key_file` must be set together".to_string()
+            })
+        };
+
+        let tls_client_ca_file = self.tls_client_ca_file;
+        if tls_client_ca_file.is_some() && tls_cert_file.is_none() {
+            return Err(ConfigError::Invalid {
+                key: "tls_client_ca_file",
+                message: "client certificates need TLS, set `tls_cert_file` and `tls_key_file` too".to_string()
+            });
+        }
+
+        let max_connections = self.max_connections.unwrap_or(defaults.max_connections);
+        check_range("max_connections", max_connections, 1..=1_000_000)?;
+
+        let header_read_timeout_secs = self.header_read_timeout_secs.unwrap_or(defaults.header_read_timeout_secs);
+        check_range("header_read_timeout_secs", header_read_timeout_secs, 1..=300)?;
+
+        let idle_timeout_secs = self.idle_timeout_secs.unwrap_or(defaults.idle_timeout_secs);
+        check_range("idle_timeout_secs", idle_timeout_secs, 1..=3600)?;
+
+        let max_header_size = self.max_header_size.unwrap_or(defaults.max_header_size);
+        // hyper needs at least 8 KiB
+        check_range("max_header_size", max_header_size, 8192..=1_048_576)?;
+
+        let max_requests_per_connection = self.max_requests_per_connection.unwrap_or(defaults.max_requests_per_connection);
+        check_range("max_requests_per_connection", max_requests_per_connection, 1..=1_000_000)?;
+
+        let log_format = match self.log_format.as_deref() {
+            Some("text") => LogFormat::Text,
+            Some("json") => LogFormat::Json,
+            Some(other) => return Err(ConfigError::Invalid {
+                key: "log_format",
+                message: format!("`{}` is not a log format, use `text` or `json`", other)
+            }),
+            None => defaults.log_format
+        };
+
+        // What the cookie encryption needs, it only uses the first 64 bytes.
+        let cookie_key = match self.cookie_key_file {
+            Some(path) => Some(read_secret(&path)?),
+            None => defaults.cookie_key
+        };
+        if let Some(key) = &cookie_key {
+            if key.len() < 64 {
+                return Err(ConfigError::Invalid {
+                    key: "cookie_key_file",
+                    message: format!("the key is {} bytes long but needs at least 64, e.g. from `openssl rand -hex 64`", key.len())
+                });
+            }
+        }
+
+        let blob_dir = self.blob_dir.unwrap_or(defaults.blob_dir);
+
+        let avatar_max_bytes = self.avatar_max_bytes.unwrap_or(defaults.avatar_max_bytes);
+        check_range("avatar_max_bytes", avatar_max_bytes, 1024..=16_777_216)?;
+
+        let deletion_grace_days = self.deletion_grace_days.unwrap_or(defaults.deletion_grace_days);
+        check_range("deletion_grace_days", deletion_grace_days, 0..=365)?;
+
+        let invitation_ttl_hours = self.invitation_ttl_hours.unwrap_or(defaults.invitation_ttl_hours);
+        check_range("invitation_ttl_hours", invitation_ttl_hours, 1..=720)?;
+
+        let impersonation_minutes = self.impersonation_minutes.unwrap_or(defaults.impersonation_minutes);
+        check_range("impersonation_minutes", impersonation_minutes, 1..=480)?;
+
+        let mut trusted_proxies = Vec::new();
+        for proxy in self.trusted_proxies.unwrap_or_default() {
+            match proxy.parse::<IpRange>() {
+                Ok(range) => trusted_proxies.push(range),
+                Err(message) => return Err(ConfigError::Invalid { key: "trusted_proxies", message })
+            }
+        }
+
+        let forwarded_header = match self.forwarded_header.as_deref() {
+            Some("x-forwarded-for") => ForwardedHeader::XForwardedFor,
+            Some("forwarded") => ForwardedHeader::Forwarded,
+            Some(other) => return Err(ConfigError::Invalid {
+                key: "forwarded_header",
+                message: format!("`{}` is not a forwarded header, use `x-forwarded-for` or `forwarded`", other)
+            }),
+            None => defaults.forwarded_header
+        };
+
+        return Ok(Config {
+            database_url,
+            bind_addr,
+            token_length,
+            bcrypt_cost,
+            purge_interval_secs,
+            purge_batch_size,
+            shutdown_timeout_secs,
+            tls_cert_file,
+            tls_key_file,
+            tls_client_ca_file,
+            max_connections,
+            header_read_timeout_secs,
+            idle_timeout_secs,
+            max_header_size,
+            max_requests_per_connection,
+            log_format,
+            cookie_key,
+            blob_dir,
+            avatar_max_bytes,
+            deletion_grace_days,
+            invitation_ttl_hours,
+            impersonation_minutes,
+            trusted_proxies,
+            forwarded_header
+        });
+    }
+}
+
+impl Config {
+    /// Loads the config file, then the environment, then the command line flags, later sources
+    /// overriding earlier ones.
+    pub fn load(args: &ConfigArgs) -> Result<Self, ConfigError> {
+        let file_path = match args.config.clone().or_else(|| env_var("CONFIG").map(PathBuf::from)) {
+            Some(path) => Some(path),
+            None => Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|path| path.exists())
+        };
+
+        let file = match file_path {
+            Some(path) => ConfigLayer::from_file(&path)?,
+            None => ConfigLayer::default()
+        };
+
+        return file
+            .merge(ConfigLayer::from_env()?)
+            .merge(ConfigLayer::from_args(args))
+            .resolve();
+    }
+}
+
+fn env_var(key: &str) -> Option<String> {
+    return std::env::var(format!("{}{}", ENV_PREFIX, key)).ok();
+}
+
+fn parse_env<T: std::str::FromStr>(key: &str, name: &'static str) -> Result<Option<T>, ConfigError>
+where
+    T::Err: fmt::Display
+{
+    return match env_var(key) {
+        Some(value) => match value.parse::<T>() {
+            Ok(value) => Ok(Some(value)),
+            Err(err) => Err(ConfigError::Invalid {
+                key: name,
+                message: format!("{}{}=`{}`: {}", ENV_PREFIX, key, value, err)
+            })
+        },
+        None => Ok(None)
+    };
+}
+
+fn check_range<T>(key: &'static str, value: T, range: RangeInclusive<T>) -> Result<(), ConfigError>
+where
+    T: PartialOrd + fmt::Display
+{
+    if range.contains(&value) {
+        return Ok(());
+    }
+
+    return Err(ConfigError::Invalid {
+        key,
+        message: format!("{} is outside the allowed range {}..={}", value, range.start(), range.end())
+    });
+}
+
+/// Reads a secret from a file, dropping the trailing newline most editors add.
+pub fn read_secret(path: &Path) -> Result<String, ConfigError> {
+    return match fs::read_to_string(path) {
+        Ok(secret) => Ok(secret.trim_end_matches(['\r', '\n']).to_string()),
+        Err(err) => Err(ConfigError::Secret(path.to_path_buf(), err))
+    };
+}
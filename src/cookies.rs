@@ -0,0 +1,127 @@
+//! Cookie sessions for browser clients.
+//!
+//! `POST /login?cookie=true` puts the session token in an encrypted, HttpOnly `session` cookie
+//! instead of the response body, and hands out a CSRF token. The token is stored twice: readable
+//! by scripts in the `csrf_token` cookie, and inside the encrypted session cookie. Requests that
+//! change state have to echo it in the `X-CSRF-Token` header, and it's compared against the copy
+//! in the session cookie, which other sites can neither read nor forge.
+
+use axum::{
+    extract::Request,
+    http::{HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response}
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, PrivateCookieJar, SameSite};
+
+use crate::{auth, telemetry::AUTH, user::{Session, SESSION_LIFETIME_DAYS}};
+
+pub const SESSION_COOKIE: &str = "session";
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+const CSRF_TOKEN_LENGTH: usize = 32;
+
+/// What the encrypted `session` cookie holds.
+#[derive(Debug, Clone)]
+pub struct SessionCookie {
+    pub token: String,
+    pub csrf_token: String
+}
+
+impl SessionCookie {
+    /// A cookie for a new session, with a fresh CSRF token.
+    pub fn new(token: String) -> Self {
+        return Self {
+            token,
+            csrf_token: Session::generate_session_token(CSRF_TOKEN_LENGTH)
+        };
+    }
+
+    /// Reads the session cookie, `None` if there is none or it wasn't encrypted with our key.
+    pub fn from_jar(jar: &PrivateCookieJar) -> Option<Self> {
+        let cookie = jar.get(SESSION_COOKIE)?;
+
+        // Both halves are alphanumeric, so the first dot is the separator.
+        let (csrf_token, token) = cookie.value().split_once('.')?;
+        if csrf_token.is_empty() || token.is_empty() {
+            return None;
+        }
+
+        return Some(Self {
+            token: token.to_string(),
+            csrf_token: csrf_token.to_string()
+        });
+    }
+
+    /// Adds the session cookie to `private` and the readable CSRF cookie to `plain`.
+    pub fn set(self, private: PrivateCookieJar, plain: CookieJar) -> (PrivateCookieJar, CookieJar) {
+        let session = Cookie::build(SESSION_COOKIE, format!("{}.{}", self.csrf_token, self.token))
+            .http_only(true)
+            .secure(true)
+            .same_site(SameSite::Lax)
+            .path("/")
+            .max_age(time::Duration::days(SESSION_LIFETIME_DAYS))
+            .finish();
+
+        let csrf = Cookie::build(CSRF_COOKIE, self.csrf_token)
+            .secure(true)
+            .same_site(SameSite::Lax)
+            .path("/")
+            .max_age(time::Duration::days(SESSION_LIFETIME_DAYS))
+            .finish();
+
+        return (private.add(session), plain.add(csrf));
+    }
+}
+
+/// Removes both cookies again. The jars only remove cookies the request came with, and the
+/// private one only knows the cookies it can decrypt, so the plain jar has to be the request's.
+pub fn clear(private: PrivateCookieJar, plain: CookieJar) -> (PrivateCookieJar, CookieJar) {
+    return (
+        private.remove(Cookie::build(SESSION_COOKIE, "").path("/").finish()),
+        plain.remove(Cookie::build(CSRF_COOKIE, "").path("/").finish())
+    );
+}
+
+/// Middleware rejecting state-changing requests that are authenticated by the session cookie
+/// but don't carry its CSRF token in the `X-CSRF-Token` header.
+///
+/// Requests with a bearer token are let through, since browsers never add one on their own and it
+/// takes precedence over the cookie anyway. Any other `Authorization` header doesn't count, the
+/// session still comes from the cookie then.
+pub async fn protect(jar: PrivateCookieJar, request: Request, next: Next) -> Response {
+    if is_safe(request.method()) || auth::bearer_token(request.headers()).is_some() {
+        return next.run(request).await;
+    }
+
+    let session = match SessionCookie::from_jar(&jar) {
+        Some(session) => session,
+        None => return next.run(request).await
+    };
+
+    if csrf_header(request.headers()).map_or(false, |token| constant_time_eq(token, &session.csrf_token)) {
+        return next.run(request).await;
+    }
+
+    tracing::warn!(target: AUTH, event = "csrf_rejected", method = %request.method(), path = %request.uri().path());
+
+    return (StatusCode::FORBIDDEN, "missing or invalid CSRF token").into_response();
+}
+
+fn is_safe(method: &Method) -> bool {
+    return matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE);
+}
+
+fn csrf_header(headers: &HeaderMap) -> Option<&str> {
+    return headers.get(CSRF_HEADER)?.to_str().ok();
+}
+
+/// Compares without returning early, so the time taken doesn't tell how much of a guess matched.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    return a.bytes().zip(b.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0;
+}
@@ -0,0 +1,221 @@
+//! Bodies of the JSON responses. Keep these free of credentials, they're what clients get to see.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{org::{Invitation, OrgMember, OrgRole, Organization}, user::{Session, User}};
+
+/// `POST /login` with a bearer session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub token: String
+}
+
+/// `POST /login?cookie=true`, the session itself is in the cookie.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CsrfTokenResponse {
+    pub csrf_token: String
+}
+
+/// What a user can see about their own account.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserProfile {
+    pub id: String,
+    pub username: String,
+    pub is_admin: bool,
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    /// Where the avatar is served, if there is one.
+    pub avatar_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Only on `GET /me`, when an admin is acting as the user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonation: Option<ImpersonationSummary>
+}
+
+impl From<&User> for UserProfile {
+    fn from(user: &User) -> Self {
+        return Self {
+            id: user.id.clone(),
+            username: user.username.clone(),
+            is_admin: user.is_admin != 0,
+            display_name: user.display_name.clone(),
+            email: user.email.clone(),
+            locale: user.locale.clone(),
+            timezone: user.timezone.clone(),
+            avatar_url: user.avatar_key.as_ref().map(|_| format!("/users/{}/avatar", user.id)),
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+            impersonation: None
+        };
+    }
+}
+
+/// Why a request body was rejected, for the field it's about.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String
+}
+
+/// A session as it appears in the data export, without its token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub id: String,
+    pub valid_to: DateTime<Utc>,
+    pub revoked: bool,
+    /// The admin who used the session to act as the user, if it was one.
+    pub impersonator_id: Option<String>,
+    /// Where the session was started from. Not recorded for impersonation sessions, which are
+    /// started by the admin.
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// The network the session is bound to, if it is.
+    pub bound_range: Option<String>
+}
+
+impl From<&Session> for SessionSummary {
+    fn from(session: &Session) -> Self {
+        return Self {
+            id: session.id.clone(),
+            valid_to: session.valid_to,
+            revoked: session.disabled != 0,
+            impersonator_id: session.impersonator_id.clone(),
+            ip: session.ip.clone(),
+            user_agent: session.user_agent.clone(),
+            bound_range: session.bound_range.clone()
+        };
+    }
+}
+
+/// `DELETE /me`, the password confirms it's really the owner.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeleteAccountRequest {
+    pub password: String
+}
+
+/// `DELETE /me` went through.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountDeletion {
+    pub deleted_at: DateTime<Utc>,
+    /// From then on the account and its data are gone for good.
+    pub purge_after: DateTime<Utc>
+}
+
+/// `POST /orgs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateOrgRequest {
+    pub name: String
+}
+
+/// `PUT /session/org`, `null` leaves the organization the session is in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwitchOrgRequest {
+    pub org_id: Option<String>
+}
+
+/// An organization with the caller's role in it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrgResponse {
+    pub org: Organization,
+    pub role: OrgRole
+}
+
+/// A member as the rest of the organization sees them, without their contact details.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberSummary {
+    pub user_id: String,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub role: OrgRole,
+    pub joined_at: DateTime<Utc>
+}
+
+impl From<&OrgMember> for MemberSummary {
+    fn from(member: &OrgMember) -> Self {
+        return Self {
+            user_id: member.user.id.clone(),
+            username: member.user.username.clone(),
+            display_name: member.user.display_name.clone(),
+            role: member.role,
+            joined_at: member.joined_at
+        };
+    }
+}
+
+/// `PUT /org/members/{user id}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateMemberRequest {
+    pub role: OrgRole
+}
+
+/// `POST /org/invitations`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateInvitationRequest {
+    pub role: OrgRole,
+    /// Who the link is for, only recorded, see [`Invitation::email`].
+    #[serde(default)]
+    pub email: Option<String>
+}
+
+/// An invitation as its organization's admins see it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvitationResponse {
+    pub id: String,
+    pub role: OrgRole,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// The path to share, `/invitations/{token}`.
+    pub link: String
+}
+
+impl InvitationResponse {
+    pub fn new(invitation: &Invitation, token: &str) -> Self {
+        return Self {
+            id: invitation.id.clone(),
+            role: invitation.role,
+            email: invitation.email.clone(),
+            created_at: invitation.created_at,
+            expires_at: invitation.expires_at,
+            link: format!("/invitations/{}", token)
+        };
+    }
+}
+
+/// `GET /invitations/{token}`, what the invitation is for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvitationPreview {
+    pub org: Organization,
+    pub role: OrgRole,
+    pub expires_at: DateTime<Utc>
+}
+
+/// `POST /admin/users/{id}/impersonate`, the token acts as the user until `expires_at`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImpersonationResponse {
+    pub token: String,
+    pub user_id: String,
+    pub impersonator_id: String,
+    pub expires_at: DateTime<Utc>
+}
+
+/// Who is acting as the user, and until when.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImpersonationSummary {
+    pub impersonator_id: String,
+    pub expires_at: DateTime<Utc>
+}
+
+impl ImpersonationSummary {
+    /// `None` for the user's own sessions.
+    pub fn of(session: &Session) -> Option<Self> {
+        return session.impersonator_id.as_ref().map(|impersonator_id| Self {
+            impersonator_id: impersonator_id.clone(),
+            expires_at: session.valid_to
+        });
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+
+use crate::store::DynStore;
+
+/// Crate version this binary was built from.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Commit this binary was built from, set by `build.rs`.
+pub const GIT_SHA: &str = env!("GIT_SHA");
+
+/// Set once graceful shutdown starts, so `/readyz` tells the orchestrator to stop sending traffic
+/// while in-flight requests drain.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownState(Arc<AtomicBool>);
+
+impl ShutdownState {
+    pub fn begin(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        return self.0.load(Ordering::Relaxed);
+    }
+}
+
+#[derive(Serialize)]
+pub struct Status {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'static str>
+}
+
+impl Status {
+    fn ok(status: &'static str) -> Json<Status> {
+        return Json(Status { status, reason: None });
+    }
+
+    fn not_ready(reason: &'static str) -> (StatusCode, Json<Status>) {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(Status { status: "not_ready", reason: Some(reason) }));
+    }
+}
+
+/// `GET /healthz`, answers as long as the process can serve requests at all.
+pub async fn healthz() -> Json<Status> {
+    return Status::ok("ok");
+}
+
+/// `GET /readyz`, fails while shutting down, when the database doesn't answer or when its schema is
+/// older than this build.
+pub async fn readyz(State(db): State<DynStore>, State(shutdown): State<ShutdownState>) -> Result<Json<Status>, (StatusCode, Json<Status>)> {
+    if shutdown.is_shutting_down() {
+        return Err(Status::not_ready("shutting_down"));
+    }
+
+    if let Err(err) = db.ping().await {
+        tracing::warn!(error = %err, "readiness check failed to reach the database");
+        return Err(Status::not_ready("database_unavailable"));
+    }
+
+    let applied = match db.schema_version().await {
+        Ok(version) => version,
+        Err(err) => {
+            tracing::warn!(error = %err, "readiness check failed to read the schema version");
+            return Err(Status::not_ready("database_unavailable"));
+        }
+    };
+
+    // A newer schema is fine, migrations are expected to stay compatible with the previous release
+    // while it's being rolled out.
+    if applied < db.expected_schema_version() {
+        return Err(Status::not_ready("migrations_pending"));
+    }
+
+    return Ok(Status::ok("ready"));
+}
+
+#[derive(Serialize)]
+pub struct BuildInfo {
+    version: &'static str,
+    git_sha: &'static str,
+    schema_version: Option<i64>
+}
+
+/// `GET /version`, what's deployed. `schema_version` is the newest migration applied to the database.
+pub async fn version(State(db): State<DynStore>) -> Json<BuildInfo> {
+    let schema_version = match db.schema_version().await {
+        Ok(version) => version,
+        Err(err) => {
+            tracing::warn!(error = %err, "failed to read the schema version");
+            None
+        }
+    };
+
+    return Json(BuildInfo { version: VERSION, git_sha: GIT_SHA, schema_version });
+}
@@ -0,0 +1,136 @@
+//! Admins acting as another user, e.g. for support to see what the user sees.
+//!
+//! `POST /admin/users/{id}/impersonate` hands out a session token for the user that also names the
+//! admin behind it. It's only open to admins with the `can_impersonate` permission, see
+//! `user impersonation` on the command line, and expires after `impersonation_minutes`. Through it
+//! the admin is the user for almost everything, but:
+//!
+//! - the account's credentials and avatar can't be changed, nor its data exported, see
+//!   [`crate::auth::AccountOwner`],
+//! - every audit event recorded carries the admin in `impersonator_id`,
+//! - `GET /me` says who is impersonating,
+//! - the session is pinned to the admin's organization, `PUT /session/org` is refused,
+//! - the session stops working as soon as the admin loses the permission.
+//!
+//! `DELETE /session/impersonation` ends it early. Other admins can't be impersonated, and neither can
+//! users outside the organization the admin's session acts in, see [`AdminUser`].
+
+use axum::{
+    Router,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, post},
+    Json
+};
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+
+use crate::{
+    audit::{AuditContext, AuditKind},
+    auth::{AdminUser, AuthSession},
+    config::Config,
+    dto::ImpersonationResponse,
+    state::AppState,
+    store::DynStore,
+    telemetry::AUTH,
+    user::Session
+};
+
+/// `POST /admin/users/{id}/impersonate` and `DELETE /session/impersonation`.
+pub fn router() -> Router<AppState> {
+    return Router::new()
+        .route("/admin/users/:user_id/impersonate", post(start))
+        .route("/session/impersonation", delete(end));
+}
+
+/// Starts acting as the user, answering with the token of the new session. The admin's own session
+/// carries on as it was. Users who aren't members of the admin's organization are `404 Not Found`.
+///
+/// The new session acts in the admin's organization and `PUT /session/org` refuses to switch it, so
+/// the admin stays inside their own organization whatever other ones the user is in.
+async fn start(State(db): State<DynStore>, State(config): State<Arc<Config>>, audit: AuditContext, AdminUser { session, user: admin, org }: AdminUser, Path(user_id): Path<String>) -> Response {
+    // An impersonation session is never an admin's, but say so rather than rely on it.
+    if admin.can_impersonate == 0 || session.impersonator_id.is_some() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    // Only members of the admin's organization can be impersonated, so there has to be one.
+    let org = match org {
+        Some(org) => org,
+        None => return (StatusCode::FORBIDDEN, "switch to an organization first").into_response()
+    };
+
+    if user_id == admin.id {
+        return (StatusCode::BAD_REQUEST, "you can't impersonate yourself").into_response();
+    }
+
+    match db.get_membership(&org.id, &user_id).await {
+        Ok(Some(_)) => {},
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    };
+
+    let subject = match db.get_user_by_id(&user_id).await {
+        Ok(Some(user)) if user.deleted_at.is_none() => user,
+        Ok(_) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    };
+
+    if subject.is_admin != 0 {
+        return (StatusCode::FORBIDDEN, "admins can't be impersonated").into_response();
+    }
+    if subject.disabled != 0 {
+        return (StatusCode::CONFLICT, "the account is disabled").into_response();
+    }
+
+    let expires_at = Utc::now() + Duration::minutes(config.impersonation_minutes as i64);
+    let mut impersonation = Session::impersonating(&subject, &admin, expires_at, config.token_length);
+    impersonation.org_id = Some(org.id.clone());
+
+    if let Err(err) = impersonation.add_to_database(&*db).await {
+        tracing::error!(user_id = %subject.id, impersonator_id = %admin.id, error = %err, "failed to store impersonation session");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    tracing::warn!(target: AUTH, event = "impersonation_started", session_id = %impersonation.id, user_id = %subject.id, impersonator_id = %admin.id);
+    audit.event(AuditKind::ImpersonationStarted)
+        .actor(&admin.id)
+        .target(&subject.id)
+        .org(&org.id)
+        .detail("session_id", impersonation.id.as_str())
+        .detail("expires_at", expires_at.to_rfc3339())
+        .record(&*db)
+        .await;
+
+    return (StatusCode::CREATED, Json(ImpersonationResponse {
+        token: impersonation.token,
+        user_id: subject.id,
+        impersonator_id: admin.id,
+        expires_at
+    })).into_response();
+}
+
+/// Revokes the impersonation session the request comes from. `404 Not Found` for the user's own
+/// sessions, which end with `/logout`.
+async fn end(State(db): State<DynStore>, audit: AuditContext, AuthSession { session, user }: AuthSession) -> StatusCode {
+    let impersonator_id = match &session.impersonator_id {
+        Some(id) => id,
+        None => return StatusCode::NOT_FOUND
+    };
+
+    if let Err(err) = db.disable_session(&session.token).await {
+        tracing::error!(session_id = %session.id, error = %err, "failed to revoke impersonation session");
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+
+    tracing::info!(target: AUTH, event = "impersonation_ended", session_id = %session.id, user_id = %user.id, impersonator_id = %impersonator_id);
+    audit.event(AuditKind::ImpersonationEnded)
+        .actor(impersonator_id)
+        .target(&user.id)
+        .detail("session_id", session.id.as_str())
+        .record(&*db)
+        .await;
+
+    return StatusCode::NO_CONTENT;
+}
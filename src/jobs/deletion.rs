@@ -0,0 +1,92 @@
+use chrono::{Duration, Utc};
+use tokio::task::JoinHandle;
+
+use super::{spawn_periodic, ShutdownReceiver};
+use crate::{
+    audit::{AuditContext, AuditKind},
+    blob::DynBlobStore,
+    store::DynStore
+};
+
+/// Removes accounts for good once their owner deleted them more than the grace period ago,
+/// together with their sessions, memberships and avatar.
+///
+/// Audit events about the account are kept, the log is append-only, but the addresses and browsers
+/// in them are cleared. Organizations don't end up without an owner: `DELETE /me` is refused to
+/// their only owner, and owners waiting to be purged don't count as one.
+#[derive(Clone)]
+pub struct AccountDeleter {
+    db: DynStore,
+    blobs: DynBlobStore,
+    grace_period: Duration,
+    batch_size: u32
+}
+
+impl AccountDeleter {
+    pub fn new(db: DynStore, blobs: DynBlobStore, grace_days: u32, batch_size: u32) -> Self {
+        return Self {
+            db,
+            blobs,
+            grace_period: Duration::days(grace_days as i64),
+            batch_size
+        };
+    }
+
+    /// Deletes every account that is due, a batch at a time, and returns how many were deleted.
+    pub async fn run_once(&self, shutdown: &ShutdownReceiver) -> Result<u64, sqlx::Error> {
+        let cutoff = Utc::now() - self.grace_period;
+        let mut deleted = 0;
+
+        loop {
+            let users = self.db.list_users_deleted_before(cutoff, self.batch_size).await?;
+
+            for user in &users {
+                // Before the account goes, so a failure is retried with the next run.
+                self.db.anonymize_audit_events(&user.id).await?;
+
+                // Deleted first, so an account is never left without the avatar it points to.
+                if !self.db.delete_user(&user.id).await? {
+                    continue;
+                }
+                deleted += 1;
+
+                if let Some(key) = &user.avatar_key {
+                    if let Err(err) = self.blobs.delete(key).await {
+                        tracing::warn!(user_id = %user.id, key, error = %err, "failed to delete avatar of deleted account");
+                    }
+                }
+
+                tracing::info!(user_id = %user.id, "deleted account for good");
+                AuditContext::default().event(AuditKind::AccountPurged)
+                    .target(&user.id)
+                    .detail("deleted_at", user.deleted_at.map(|at| at.to_rfc3339()))
+                    .record(&*self.db)
+                    .await;
+            }
+
+            if users.len() < self.batch_size as usize || *shutdown.borrow() {
+                break;
+            }
+
+            tokio::task::yield_now().await;
+        }
+
+        return Ok(deleted);
+    }
+
+    /// Runs the deletion every `period` until shutdown.
+    pub fn spawn(self, period: std::time::Duration, shutdown: ShutdownReceiver) -> JoinHandle<()> {
+        let run_shutdown = shutdown.clone();
+
+        return spawn_periodic("account deletion", period, shutdown, move || {
+            let deleter = self.clone();
+            let shutdown = run_shutdown.clone();
+
+            async move {
+                if let Err(err) = deleter.run_once(&shutdown).await {
+                    tracing::error!(error = %err, "account deletion failed");
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,53 @@
+use std::{future::Future, time::Duration};
+use tokio::{sync::watch, task::JoinHandle, time::MissedTickBehavior};
+
+pub mod deletion;
+pub mod purge;
+
+pub use self::deletion::AccountDeleter;
+pub use self::purge::{PurgeStats, SessionPurger};
+
+/// Tells background jobs to stop. Sending `true`, or dropping the sender, stops them.
+pub type ShutdownReceiver = watch::Receiver<bool>;
+
+/// Completes once shutdown has been requested or the sender is gone.
+pub async fn wait_for_shutdown(shutdown: &mut ShutdownReceiver) {
+    while !*shutdown.borrow() {
+        if shutdown.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Runs `task` every `period` until shutdown.
+///
+/// Each run is spawned on its own, so a run that panics is reported and the job carries on with
+/// the next tick instead of dying. A run that is in progress when shutdown is requested is allowed
+/// to finish.
+pub fn spawn_periodic<F, Fut>(name: &'static str, period: Duration, mut shutdown: ShutdownReceiver, task: F) -> JoinHandle<()>
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static
+{
+    return tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            if *shutdown.borrow() {
+                return;
+            }
+
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = shutdown.changed() => return
+            }
+
+            if let Err(err) = tokio::spawn(task()).await {
+                if err.is_panic() {
+                    tracing::error!(job = name, "background job panicked, retrying on the next run");
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,109 @@
+use std::{sync::{Arc, RwLock}, time::{Duration, Instant}};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::task::JoinHandle;
+
+use super::{spawn_periodic, ShutdownReceiver};
+use crate::store::DynStore;
+
+/// What the last cleanup run did.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PurgeStats {
+    pub runs: u64,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_run_millis: u64,
+    pub last_run_deleted: u64,
+    pub last_run_batches: u64,
+    pub last_error: Option<String>,
+    pub total_deleted: u64
+}
+
+/// Deletes expired and disabled sessions in batches so the table doesn't grow forever.
+///
+/// Sessions are the only thing it purges for now. Password reset tokens and login lockout counters
+/// would belong here too, but neither exists yet: whatever adds them should add their cleanup to
+/// [`SessionPurger::run_once`] as well.
+#[derive(Clone)]
+pub struct SessionPurger {
+    db: DynStore,
+    batch_size: u32,
+    stats: Arc<RwLock<PurgeStats>>
+}
+
+impl SessionPurger {
+    pub fn new(db: DynStore, batch_size: u32) -> Self {
+        return Self {
+            db,
+            batch_size,
+            stats: Arc::new(RwLock::new(PurgeStats::default()))
+        };
+    }
+
+    /// Stats of the most recent run.
+    pub fn stats(&self) -> PurgeStats {
+        return self.stats.read().unwrap().clone();
+    }
+
+    /// Deletes stale sessions one batch at a time until none are left or shutdown is requested.
+    pub async fn run_once(&self, shutdown: &ShutdownReceiver) -> PurgeStats {
+        let started = Instant::now();
+        let now = Utc::now();
+
+        let mut deleted = 0;
+        let mut batches = 0;
+        let mut error = None;
+
+        loop {
+            match self.db.delete_stale_sessions(now, self.batch_size).await {
+                Ok(count) => {
+                    deleted += count;
+                    batches += 1;
+
+                    if count < self.batch_size as u64 {
+                        break;
+                    }
+                },
+                Err(err) => {
+                    error = Some(err.to_string());
+                    break;
+                }
+            };
+
+            // Stop between batches so shutdown isn't held up by a large backlog.
+            if *shutdown.borrow() {
+                break;
+            }
+
+            tokio::task::yield_now().await;
+        }
+
+        let mut stats = self.stats.write().unwrap();
+        stats.runs += 1;
+        stats.last_run_at = Some(now);
+        stats.last_run_millis = started.elapsed().as_millis() as u64;
+        stats.last_run_deleted = deleted;
+        stats.last_run_batches = batches;
+        stats.last_error = error;
+        stats.total_deleted += deleted;
+
+        return stats.clone();
+    }
+
+    /// Runs the cleanup every `period` until shutdown.
+    pub fn spawn(self, period: Duration, shutdown: ShutdownReceiver) -> JoinHandle<()> {
+        let run_shutdown = shutdown.clone();
+
+        return spawn_periodic("session purge", period, shutdown, move || {
+            let purger = self.clone();
+            let shutdown = run_shutdown.clone();
+
+            async move {
+                let stats = purger.run_once(&shutdown).await;
+
+                if let Some(err) = &stats.last_error {
+                    tracing::error!(deleted = stats.last_run_deleted, error = %err, "session purge failed");
+                }
+            }
+        });
+    }
+}
@@ -1 +1,29 @@
+pub mod account;
+pub mod admin;
+pub mod audit;
+pub mod auth;
+pub mod blob;
+pub mod client;
+pub mod cli;
+pub mod config;
+pub mod cookies;
+pub mod dto;
+pub mod health;
+pub mod impersonation;
+pub mod jobs;
+pub mod metrics;
+pub mod negotiate;
+pub mod notify;
+pub mod org;
+pub mod profile;
+pub mod routes;
+pub mod server;
+pub mod state;
+pub mod store;
+pub mod telemetry;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod user;
+pub mod zip;
+
+pub use routes::app;
@@ -0,0 +1,58 @@
+use axum::extract::State;
+use axum_extra::metrics::{Counter, Gauge, Metrics};
+use chrono::Utc;
+
+use crate::{store::DynStore, user};
+
+/// The application's Prometheus metrics.
+///
+/// Request counts and latencies per route are recorded by [`Metrics::layer`], the rest are the
+/// authentication counters below and gauges that are refreshed on every scrape.
+#[derive(Clone)]
+pub struct AppMetrics {
+    pub registry: Metrics,
+    pub logins_succeeded: Counter,
+    pub logins_failed: Counter,
+    pub registrations: Counter,
+    active_sessions: Gauge,
+    bcrypt_queue_depth: Gauge
+}
+
+impl AppMetrics {
+    pub fn new() -> Self {
+        let registry = Metrics::new();
+
+        return Self {
+            logins_succeeded: registry.counter("auth_logins_total", "Login attempts by result.", &[("result", "succeeded")]),
+            logins_failed: registry.counter("auth_logins_total", "Login attempts by result.", &[("result", "failed")]),
+            registrations: registry.counter("auth_registrations_total", "Accounts registered.", &[]),
+            active_sessions: registry.gauge("auth_active_sessions", "Sessions that are not expired and belong to an enabled user.", &[]),
+            bcrypt_queue_depth: registry.gauge("bcrypt_queue_depth", "Password hashes being computed or waiting for a thread.", &[]),
+            registry
+        };
+    }
+
+    /// Updates the gauges that are sampled rather than tracked as things happen.
+    async fn refresh(&self, db: &DynStore) {
+        match db.count_active_sessions(Utc::now()).await {
+            Ok(count) => self.active_sessions.set(count as i64),
+            // Keep the last value, a failing scrape would hide every other metric too.
+            Err(err) => tracing::error!(error = %err, "failed to count active sessions")
+        };
+
+        self.bcrypt_queue_depth.set(user::bcrypt_queue_depth() as i64);
+    }
+}
+
+impl Default for AppMetrics {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+/// `GET /metrics`, in the Prometheus text format.
+pub async fn scrape(State(db): State<DynStore>, State(metrics): State<AppMetrics>) -> Metrics {
+    metrics.refresh(&db).await;
+
+    return metrics.registry;
+}
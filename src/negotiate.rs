@@ -0,0 +1,102 @@
+//! Picking the response format from the `Accept` header, so HTML forms and JavaScript clients
+//! can share endpoints.
+
+use std::convert::Infallible;
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header::ACCEPT, request::Parts, StatusCode},
+    response::{Html, IntoResponse, Response}
+};
+
+/// The response format a client prefers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    Json,
+    Html,
+    /// No `Accept` header, or only wildcards and types we don't serve. Handlers answer the way
+    /// they did before negotiation existed.
+    Unspecified
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ResponseFormat
+where
+    S: Send + Sync
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        return Ok(match parts.headers.get(ACCEPT).and_then(|value| value.to_str().ok()) {
+            Some(accept) => ResponseFormat::from_accept(accept),
+            None => ResponseFormat::Unspecified
+        });
+    }
+}
+
+impl ResponseFormat {
+    /// The format with the highest quality in an `Accept` header, the earlier one on ties.
+    pub fn from_accept(accept: &str) -> Self {
+        let mut best = (ResponseFormat::Unspecified, 0.0);
+
+        for range in accept.split(',') {
+            let mut params = range.split(';');
+            let media_type = params.next().unwrap_or("").trim();
+
+            let format = if media_type.eq_ignore_ascii_case("application/json") {
+                ResponseFormat::Json
+            } else if media_type.eq_ignore_ascii_case("text/html") || media_type.eq_ignore_ascii_case("application/xhtml+xml") {
+                ResponseFormat::Html
+            } else {
+                continue;
+            };
+
+            let quality = params
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .map_or(1.0, |(_, value)| value.trim().parse::<f32>().unwrap_or(0.0));
+
+            if quality > best.1 {
+                best = (format, quality);
+            }
+        }
+
+        return best.0;
+    }
+
+    /// An error response: a page explaining it for HTML clients, just the status for the rest.
+    pub fn error(self, status: StatusCode, title: &str, message: &str) -> Response {
+        return match self {
+            ResponseFormat::Html => html_page(status, title, message),
+            ResponseFormat::Json | ResponseFormat::Unspecified => status.into_response()
+        };
+    }
+}
+
+/// A minimal HTML page, for form submissions that expect one back.
+pub fn html_page(status: StatusCode, title: &str, message: &str) -> Response {
+    let page = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n<p>{message}</p>\n</body>\n</html>\n",
+        title = escape_html(title),
+        message = escape_html(message)
+    );
+
+    return (status, Html(page)).into_response();
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c)
+        }
+    }
+
+    return escaped;
+}
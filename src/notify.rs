@@ -0,0 +1,90 @@
+//! Telling users about things that happen to their account, such as a login from a new device.
+//!
+//! By default notifications only go to the log. Email, push or a webhook can be plugged in by
+//! implementing [`Notifier`] and replacing `AppState::notifier`.
+
+use std::{net::IpAddr, sync::{Arc, Mutex}};
+use async_trait::async_trait;
+use axum::BoxError;
+use chrono::{DateTime, Utc};
+
+use crate::telemetry::AUTH;
+
+/// Something a user should hear about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notification {
+    /// A login from a device the user hasn't logged in from before.
+    NewDevice {
+        user_id: String,
+        username: String,
+        session_id: String,
+        device_id: String,
+        ip: Option<IpAddr>,
+        user_agent: Option<String>,
+        at: DateTime<Utc>
+    }
+}
+
+impl Notification {
+    /// The user the notification is for.
+    pub fn user_id(&self) -> &str {
+        return match self {
+            Notification::NewDevice { user_id, .. } => user_id
+        };
+    }
+}
+
+/// A way to reach users.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Sends the notification. Runs in the background of the request, so it may take its time.
+    /// Failures are logged by the caller, they never fail the request.
+    async fn notify(&self, notification: &Notification) -> Result<(), BoxError>;
+}
+
+/// The notifier shared between handlers.
+pub type DynNotifier = Arc<dyn Notifier>;
+
+/// Writes notifications to the log and nowhere else.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), BoxError> {
+        match notification {
+            Notification::NewDevice { user_id, session_id, device_id, ip, user_agent, .. } => {
+                let ip = ip.map(|ip| ip.to_string());
+                tracing::info!(target: AUTH, event = "new_device", user_id = %user_id, session_id = %session_id, device_id = %device_id, ip = ?ip, user_agent = ?user_agent);
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+/// Keeps every notification in memory, for tests.
+#[derive(Debug, Default)]
+pub struct MemoryNotifier {
+    sent: Mutex<Vec<Notification>>
+}
+
+impl MemoryNotifier {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Everything sent so far, oldest first.
+    pub fn sent(&self) -> Vec<Notification> {
+        return self.sent.lock().unwrap().clone();
+    }
+}
+
+#[async_trait]
+impl Notifier for MemoryNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), BoxError> {
+        self.sent.lock().unwrap().push(notification.clone());
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,64 @@
+//! Invitation links.
+//!
+//! The token in a link is `<invitation id>.<signature>`, where the signature is an HMAC-SHA256 over
+//! the invitation's terms (organization, role, email and expiry) with the signing half of the
+//! cookie key. A link can't be made up or changed, e.g. to a higher role, and it stops working when
+//! the stored invitation is accepted, revoked or expires.
+
+use axum_extra::extract::cookie::Key;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::Invitation;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Keeps these signatures apart from anything else signed with the same key.
+const CONTEXT: &[u8] = b"org-invitation\0";
+
+/// The token for the invitation's link.
+pub fn token(invitation: &Invitation, key: &Key) -> String {
+    let signature = mac(invitation, key).finalize().into_bytes();
+
+    return format!("{}.{}", invitation.id, URL_SAFE_NO_PAD.encode(signature));
+}
+
+/// The invitation id in a token, without checking the signature, `None` if it isn't a token.
+pub fn invitation_id(token: &str) -> Option<&str> {
+    let (id, signature) = token.split_once('.')?;
+    if id.is_empty() || signature.is_empty() {
+        return None;
+    }
+
+    return Some(id);
+}
+
+/// Whether the token was signed for exactly this invitation. Compares in constant time.
+pub fn verify(token: &str, invitation: &Invitation, key: &Key) -> bool {
+    let signature = match token.split_once('.') {
+        Some((id, signature)) if id == invitation.id => signature,
+        _ => return false
+    };
+
+    let signature = match URL_SAFE_NO_PAD.decode(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false
+    };
+
+    return mac(invitation, key).verify_slice(&signature).is_ok();
+}
+
+fn mac(invitation: &Invitation, key: &Key) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key.signing()).expect("HMAC takes keys of any length");
+
+    mac.update(CONTEXT);
+    for field in [&invitation.id, &invitation.org_id, invitation.role.as_str(), invitation.email.as_deref().unwrap_or("")] {
+        // Length-prefixed, so moving bytes between fields changes the signature.
+        mac.update(&(field.len() as u64).to_be_bytes());
+        mac.update(field.as_bytes());
+    }
+    mac.update(&invitation.expires_at.timestamp().to_be_bytes());
+
+    return mac;
+}
@@ -0,0 +1,164 @@
+//! Organizations: groups of users, each with a role per organization.
+//!
+//! A session acts in at most one organization at a time, see `PUT /session/org`, which
+//! [`crate::auth::CurrentOrg`] resolves for the handlers. Everything an organization's admins can
+//! see is scoped to it.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::user::User;
+
+pub mod invitation;
+pub mod routes;
+
+#[derive(FromRow, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>
+}
+
+impl Organization {
+    pub fn new(name: &str) -> Self {
+        return Self {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            created_at: Utc::now()
+        };
+    }
+}
+
+/// What a member may do in an organization, each role can do everything the ones before it can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrgRole {
+    /// Sees the organization and its members.
+    Member,
+    /// Invites people, changes roles below owner and reads the organization's audit log.
+    Admin,
+    /// Everything, including making other members owners.
+    Owner
+}
+
+impl OrgRole {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            OrgRole::Member => "member",
+            OrgRole::Admin => "admin",
+            OrgRole::Owner => "owner"
+        };
+    }
+}
+
+impl TryFrom<String> for OrgRole {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        return match value.as_str() {
+            "member" => Ok(OrgRole::Member),
+            "admin" => Ok(OrgRole::Admin),
+            "owner" => Ok(OrgRole::Owner),
+            _ => Err(format!("unknown organization role `{}`", value))
+        };
+    }
+}
+
+#[derive(FromRow, Debug, Clone, PartialEq, Eq)]
+pub struct Membership {
+    pub org_id: String,
+    pub user_id: String,
+    #[sqlx(try_from = "String")]
+    pub role: OrgRole,
+    pub created_at: DateTime<Utc>
+}
+
+impl Membership {
+    pub fn new(org_id: &str, user_id: &str, role: OrgRole) -> Self {
+        return Self {
+            org_id: org_id.to_string(),
+            user_id: user_id.to_string(),
+            role,
+            created_at: Utc::now()
+        };
+    }
+}
+
+/// A user with their role in the organization being listed.
+#[derive(FromRow, Debug, Clone)]
+pub struct OrgMember {
+    #[sqlx(flatten)]
+    pub user: User,
+    #[sqlx(try_from = "String")]
+    pub role: OrgRole,
+    pub joined_at: DateTime<Utc>
+}
+
+/// An organization with the role of the user whose organizations are being listed.
+#[derive(FromRow, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserOrg {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub org: Organization,
+    #[sqlx(try_from = "String")]
+    pub role: OrgRole,
+    pub joined_at: DateTime<Utc>
+}
+
+/// An offer to join an organization, handed out as a signed link, see [`invitation`].
+#[derive(FromRow, Debug, Clone, PartialEq, Eq)]
+pub struct Invitation {
+    pub id: String,
+    pub org_id: String,
+    #[sqlx(try_from = "String")]
+    pub role: OrgRole,
+    /// Who the link was meant for, for the admins' records. Anyone with the link can accept it:
+    /// emails aren't verified, so matching the account's email would prove nothing.
+    pub email: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_by: Option<String>,
+    pub accepted_at: Option<DateTime<Utc>>
+}
+
+impl Invitation {
+    pub fn new(org_id: &str, role: OrgRole, email: Option<String>, created_by: &str, expires_at: DateTime<Utc>) -> Self {
+        return Self {
+            id: Uuid::new_v4().to_string(),
+            org_id: org_id.to_string(),
+            role,
+            email,
+            created_by: created_by.to_string(),
+            created_at: Utc::now(),
+            expires_at,
+            accepted_by: None,
+            accepted_at: None
+        };
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AcceptInvitationResult {
+    Success,
+    /// Somebody accepted it already, or it was revoked.
+    Unavailable,
+    AlreadyMember,
+    DatabaseError
+}
+
+/// Checks the name of a new organization, returning it trimmed.
+pub fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+
+    if name.is_empty() || name.chars().count() > 100 {
+        return Err("must be between 1 and 100 characters".to_string());
+    }
+    if name.chars().any(char::is_control) {
+        return Err("must not contain control characters".to_string());
+    }
+
+    return Ok(name.to_string());
+}
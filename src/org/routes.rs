@@ -0,0 +1,370 @@
+//! Endpoints for organizations, their members and invitations.
+//!
+//! Everything under `/org` acts on the organization the session is in, see
+//! [`CurrentOrg`], so one organization's admins never see another's members or events.
+
+use axum::{
+    Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Json
+};
+use axum_extra::extract::cookie::Key;
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+
+use super::{invitation, validate_name, AcceptInvitationResult, Invitation, Membership, OrgRole, Organization};
+use crate::{
+    admin::{self, AuditPage, Page},
+    audit::{AuditContext, AuditFilter, AuditKind},
+    auth::{AuthSession, AuthUser, CurrentOrg},
+    config::Config,
+    dto::{CreateInvitationRequest, CreateOrgRequest, FieldError, InvitationPreview, InvitationResponse, MemberSummary, OrgResponse, SwitchOrgRequest, UpdateMemberRequest},
+    profile,
+    state::AppState,
+    store::DynStore
+};
+
+/// The organization endpoints that need a session. `GET /invitations/{token}` is separate, see
+/// [`preview_invitation`].
+pub fn router() -> Router<AppState> {
+    return Router::new()
+        .route("/orgs", get(list_orgs).post(create_org))
+        .route("/session/org", put(switch_org))
+        .route("/org", get(current_org))
+        .route("/org/members", get(list_members))
+        .route("/org/members/:user_id", put(update_member).delete(remove_member))
+        .route("/org/invitations", get(list_invitations).post(create_invitation))
+        .route("/org/invitations/:id", delete(revoke_invitation))
+        .route("/org/audit", get(org_audit))
+        .route("/invitations/:token/accept", post(accept_invitation));
+}
+
+/// `POST /orgs`, the caller becomes its owner.
+async fn create_org(State(db): State<DynStore>, audit: AuditContext, AuthUser(user): AuthUser, Json(request): Json<CreateOrgRequest>) -> Response {
+    let name = match validate_name(&request.name) {
+        Ok(name) => name,
+        Err(message) => return (StatusCode::UNPROCESSABLE_ENTITY, Json(FieldError { field: "name".to_string(), message })).into_response()
+    };
+
+    let org = Organization::new(&name);
+    let owner = Membership::new(&org.id, &user.id, OrgRole::Owner);
+
+    if let Err(err) = db.add_org(&org, &owner).await {
+        tracing::error!(user_id = %user.id, error = %err, "failed to create organization");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    audit.event(AuditKind::OrgCreated)
+        .actor(&user.id)
+        .org(&org.id)
+        .detail("name", name)
+        .record(&*db)
+        .await;
+
+    return (StatusCode::CREATED, Json(OrgResponse { org, role: OrgRole::Owner })).into_response();
+}
+
+/// `GET /orgs`, the organizations the caller belongs to with their role in each.
+async fn list_orgs(State(db): State<DynStore>, AuthUser(user): AuthUser) -> Response {
+    return match db.list_user_orgs(&user.id).await {
+        Ok(orgs) => Json(orgs).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    };
+}
+
+/// `PUT /session/org`, switches the organization the session acts in. Organizations the caller
+/// isn't a member of are `404 Not Found`, whether they exist or not. Impersonation sessions stay in
+/// the impersonating admin's organization and get `403 Forbidden`.
+async fn switch_org(State(db): State<DynStore>, AuthSession { session, user }: AuthSession, Json(request): Json<SwitchOrgRequest>) -> Response {
+    if session.impersonator_id.is_some() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let org_id = match request.org_id {
+        Some(org_id) => org_id,
+        None => {
+            return match db.set_session_org(&session.token, None).await {
+                Ok(_) => StatusCode::NO_CONTENT.into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            };
+        }
+    };
+
+    let role = match db.get_membership(&org_id, &user.id).await {
+        Ok(Some(membership)) => membership.role,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    };
+
+    let org = match db.get_org(&org_id).await {
+        Ok(Some(org)) => org,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    };
+
+    if let Err(err) = db.set_session_org(&session.token, Some(&org.id)).await {
+        tracing::error!(session_id = %session.id, error = %err, "failed to switch organization");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    return Json(OrgResponse { org, role }).into_response();
+}
+
+/// `GET /org`.
+async fn current_org(current: CurrentOrg) -> Json<OrgResponse> {
+    return Json(OrgResponse { org: current.org, role: current.role });
+}
+
+/// `GET /org/members`, by username.
+async fn list_members(State(db): State<DynStore>, current: CurrentOrg) -> Response {
+    return match db.list_org_members(&current.org.id).await {
+        Ok(members) => Json(members.iter().map(MemberSummary::from).collect::<Vec<_>>()).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    };
+}
+
+/// How many owners the organization has. Owners whose account is waiting to be purged don't count,
+/// they're about to go.
+pub async fn owner_count(db: &DynStore, org_id: &str) -> Result<usize, StatusCode> {
+    return match db.list_org_members(org_id).await {
+        Ok(members) => Ok(members.iter().filter(|member| member.role == OrgRole::Owner && member.user.deleted_at.is_none()).count()),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+}
+
+/// The membership of `user_id` in the current organization, after checking the caller may change
+/// it: admins can manage members and admins, only owners can manage owners.
+async fn managed_membership(db: &DynStore, current: &CurrentOrg, user_id: &str) -> Result<Membership, StatusCode> {
+    current.require(OrgRole::Admin)?;
+
+    let membership = match db.get_membership(&current.org.id, user_id).await {
+        Ok(Some(membership)) => membership,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+
+    if membership.role == OrgRole::Owner {
+        current.require(OrgRole::Owner)?;
+    }
+
+    return Ok(membership);
+}
+
+/// `PUT /org/members/{user id}`. Needs admin, or owner to make or unmake owners.
+async fn update_member(State(db): State<DynStore>, audit: AuditContext, current: CurrentOrg, Path(user_id): Path<String>, Json(request): Json<UpdateMemberRequest>) -> Result<StatusCode, StatusCode> {
+    let membership = managed_membership(&db, &current, &user_id).await?;
+    if request.role == OrgRole::Owner {
+        current.require(OrgRole::Owner)?;
+    }
+
+    if membership.role == request.role {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+
+    // There's always someone left who can manage the organization.
+    if membership.role == OrgRole::Owner && owner_count(&db, &current.org.id).await? <= 1 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    match db.set_member_role(&current.org.id, &user_id, request.role).await {
+        Ok(true) => {},
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    audit.event(AuditKind::MemberRoleChanged)
+        .actor(&current.user.id)
+        .target(&user_id)
+        .org(&current.org.id)
+        .detail("from", membership.role.as_str())
+        .detail("to", request.role.as_str())
+        .record(&*db)
+        .await;
+
+    return Ok(StatusCode::NO_CONTENT);
+}
+
+/// `DELETE /org/members/{user id}`. Anyone can leave, removing others needs admin, or owner to
+/// remove an owner. The last owner can do neither.
+async fn remove_member(State(db): State<DynStore>, audit: AuditContext, current: CurrentOrg, Path(user_id): Path<String>) -> Result<StatusCode, StatusCode> {
+    let membership = if user_id == current.user.id {
+        Membership::new(&current.org.id, &user_id, current.role)
+    } else {
+        managed_membership(&db, &current, &user_id).await?
+    };
+
+    if membership.role == OrgRole::Owner && owner_count(&db, &current.org.id).await? <= 1 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    match db.delete_membership(&current.org.id, &user_id).await {
+        Ok(true) => {},
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    audit.event(AuditKind::MemberRemoved)
+        .actor(&current.user.id)
+        .target(&user_id)
+        .org(&current.org.id)
+        .detail("role", membership.role.as_str())
+        .record(&*db)
+        .await;
+
+    return Ok(StatusCode::NO_CONTENT);
+}
+
+/// `POST /org/invitations`, needs admin, or owner to invite owners. The link is valid for
+/// `invitation_ttl_hours` and works once.
+async fn create_invitation(State(db): State<DynStore>, State(config): State<Arc<Config>>, State(key): State<Key>, audit: AuditContext, current: CurrentOrg, Json(request): Json<CreateInvitationRequest>) -> Result<Response, StatusCode> {
+    current.require(OrgRole::Admin)?;
+    if request.role == OrgRole::Owner {
+        current.require(OrgRole::Owner)?;
+    }
+
+    let email = match request.email.as_deref().map(str::trim).filter(|email| !email.is_empty()) {
+        None => None,
+        Some(email) => match profile::validate_email(email) {
+            Ok(email) => Some(email),
+            Err(message) => return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(FieldError { field: "email".to_string(), message })).into_response())
+        }
+    };
+
+    let expires_at = Utc::now() + Duration::hours(config.invitation_ttl_hours as i64);
+    let invitation = Invitation::new(&current.org.id, request.role, email, &current.user.id, expires_at);
+
+    if let Err(err) = db.add_invitation(&invitation).await {
+        tracing::error!(org_id = %current.org.id, error = %err, "failed to store invitation");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    audit.event(AuditKind::InvitationCreated)
+        .actor(&current.user.id)
+        .org(&current.org.id)
+        .detail("invitation_id", invitation.id.as_str())
+        .detail("role", invitation.role.as_str())
+        .detail("email", invitation.email.clone())
+        .record(&*db)
+        .await;
+
+    let token = invitation::token(&invitation, &key);
+
+    return Ok((StatusCode::CREATED, Json(InvitationResponse::new(&invitation, &token))).into_response());
+}
+
+/// `GET /org/invitations`, the ones nobody accepted yet. Needs admin.
+async fn list_invitations(State(db): State<DynStore>, State(key): State<Key>, current: CurrentOrg) -> Result<Json<Vec<InvitationResponse>>, StatusCode> {
+    current.require(OrgRole::Admin)?;
+
+    let invitations = match db.list_pending_invitations(&current.org.id).await {
+        Ok(invitations) => invitations,
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+
+    // Signing is deterministic, so the links are the same ones handed out when they were created.
+    return Ok(Json(invitations.iter()
+        .map(|invitation| InvitationResponse::new(invitation, &invitation::token(invitation, &key)))
+        .collect()));
+}
+
+/// `DELETE /org/invitations/{id}`, its link stops working. Needs admin.
+async fn revoke_invitation(State(db): State<DynStore>, audit: AuditContext, current: CurrentOrg, Path(id): Path<String>) -> Result<StatusCode, StatusCode> {
+    current.require(OrgRole::Admin)?;
+
+    match db.delete_invitation(&current.org.id, &id).await {
+        Ok(true) => {},
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    audit.event(AuditKind::InvitationRevoked)
+        .actor(&current.user.id)
+        .org(&current.org.id)
+        .detail("invitation_id", id)
+        .record(&*db)
+        .await;
+
+    return Ok(StatusCode::NO_CONTENT);
+}
+
+/// The invitation behind a link, if the link is genuine and still usable. Made up and revoked
+/// links are `404 Not Found`, used and expired ones `410 Gone`.
+async fn usable_invitation(db: &DynStore, key: &Key, token: &str) -> Result<Invitation, StatusCode> {
+    let id = match invitation::invitation_id(token) {
+        Some(id) => id,
+        None => return Err(StatusCode::NOT_FOUND)
+    };
+
+    let invitation = match db.get_invitation(id).await {
+        Ok(Some(invitation)) => invitation,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+
+    if !invitation::verify(token, &invitation, key) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    if invitation.accepted_at.is_some() || invitation.expires_at < Utc::now() {
+        return Err(StatusCode::GONE);
+    }
+
+    return Ok(invitation);
+}
+
+/// `GET /invitations/{token}`, which organization and role the link is for. Needs no session, so
+/// it can be shown before signing in.
+pub async fn preview_invitation(State(db): State<DynStore>, State(key): State<Key>, Path(token): Path<String>) -> Result<Json<InvitationPreview>, StatusCode> {
+    let invitation = usable_invitation(&db, &key, &token).await?;
+
+    return match db.get_org(&invitation.org_id).await {
+        Ok(Some(org)) => Ok(Json(InvitationPreview { org, role: invitation.role, expires_at: invitation.expires_at })),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+}
+
+/// `POST /invitations/{token}/accept`, the caller joins with the invitation's role. Whoever holds
+/// the link can accept it, the invitation's email isn't checked, see [`Invitation::email`].
+async fn accept_invitation(State(db): State<DynStore>, State(key): State<Key>, audit: AuditContext, AuthUser(user): AuthUser, Path(token): Path<String>) -> Result<Json<OrgResponse>, StatusCode> {
+    let invitation = usable_invitation(&db, &key, &token).await?;
+
+    let org = match db.get_org(&invitation.org_id).await {
+        Ok(Some(org)) => org,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+
+    let membership = Membership::new(&org.id, &user.id, invitation.role);
+
+    match db.accept_invitation(&invitation.id, &membership).await {
+        AcceptInvitationResult::Success => {},
+        AcceptInvitationResult::Unavailable => return Err(StatusCode::GONE),
+        AcceptInvitationResult::AlreadyMember => return Err(StatusCode::CONFLICT),
+        AcceptInvitationResult::DatabaseError => return Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    audit.event(AuditKind::MemberAdded)
+        .actor(&user.id)
+        .target(&user.id)
+        .org(&org.id)
+        .detail("invitation_id", invitation.id.as_str())
+        .detail("role", invitation.role.as_str())
+        .record(&*db)
+        .await;
+
+    return Ok(Json(OrgResponse { org, role: invitation.role }));
+}
+
+/// `GET /org/audit`, the organization's events with the filters and paging of `/admin/audit`.
+/// Needs admin. Events of other organizations can't be asked for.
+async fn org_audit(State(db): State<DynStore>, current: CurrentOrg, Query(mut filter): Query<AuditFilter>, Query(page): Query<Page>) -> Result<Json<AuditPage>, StatusCode> {
+    current.require(OrgRole::Admin)?;
+
+    filter.org_id = Some(current.org.id.clone());
+
+    return admin::audit_page(&db, &filter, page).await;
+}
@@ -0,0 +1,455 @@
+//! The caller's own profile: `GET`/`PATCH /me` and the avatar.
+//!
+//! `PATCH /me` takes a JSON merge patch (RFC 7396): fields that are left out stay as they are,
+//! `null` clears a field. Edits need the profile's current `ETag` in `If-Match`, so two clients
+//! can't overwrite each other's changes without noticing.
+
+use axum::{
+    Router,
+    body::Bytes,
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::{header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH, X_CONTENT_TYPE_OPTIONS}, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Json
+};
+use chrono::Utc;
+use serde::{Deserialize, Deserializer};
+use std::sync::Arc;
+
+use crate::{
+    audit::{AuditContext, AuditKind},
+    auth::{AccountOwner, AuthSession},
+    blob::DynBlobStore,
+    config::Config,
+    dto::{FieldError, ImpersonationSummary, UserProfile},
+    state::AppState,
+    store::DynStore,
+    user::{UpdateProfileResult, User}
+};
+
+/// Multipart boundaries and part headers on top of the avatar itself.
+const MULTIPART_OVERHEAD: usize = 16 * 1024;
+
+/// `/me` and `/me/avatar`. Avatars larger than `avatar_max_bytes` are refused.
+pub fn router(avatar_max_bytes: usize) -> Router<AppState> {
+    return Router::new()
+        .route("/me", get(get_me).patch(update_me))
+        .route("/me/avatar", put(upload_avatar).delete(delete_avatar)
+            .layer(DefaultBodyLimit::max(avatar_max_bytes + MULTIPART_OVERHEAD)));
+}
+
+/// Identifies a version of a user's profile. The id is part of it so a browser that switches
+/// accounts can't reuse a cached profile of the other one.
+pub fn etag(user: &User) -> String {
+    return format!("\"{}.{}\"", user.id, user.version);
+}
+
+/// The profile with its `ETag`, or `304 Not Modified` if the client's copy is current.
+///
+/// During an impersonation the profile says who is impersonating. That isn't part of the profile's
+/// version, so those responses are never cached and always sent in full.
+async fn get_me(AuthSession { session, user }: AuthSession, headers: HeaderMap) -> Response {
+    if let Some(impersonation) = ImpersonationSummary::of(&session) {
+        let profile = UserProfile { impersonation: Some(impersonation), ..UserProfile::from(&user) };
+
+        return ([(ETAG, etag(&user)), (CACHE_CONTROL, "no-store".to_string())], Json(profile)).into_response();
+    }
+
+    let etag = etag(&user);
+
+    if let Some(value) = headers.get(IF_NONE_MATCH).and_then(|value| value.to_str().ok()) {
+        // If-None-Match uses the weak comparison.
+        let matches = value.split(',').map(str::trim).any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+        if matches {
+            return (StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response();
+        }
+    }
+
+    return profile_response(&user);
+}
+
+fn profile_response(user: &User) -> Response {
+    return ([(ETAG, etag(user))], Json(UserProfile::from(user))).into_response();
+}
+
+/// The fields `PATCH /me` can change. Anything else in the patch is rejected.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProfilePatch {
+    #[serde(default, deserialize_with = "nullable")]
+    pub display_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub email: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub locale: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub timezone: Option<Option<String>>
+}
+
+/// Tells a `null` (`Some(None)`) apart from a missing field (`None`, from `#[serde(default)]`).
+fn nullable<'de, D>(deserializer: D) -> Result<Option<Option<String>>, D::Error>
+where
+    D: Deserializer<'de>
+{
+    return Option::<String>::deserialize(deserializer).map(Some);
+}
+
+impl ProfilePatch {
+    /// Validates the patch and applies it to `user`, returning the names of the fields that changed.
+    pub fn apply(self, user: &mut User) -> Result<Vec<&'static str>, FieldError> {
+        let mut changed = Vec::new();
+
+        let fields = [
+            ("display_name", self.display_name, &mut user.display_name, validate_display_name as fn(&str) -> Result<String, String>),
+            ("email", self.email, &mut user.email, validate_email),
+            ("locale", self.locale, &mut user.locale, validate_locale),
+            ("timezone", self.timezone, &mut user.timezone, validate_timezone)
+        ];
+
+        for (name, patch, current, validate) in fields {
+            let value = match patch {
+                None => continue,
+                Some(None) => None,
+                Some(Some(value)) if value.trim().is_empty() => None,
+                Some(Some(value)) => match validate(value.trim()) {
+                    Ok(value) => Some(value),
+                    Err(message) => return Err(FieldError { field: name.to_string(), message })
+                }
+            };
+
+            if *current != value {
+                *current = value;
+                changed.push(name);
+            }
+        }
+
+        return Ok(changed);
+    }
+}
+
+fn validate_display_name(value: &str) -> Result<String, String> {
+    if value.chars().count() > 100 {
+        return Err("must be at most 100 characters".to_string());
+    }
+    if value.chars().any(char::is_control) {
+        return Err("must not contain control characters".to_string());
+    }
+
+    return Ok(value.to_string());
+}
+
+/// Only catches obvious mistakes, whether the address works is up to whoever mails it.
+pub fn validate_email(value: &str) -> Result<String, String> {
+    let invalid = || Err("is not an email address like name@example.com".to_string());
+
+    if value.len() > 254 || value.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return invalid();
+    }
+
+    let (local, domain) = match value.rsplit_once('@') {
+        Some(parts) => parts,
+        None => return invalid()
+    };
+
+    let domain_ok = domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.') && !domain.contains('@');
+    if local.is_empty() || local.len() > 64 || !domain_ok {
+        return invalid();
+    }
+
+    // Addresses are unique, and nobody means a different mailbox by a different case.
+    return Ok(value.to_lowercase());
+}
+
+/// A BCP 47 language tag: a 2 to 8 letter language, then subtags of 1 to 8 letters or digits.
+fn validate_locale(value: &str) -> Result<String, String> {
+    let mut subtags = value.split('-');
+
+    let language_ok = subtags.next().map_or(false, |language| (2..=8).contains(&language.len()) && language.chars().all(|c| c.is_ascii_alphabetic()));
+    let rest_ok = subtags.all(|subtag| (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric()));
+
+    if value.len() > 35 || !language_ok || !rest_ok {
+        return Err("is not a language tag like en or en-GB".to_string());
+    }
+
+    return Ok(value.to_string());
+}
+
+/// The shape of an IANA time zone name. It isn't checked against the time zone database, which
+/// changes more often than this code does.
+fn validate_timezone(value: &str) -> Result<String, String> {
+    let segment_ok = |segment: &str| {
+        !segment.is_empty() && segment.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+'))
+    };
+
+    if value.len() > 64 || !value.split('/').all(segment_ok) || !value.starts_with(|c: char| c.is_ascii_uppercase()) {
+        return Err("is not a time zone name like UTC or Europe/Berlin".to_string());
+    }
+
+    return Ok(value.to_string());
+}
+
+/// Whether `If-Match` allows changing the profile, `None` if the header is missing.
+fn if_match(headers: &HeaderMap, user: &User) -> Option<bool> {
+    let value = headers.get(IF_MATCH)?.to_str().unwrap_or("");
+    let etag = etag(user);
+
+    // If-Match uses the strong comparison, so weak tags never match.
+    return Some(value.split(',').map(str::trim).any(|tag| tag == "*" || tag == etag));
+}
+
+/// Stores the changed profile with the next version, see [`crate::store::UserStore::update_profile`].
+async fn save_profile(db: &DynStore, mut user: User) -> Result<User, Response> {
+    let expected_version = user.version;
+    user.version += 1;
+    user.updated_at = Utc::now();
+
+    return match db.update_profile(&user, expected_version).await {
+        UpdateProfileResult::Success => Ok(user),
+        UpdateProfileResult::Conflict => Err((StatusCode::PRECONDITION_FAILED, "the profile was changed in the meantime").into_response()),
+        UpdateProfileResult::EmailTaken => Err((StatusCode::CONFLICT, Json(FieldError {
+            field: "email".to_string(),
+            message: "is already used by another account".to_string()
+        })).into_response()),
+        UpdateProfileResult::DatabaseError => Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+    };
+}
+
+/// Applies a merge patch. Needs `If-Match` with the current `ETag`. The email can't be changed
+/// through an impersonation session, password resets would go there.
+async fn update_me(State(db): State<DynStore>, audit: AuditContext, AuthSession { session, user }: AuthSession, headers: HeaderMap, Json(patch): Json<ProfilePatch>) -> Response {
+    if patch.email.is_some() && session.impersonator_id.is_some() {
+        return (StatusCode::FORBIDDEN, "the email can't be changed while impersonating").into_response();
+    }
+
+    match if_match(&headers, &user) {
+        Some(true) => {},
+        Some(false) => return (StatusCode::PRECONDITION_FAILED, [(ETAG, etag(&user))]).into_response(),
+        None => return (StatusCode::PRECONDITION_REQUIRED, "send the profile's ETag in If-Match").into_response()
+    }
+
+    let mut updated = user.clone();
+    let changed = match patch.apply(&mut updated) {
+        Ok(changed) => changed,
+        Err(err) => return (StatusCode::UNPROCESSABLE_ENTITY, Json(err)).into_response()
+    };
+
+    if changed.is_empty() {
+        return profile_response(&user);
+    }
+
+    let updated = match save_profile(&db, updated).await {
+        Ok(updated) => updated,
+        Err(response) => return response
+    };
+
+    audit.event(AuditKind::ProfileUpdated)
+        .actor(&user.id)
+        .target(&user.id)
+        .detail("fields", changed)
+        .record(&*db)
+        .await;
+
+    return profile_response(&updated);
+}
+
+/// Image formats accepted as avatars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageType {
+    Png,
+    Jpeg,
+    Gif,
+    Webp
+}
+
+impl ImageType {
+    const ALL: [ImageType; 4] = [ImageType::Png, ImageType::Jpeg, ImageType::Gif, ImageType::Webp];
+
+    pub fn mime(self) -> &'static str {
+        return match self {
+            ImageType::Png => "image/png",
+            ImageType::Jpeg => "image/jpeg",
+            ImageType::Gif => "image/gif",
+            ImageType::Webp => "image/webp"
+        };
+    }
+
+    pub fn extension(self) -> &'static str {
+        return match self {
+            ImageType::Png => "png",
+            ImageType::Jpeg => "jpg",
+            ImageType::Gif => "gif",
+            ImageType::Webp => "webp"
+        };
+    }
+
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        let essence = mime.split(';').next().unwrap_or("").trim();
+
+        return Self::ALL.into_iter().find(|image| image.mime().eq_ignore_ascii_case(essence));
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        return Self::ALL.into_iter().find(|image| image.extension() == extension);
+    }
+
+    /// The format the data actually is in, going by its first bytes.
+    pub fn sniff(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            return Some(ImageType::Png);
+        }
+        if data.starts_with(b"\xff\xd8\xff") {
+            return Some(ImageType::Jpeg);
+        }
+        if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            return Some(ImageType::Gif);
+        }
+        if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+            return Some(ImageType::Webp);
+        }
+
+        return None;
+    }
+}
+
+/// Replaces the avatar with the `avatar` part of a multipart form. Not open to impersonation,
+/// the picture others see the user by is theirs to pick.
+async fn upload_avatar(State(db): State<DynStore>, State(blobs): State<DynBlobStore>, State(config): State<Arc<Config>>, audit: AuditContext, AccountOwner(user): AccountOwner, mut multipart: Multipart) -> Response {
+    let (image, data) = loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => return (StatusCode::UNPROCESSABLE_ENTITY, "expected an `avatar` part").into_response(),
+            Err(err) => return err.into_response()
+        };
+
+        if field.name() != Some("avatar") {
+            continue;
+        }
+
+        let image = match field.content_type().and_then(ImageType::from_mime) {
+            Some(image) => image,
+            None => return (StatusCode::UNSUPPORTED_MEDIA_TYPE, "avatars have to be PNG, JPEG, GIF or WebP images").into_response()
+        };
+
+        let mut data = Vec::new();
+        loop {
+            match field.chunk().await {
+                Ok(Some(chunk)) => data.extend_from_slice(&chunk),
+                Ok(None) => break,
+                Err(err) => return err.into_response()
+            }
+
+            if data.len() > config.avatar_max_bytes {
+                return (StatusCode::PAYLOAD_TOO_LARGE, format!("avatars can be at most {} bytes", config.avatar_max_bytes)).into_response();
+            }
+        }
+
+        break (image, data);
+    };
+
+    // The declared type is the client's word, the contents have to agree.
+    if ImageType::sniff(&data) != Some(image) {
+        return (StatusCode::UNSUPPORTED_MEDIA_TYPE, format!("the upload isn't a {} image", image.mime())).into_response();
+    }
+
+    let key = format!("avatars/{}/{}.{}", user.id, uuid::Uuid::new_v4(), image.extension());
+    if let Err(err) = blobs.put(&key, Bytes::from(data)).await {
+        tracing::error!(user_id = %user.id, key, error = %err, "failed to store avatar");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    let mut updated = user.clone();
+    updated.avatar_key = Some(key.clone());
+
+    let updated = match save_profile(&db, updated).await {
+        Ok(updated) => updated,
+        Err(response) => {
+            delete_blob(&blobs, &key).await;
+            return response;
+        }
+    };
+
+    if let Some(old) = &user.avatar_key {
+        delete_blob(&blobs, old).await;
+    }
+
+    audit.event(AuditKind::ProfileUpdated)
+        .actor(&user.id)
+        .target(&user.id)
+        .detail("fields", vec!["avatar"])
+        .record(&*db)
+        .await;
+
+    return profile_response(&updated);
+}
+
+/// Removes the avatar. Not open to impersonation either.
+async fn delete_avatar(State(db): State<DynStore>, State(blobs): State<DynBlobStore>, audit: AuditContext, AccountOwner(user): AccountOwner) -> Response {
+    let old = match &user.avatar_key {
+        Some(key) => key.clone(),
+        None => return StatusCode::NO_CONTENT.into_response()
+    };
+
+    let mut updated = user.clone();
+    updated.avatar_key = None;
+
+    if let Err(response) = save_profile(&db, updated).await {
+        return response;
+    }
+
+    delete_blob(&blobs, &old).await;
+
+    audit.event(AuditKind::ProfileUpdated)
+        .actor(&user.id)
+        .target(&user.id)
+        .detail("fields", vec!["avatar"])
+        .record(&*db)
+        .await;
+
+    return StatusCode::NO_CONTENT.into_response();
+}
+
+/// A blob that's no longer referenced. Failing to delete it only wastes space, so it's logged.
+async fn delete_blob(blobs: &DynBlobStore, key: &str) {
+    if let Err(err) = blobs.delete(key).await {
+        tracing::warn!(key, error = %err, "failed to delete blob");
+    }
+}
+
+/// Serves a user's avatar, publicly so it works in `<img>` tags. Disabled accounts and ones
+/// waiting to be purged have none, as if they didn't exist.
+pub async fn avatar(State(db): State<DynStore>, State(blobs): State<DynBlobStore>, Path(user_id): Path<String>, headers: HeaderMap) -> Response {
+    let key = match db.get_user_by_id(&user_id).await {
+        Ok(Some(User { avatar_key: Some(key), disabled: 0, deleted_at: None, .. })) => key,
+        Ok(_) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    };
+
+    // Every upload gets a new key, so the key identifies the contents.
+    let etag = format!("\"{}\"", key.rsplit('/').next().unwrap_or(&key));
+    if headers.get(IF_NONE_MATCH).and_then(|value| value.to_str().ok()) == Some(etag.as_str()) {
+        return (StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response();
+    }
+
+    let image = match key.rsplit_once('.').and_then(|(_, extension)| ImageType::from_extension(extension)) {
+        Some(image) => image,
+        None => return StatusCode::NOT_FOUND.into_response()
+    };
+
+    return match blobs.get(&key).await {
+        Ok(Some(data)) => (
+            [
+                (CONTENT_TYPE, HeaderValue::from_static(image.mime())),
+                (X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+                (CACHE_CONTROL, HeaderValue::from_static("public, no-cache")),
+                (ETAG, HeaderValue::from_str(&etag).unwrap())
+            ],
+            data
+        ).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            tracing::error!(key, error = %err, "failed to read avatar");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    };
+}
@@ -0,0 +1,242 @@
+use std::sync::Arc;
+use axum::{Router, extract::{Query, State}, Json, middleware, routing::{get, post}, http::StatusCode, response::{IntoResponse, Response}};
+use axum_extra::extract::{cookie::{CookieJar, PrivateCookieJar}, JsonOrForm};
+use chrono::Utc;
+use serde::Deserialize;
+use tracing::Instrument;
+
+use crate::{account, admin, audit::{AuditContext, AuditKind}, auth::{self, SessionToken}, client::{ClientInfo, IpRange}, config::Config, cookies::{self, SessionCookie}, dto::{CsrfTokenResponse, TokenResponse, UserProfile}, health, impersonation, metrics::{self, AppMetrics}, negotiate::{self, ResponseFormat}, notify::Notification, org, profile, state::AppState, store::DynStore, telemetry::{self, AUTH}, user::{self, DeviceSeen, User}};
+
+/// Builds the application router on top of the given state.
+///
+/// Every request gets an id and a tracing span, see [`telemetry::trace_request`], and is counted
+/// in the metrics served at `/metrics`.
+pub fn app(state: AppState) -> Router {
+    let metrics_layer = state.metrics.registry.layer();
+
+    // Routes that act on the caller's session, which browsers send along as a cookie. Those need
+    // the CSRF token for anything but reads, and get the session looked up ahead of the handler.
+    let session_routes = Router::new()
+        .route("/logout", post(logout))
+        .merge(profile::router(state.config.avatar_max_bytes))
+        .merge(account::router())
+        .merge(org::routes::router())
+        .merge(impersonation::router())
+        .nest("/admin", admin::router())
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::load_session))
+        .route_layer(middleware::from_fn_with_state(state.clone(), cookies::protect));
+
+    return Router::new()
+        .route("/login", post(login))
+        .route("/register", post(register))
+        .route("/verify", post(verify))
+        .route("/metrics", get(metrics::scrape))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/version", get(health::version))
+        .route("/users/:id/avatar", get(profile::avatar))
+        .route("/invitations/:token", get(org::routes::preview_invitation))
+        .merge(session_routes)
+        .layer(metrics_layer)
+        .layer(middleware::from_fn(telemetry::trace_request))
+        .with_state(state);
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct LoginOptions {
+    /// Put the session in a cookie instead of returning the token, see [`cookies`].
+    cookie: bool,
+    /// Only accept the session from the network it was started from, see [`IpRange::around`].
+    bind_ip: bool
+}
+
+#[derive(Deserialize, Debug)]
+struct LoginForm {
+    username: String,
+    password: String
+}
+
+const INTERNAL_ERROR_MESSAGE: &str = "Something went wrong on our side, please try again later.";
+
+/// Takes JSON or a form and answers in the format asked for in `Accept`.
+async fn login(State(state): State<AppState>, audit: AuditContext, client: ClientInfo, format: ResponseFormat, jar: PrivateCookieJar, Query(options): Query<LoginOptions>, JsonOrForm(data): JsonOrForm<LoginForm>) -> Response {
+    let AppState { db, config, metrics, notifier, .. } = state;
+
+    let bound_range = match (options.bind_ip, client.ip) {
+        (false, _) => None,
+        (true, Some(ip)) => Some(IpRange::around(ip)),
+        (true, None) => return format.error(StatusCode::BAD_REQUEST, "Sign in failed", "Your address isn't known, so the session can't be bound to it.")
+    };
+
+    let user = match user::authenticate(&data.username, &data.password, &*db).await {
+        Ok(user) => user,
+        Err(err) => {
+            metrics.logins_failed.inc();
+
+            let mut event = audit.event(AuditKind::LoginFailed)
+                .detail("username", data.username.as_str())
+                .detail("reason", err.reason());
+            if let Some(user_id) = err.user_id() {
+                event = event.target(user_id);
+            }
+            event.record(&*db).await;
+
+            return format.error(StatusCode::UNAUTHORIZED, "Sign in failed", "Wrong username or password.");
+        }
+    };
+    
+    let mut token = user::Session::with_token_length(&user, config.token_length);
+    token.started_by(&client);
+    token.bound_range = bound_range.map(|range| range.to_string());
+
+    if let Err(err) = token.add_to_database(&*db).await {
+        tracing::error!(user_id = %user.id, error = %err, "failed to store session");
+        return format.error(StatusCode::INTERNAL_SERVER_ERROR, "Sign in failed", INTERNAL_ERROR_MESSAGE);
+    }
+
+    // A first device has nothing to be new compared to, so only later ones are pointed out.
+    let new_device = match db.remember_device(&user.id, &client.device_id, Utc::now()).await {
+        Ok(seen) => seen == DeviceSeen::New,
+        Err(err) => {
+            tracing::error!(user_id = %user.id, error = %err, "failed to remember device");
+            false
+        }
+    };
+
+    metrics.logins_succeeded.inc();
+    audit.event(AuditKind::LoginSucceeded)
+        .actor(&user.id)
+        .target(&user.id)
+        .detail("session_id", token.id.as_str())
+        .detail("device_id", client.device_id.as_str())
+        .detail("new_device", new_device)
+        .record(&*db)
+        .await;
+
+    if new_device {
+        let notification = Notification::NewDevice {
+            user_id: user.id.clone(),
+            username: user.username.clone(),
+            session_id: token.id.clone(),
+            device_id: client.device_id.clone(),
+            ip: client.ip,
+            user_agent: client.user_agent.clone(),
+            at: Utc::now()
+        };
+
+        // Sent in the background, a slow mail server or webhook mustn't hold up the login. The
+        // task stays in the request's span, so its log lines still carry the request id.
+        let user_id = user.id.clone();
+        tokio::spawn(async move {
+            if let Err(err) = notifier.notify(&notification).await {
+                tracing::error!(user_id = %user_id, error = %err, "failed to send new device notification");
+            }
+        }.in_current_span());
+    }
+
+    if options.cookie {
+        let cookie = SessionCookie::new(token.token);
+        let csrf_response = CsrfTokenResponse {
+            csrf_token: cookie.csrf_token.clone()
+        };
+        let cookies = cookie.set(jar, CookieJar::new());
+
+        return match format {
+            ResponseFormat::Html => (cookies, negotiate::html_page(StatusCode::OK, "Signed in", &format!("You are signed in as {}.", user.username))).into_response(),
+            ResponseFormat::Json | ResponseFormat::Unspecified => (cookies, Json(csrf_response)).into_response()
+        };
+    }
+
+    let token_response = TokenResponse {
+        token: token.token
+    };
+
+    return match format {
+        ResponseFormat::Html => negotiate::html_page(StatusCode::OK, "Signed in", &format!("Your session token is {}", token_response.token)),
+        ResponseFormat::Json | ResponseFormat::Unspecified => Json(token_response).into_response()
+    };
+}
+
+/// Takes JSON or a form. Without an `Accept` header it answers with a plain `Success`, like it
+/// always has.
+async fn register(State(db): State<DynStore>, State(config): State<Arc<Config>>, State(metrics): State<AppMetrics>, audit: AuditContext, format: ResponseFormat, JsonOrForm(data): JsonOrForm<LoginForm>) -> Response {
+    let user = match User::create(&data.username, &data.password, config.bcrypt_cost).await {
+        Ok(user) => user,
+        Err(_) => return format.error(StatusCode::INTERNAL_SERVER_ERROR, "Sign up failed", INTERNAL_ERROR_MESSAGE)
+    };
+
+    match user.add_to_database(&*db).await {
+        user::AddUserResult::Success => {
+            tracing::info!(target: AUTH, event = "user_registered", user_id = %user.id, username = %user.username);
+            metrics.registrations.inc();
+            audit.event(AuditKind::UserRegistered)
+                .actor(&user.id)
+                .target(&user.id)
+                .detail("username", user.username.as_str())
+                .record(&*db)
+                .await;
+
+            return match format {
+                ResponseFormat::Json => Json(UserProfile::from(&user)).into_response(),
+                ResponseFormat::Html => negotiate::html_page(StatusCode::OK, "Account created", &format!("You can now sign in as {}.", user.username)),
+                ResponseFormat::Unspecified => "Success".into_response()
+            };
+        },
+        user::AddUserResult::UsernameTaken => {
+            tracing::info!(target: AUTH, event = "registration_failed", username = %user.username, reason = "username_taken");
+            return format.error(StatusCode::UNAUTHORIZED, "Sign up failed", "That username is already taken.");
+        },
+        user::AddUserResult::DatabaseError => return format.error(StatusCode::INTERNAL_SERVER_ERROR, "Sign up failed", INTERNAL_ERROR_MESSAGE)
+    };
+}
+
+#[derive(Deserialize, Debug)]
+struct TokenInput {
+    token: String
+}
+
+/// Checks a token, answering with the profile of the user it belongs to. Tokens bound to a network
+/// only check out from within it, like everywhere else.
+async fn verify(State(db): State<DynStore>, client: ClientInfo, Json(data): Json<TokenInput>) -> std::result::Result<Json<UserProfile>, StatusCode> {
+    let (session, user) = match user::Session::get_token_session(&data.token, &*db).await {
+        user::GetTokenSessionResult::Success(session, user) => (session, user),
+        user::GetTokenSessionResult::NotFound => return Err(StatusCode::UNAUTHORIZED),
+        user::GetTokenSessionResult::Unauthorized => return Err(StatusCode::UNAUTHORIZED),
+        user::GetTokenSessionResult::DatabaseError => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    if !auth::allows_client(&session, &client) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    return Ok(Json(UserProfile::from(&user)));
+}
+
+/// Revokes the caller's session and clears the session cookie, if there is one.
+async fn logout(State(db): State<DynStore>, audit: AuditContext, private: PrivateCookieJar, plain: CookieJar, SessionToken(token): SessionToken) -> ((PrivateCookieJar, CookieJar), StatusCode) {
+    let jar = cookies::clear(private, plain);
+
+    let session = match db.get_session_by_token(&token).await {
+        Ok(Some(session)) if session.disabled == 0 => session,
+        Ok(_) => return (jar, StatusCode::UNAUTHORIZED),
+        Err(_) => return (jar, StatusCode::INTERNAL_SERVER_ERROR)
+    };
+
+    if let Err(err) = db.disable_session(&token).await {
+        tracing::error!(session_id = %session.id, error = %err, "failed to revoke session");
+        return (jar, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    tracing::info!(target: AUTH, event = "session_revoked", session_id = %session.id, user_id = %session.user_id, reason = "logout");
+    audit.event(AuditKind::SessionsRevoked)
+        .actor(&session.user_id)
+        .target(&session.user_id)
+        .detail("session_id", session.id.as_str())
+        .detail("count", 1)
+        .detail("reason", "logout")
+        .record(&*db)
+        .await;
+
+    return (jar, StatusCode::NO_CONTENT);
+}
@@ -0,0 +1,185 @@
+use std::{error::Error, fs::File, io::{self, BufReader}, path::Path, sync::Arc, time::Duration};
+use axum::serve::{Listener, ReloadingCertResolver, RustlsConfig, Serve, TlsConnectInfo};
+use rustls::{server::AllowAnyAnonymousOrAuthenticatedClient, Certificate, RootCertStore, ServerConfig};
+use tokio::sync::watch;
+
+use crate::{app, config::Config, jobs::{self, AccountDeleter, SessionPurger}, state::AppState, store};
+
+/// Connects to the store, binds the configured address (or takes over a socket passed in by systemd)
+/// and serves the app until it fails or the
+/// process is asked to stop with Ctrl-C or SIGTERM.
+///
+/// With `tls_cert_file` and `tls_key_file` set it serves HTTPS, and handlers can read the client's
+/// certificates through `ConnectInfo<TlsConnectInfo>` when `tls_client_ca_file` is set too.
+///
+/// Every connection is subject to the limits in the config (`max_connections`, the header read and
+/// idle timeouts and so on), so slow clients can't exhaust the server.
+///
+/// On shutdown `/readyz` starts failing, in-flight requests get `shutdown_timeout_secs` to finish,
+/// and background jobs are stopped before this returns.
+pub async fn serve(config: Config) -> Result<(), Box<dyn Error>> {
+    let db = store::connect(&config.database_url).await?;
+
+    let tls = tls_config(&config)?;
+
+    let listener = match inherited_listener()? {
+        Some(listener) => {
+            tracing::info!("using the socket passed in by systemd");
+            tokio::net::TcpListener::from_std(listener)?
+        },
+        None => tokio::net::TcpListener::bind(config.bind_addr).await?
+    };
+
+    tracing::info!(addr = %listener.local_addr()?, tls = tls.is_some(), "listening");
+
+    if config.cookie_key.is_none() {
+        tracing::warn!("no cookie_key_file set, cookie sessions and invitation links won't survive a restart");
+    }
+
+    let state = AppState::new(db.clone(), config.clone());
+    let shutdown_state = state.shutdown.clone();
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let signal_task = tokio::spawn(async move {
+        shutdown_signal().await;
+        tracing::info!("shutting down");
+        shutdown_state.begin();
+        let _ = shutdown_tx.send(true);
+    });
+
+    let purger = SessionPurger::new(db.clone(), config.purge_batch_size);
+    let purge_job = purger.spawn(Duration::from_secs(config.purge_interval_secs), shutdown_rx.clone());
+
+    let deleter = AccountDeleter::new(db.clone(), state.blobs.clone(), config.deletion_grace_days, config.purge_batch_size);
+    let deletion_job = deleter.spawn(Duration::from_secs(config.purge_interval_secs), shutdown_rx.clone());
+
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout_secs);
+    let app = app(state)
+        .into_make_service_with_connect_info::<TlsConnectInfo>();
+
+    let mut server_shutdown = shutdown_rx;
+    let shutdown = async move { jobs::wait_for_shutdown(&mut server_shutdown).await };
+
+    let result = match tls {
+        Some(tls) => with_limits(axum::serve::serve_tls(listener, tls, app), &config)
+            .with_graceful_shutdown(shutdown)
+            .with_shutdown_timeout(shutdown_timeout)
+            .await,
+        None => with_limits(axum::serve(listener, app), &config)
+            .with_graceful_shutdown(shutdown)
+            .with_shutdown_timeout(shutdown_timeout)
+            .await
+    };
+
+    // If the server stopped on its own, dropping the sender stops the jobs.
+    signal_task.abort();
+    let _ = purge_job.await;
+    let _ = deletion_job.await;
+
+    result?;
+
+    return Ok(());
+}
+
+/// Applies the connection limits from the config.
+fn with_limits<L, M, S>(serve: Serve<L, M, S>, config: &Config) -> Serve<L, M, S>
+where
+    L: Listener
+{
+    return serve
+        .with_max_connections(config.max_connections)
+        .with_header_read_timeout(Duration::from_secs(config.header_read_timeout_secs))
+        .with_idle_timeout(Duration::from_secs(config.idle_timeout_secs))
+        .with_max_header_size(config.max_header_size)
+        .with_max_requests_per_connection(config.max_requests_per_connection);
+}
+
+/// The listening socket systemd passed to this process through socket activation, if any.
+///
+/// systemd sets `LISTEN_PID` to our pid and `LISTEN_FDS` to the number of sockets, which start at
+/// file descriptor 3. Only the first one is used.
+#[cfg(unix)]
+fn inherited_listener() -> io::Result<Option<std::net::TcpListener>> {
+    use std::os::unix::io::FromRawFd;
+
+    const SD_LISTEN_FDS_START: i32 = 3;
+
+    let for_us = std::env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok()) == Some(std::process::id());
+    let fds = std::env::var("LISTEN_FDS").ok().and_then(|fds| fds.parse::<u32>().ok()).unwrap_or(0);
+
+    if !for_us || fds == 0 {
+        return Ok(None);
+    }
+
+    // Safety: systemd hands the descriptor over to us and nothing else in the process uses it.
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(SD_LISTEN_FDS_START) };
+    listener.set_nonblocking(true)?;
+
+    return Ok(Some(listener));
+}
+
+#[cfg(not(unix))]
+fn inherited_listener() -> io::Result<Option<std::net::TcpListener>> {
+    return Ok(None);
+}
+
+/// Builds the rustls config when TLS is configured. The certificate is reloaded from disk whenever
+/// its files change.
+fn tls_config(config: &Config) -> Result<Option<RustlsConfig>, Box<dyn Error>> {
+    let (cert_file, key_file) = match (&config.tls_cert_file, &config.tls_key_file) {
+        (Some(cert_file), Some(key_file)) => (cert_file, key_file),
+        _ => return Ok(None)
+    };
+
+    let resolver = Arc::new(ReloadingCertResolver::from_pem_file(cert_file, key_file)?);
+    let builder = ServerConfig::builder().with_safe_defaults();
+
+    let server_config = match &config.tls_client_ca_file {
+        Some(ca_file) => {
+            // Clients without a certificate can still use passwords and tokens.
+            let verifier = AllowAnyAnonymousOrAuthenticatedClient::new(load_roots(ca_file)?).boxed();
+            builder.with_client_cert_verifier(verifier).with_cert_resolver(resolver)
+        },
+        None => builder.with_no_client_auth().with_cert_resolver(resolver)
+    };
+
+    return Ok(Some(RustlsConfig::new(server_config)));
+}
+
+fn load_roots(path: &Path) -> Result<RootCertStore, Box<dyn Error>> {
+    let mut roots = RootCertStore::empty();
+
+    for der in rustls_pemfile::certs(&mut BufReader::new(File::open(path)?))? {
+        roots.add(&Certificate(der))?;
+    }
+
+    if roots.is_empty() {
+        return Err(format!("no CA certificates found in {}", path.display()).into());
+    }
+
+    return Ok(roots);
+}
+
+/// Completes on Ctrl-C, or SIGTERM on unix.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("failed to install Ctrl-C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {}
+    }
+}
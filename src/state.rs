@@ -0,0 +1,83 @@
+use std::sync::Arc;
+use axum::extract::FromRef;
+use axum_extra::extract::cookie::Key;
+
+use crate::{blob::{DynBlobStore, FsBlobStore}, config::Config, health::ShutdownState, metrics::AppMetrics, notify::{DynNotifier, LogNotifier}, store::DynStore};
+
+/// Everything the handlers share. Individual parts can be extracted with `State<T>`.
+#[derive(Clone)]
+pub struct AppState {
+    pub db: DynStore,
+    pub config: Arc<Config>,
+    pub metrics: AppMetrics,
+    pub shutdown: ShutdownState,
+    /// Encrypts the session cookies, see [`crate::cookies`].
+    pub cookie_key: Key,
+    /// Uploaded files, on the filesystem under `blob_dir` unless replaced.
+    pub blobs: DynBlobStore,
+    /// Reaches users about their account, only logs unless replaced.
+    pub notifier: DynNotifier
+}
+
+impl AppState {
+    pub fn new(db: DynStore, config: Config) -> Self {
+        let cookie_key = match &config.cookie_key {
+            Some(key) => Key::from(key.as_bytes()),
+            None => Key::generate()
+        };
+
+        let blobs = Arc::new(FsBlobStore::new(config.blob_dir.clone()));
+
+        return Self {
+            db,
+            config: Arc::new(config),
+            metrics: AppMetrics::new(),
+            shutdown: ShutdownState::default(),
+            cookie_key,
+            blobs,
+            notifier: Arc::new(LogNotifier)
+        };
+    }
+}
+
+impl FromRef<AppState> for DynStore {
+    fn from_ref(state: &AppState) -> Self {
+        return state.db.clone();
+    }
+}
+
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        return state.config.clone();
+    }
+}
+
+impl FromRef<AppState> for AppMetrics {
+    fn from_ref(state: &AppState) -> Self {
+        return state.metrics.clone();
+    }
+}
+
+impl FromRef<AppState> for ShutdownState {
+    fn from_ref(state: &AppState) -> Self {
+        return state.shutdown.clone();
+    }
+}
+
+impl FromRef<AppState> for Key {
+    fn from_ref(state: &AppState) -> Self {
+        return state.cookie_key.clone();
+    }
+}
+
+impl FromRef<AppState> for DynBlobStore {
+    fn from_ref(state: &AppState) -> Self {
+        return state.blobs.clone();
+    }
+}
+
+impl FromRef<AppState> for DynNotifier {
+    fn from_ref(state: &AppState) -> Self {
+        return state.notifier.clone();
+    }
+}
@@ -0,0 +1,459 @@
+use std::{collections::HashMap, sync::RwLock};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::{AuditStore, OrgStore, SessionStore, StoreHealth, UserStore};
+use crate::{
+    audit::{AuditEvent, AuditFilter, AuditScope, NewAuditEvent},
+    org::{AcceptInvitationResult, Invitation, Membership, OrgMember, OrgRole, Organization, UserOrg},
+    user::{AddUserResult, DeviceSeen, UpdateProfileResult, User, Session}
+};
+
+/// A store that keeps everything in process memory, for tests and ephemeral deployments.
+///
+/// Nothing is persisted and the data is lost when the store is dropped.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    users: RwLock<HashMap<String, User>>,
+    sessions: RwLock<HashMap<String, Session>>,
+    /// When each device was last seen, by user and device id.
+    known_devices: RwLock<HashMap<(String, String), DateTime<Utc>>>,
+    orgs: RwLock<HashMap<String, Organization>>,
+    /// By organization and user id.
+    memberships: RwLock<HashMap<(String, String), Membership>>,
+    invitations: RwLock<HashMap<String, Invitation>>,
+    /// In insertion order, so an event's id is its position plus one.
+    audit_events: RwLock<Vec<AuditEvent>>
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryStore {
+    async fn add_user(&self, user: &User) -> AddUserResult {
+        let mut users = self.users.write().unwrap();
+
+        // Checked under the write lock, which gives the same guarantee as the UNIQUE constraint.
+        if users.contains_key(&user.id) || users.values().any(|existing| existing.username == user.username) {
+            return AddUserResult::UsernameTaken;
+        }
+
+        users.insert(user.id.clone(), user.clone());
+
+        return AddUserResult::Success;
+    }
+
+    async fn get_user_by_id(&self, id: &str) -> Result<Option<User>, sqlx::Error> {
+        return Ok(self.users.read().unwrap().get(id).cloned());
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<User>, sqlx::Error> {
+        return Ok(self.users.read().unwrap().values().find(|user| user.username == username).cloned());
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>, sqlx::Error> {
+        let mut users: Vec<User> = self.users.read().unwrap().values().cloned().collect();
+        users.sort_by(|a, b| a.username.cmp(&b.username));
+
+        return Ok(users);
+    }
+
+    async fn update_password_hash(&self, id: &str, password_hash: &str) -> Result<bool, sqlx::Error> {
+        return Ok(match self.users.write().unwrap().get_mut(id) {
+            Some(user) => {
+                user.password_hash = password_hash.to_string();
+                true
+            },
+            None => false
+        });
+    }
+
+    async fn set_user_disabled(&self, id: &str, disabled: bool) -> Result<bool, sqlx::Error> {
+        return Ok(match self.users.write().unwrap().get_mut(id) {
+            Some(user) => {
+                user.disabled = disabled as i64;
+                true
+            },
+            None => false
+        });
+    }
+
+    async fn set_user_can_impersonate(&self, id: &str, allowed: bool) -> Result<bool, sqlx::Error> {
+        return Ok(match self.users.write().unwrap().get_mut(id) {
+            Some(user) => {
+                user.can_impersonate = allowed as i64;
+                true
+            },
+            None => false
+        });
+    }
+
+    async fn update_profile(&self, user: &User, expected_version: i64) -> UpdateProfileResult {
+        let mut users = self.users.write().unwrap();
+
+        if user.email.is_some() && users.values().any(|existing| existing.id != user.id && existing.email == user.email) {
+            return UpdateProfileResult::EmailTaken;
+        }
+
+        return match users.get_mut(&user.id) {
+            Some(stored) if stored.version == expected_version => {
+                stored.display_name = user.display_name.clone();
+                stored.email = user.email.clone();
+                stored.locale = user.locale.clone();
+                stored.timezone = user.timezone.clone();
+                stored.avatar_key = user.avatar_key.clone();
+                stored.version = user.version;
+                stored.updated_at = user.updated_at;
+                UpdateProfileResult::Success
+            },
+            _ => UpdateProfileResult::Conflict
+        };
+    }
+
+    async fn mark_user_deleted(&self, id: &str, deleted_at: Option<DateTime<Utc>>) -> Result<bool, sqlx::Error> {
+        return Ok(match self.users.write().unwrap().get_mut(id) {
+            Some(user) => {
+                user.deleted_at = deleted_at;
+                true
+            },
+            None => false
+        });
+    }
+
+    async fn list_users_deleted_before(&self, cutoff: DateTime<Utc>, limit: u32) -> Result<Vec<User>, sqlx::Error> {
+        let mut users: Vec<User> = self.users.read().unwrap().values()
+            .filter(|user| user.deleted_at.map_or(false, |deleted_at| deleted_at < cutoff))
+            .cloned()
+            .collect();
+        users.sort_by_key(|user| user.deleted_at);
+        users.truncate(limit as usize);
+
+        return Ok(users);
+    }
+
+    async fn delete_user(&self, id: &str) -> Result<bool, sqlx::Error> {
+        let mut users = self.users.write().unwrap();
+        if users.remove(id).is_none() {
+            return Ok(false);
+        }
+
+        // Mirror ON DELETE CASCADE on sessions.user_id, sessions.impersonator_id, known_devices.user_id and memberships.user_id.
+        self.sessions.write().unwrap().retain(|_, session| session.user_id != id && session.impersonator_id.as_deref() != Some(id));
+        self.known_devices.write().unwrap().retain(|(user_id, _), _| user_id != id);
+        self.memberships.write().unwrap().retain(|(_, user_id), _| user_id != id);
+
+        return Ok(true);
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn add_session(&self, session: &Session) -> Result<(), sqlx::Error> {
+        // Mirror the foreign key on sessions.user_id.
+        if !self.users.read().unwrap().contains_key(&session.user_id) {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        self.sessions.write().unwrap().insert(session.token.clone(), session.clone());
+
+        return Ok(());
+    }
+
+    async fn get_session_by_token(&self, token: &str) -> Result<Option<Session>, sqlx::Error> {
+        return Ok(self.sessions.read().unwrap().get(token).cloned());
+    }
+
+    async fn list_user_sessions(&self, user_id: &str) -> Result<Vec<Session>, sqlx::Error> {
+        let mut sessions: Vec<Session> = self.sessions.read().unwrap().values()
+            .filter(|session| session.user_id == user_id)
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.valid_to));
+
+        return Ok(sessions);
+    }
+
+    async fn set_session_org(&self, token: &str, org_id: Option<&str>) -> Result<bool, sqlx::Error> {
+        return Ok(match self.sessions.write().unwrap().get_mut(token) {
+            Some(session) => {
+                session.org_id = org_id.map(str::to_string);
+                true
+            },
+            None => false
+        });
+    }
+
+    async fn disable_session(&self, token: &str) -> Result<bool, sqlx::Error> {
+        return Ok(match self.sessions.write().unwrap().get_mut(token) {
+            Some(session) => {
+                session.disabled = 1;
+                true
+            },
+            None => false
+        });
+    }
+
+    async fn disable_user_sessions(&self, user_id: &str) -> Result<u64, sqlx::Error> {
+        let mut count = 0;
+
+        for session in self.sessions.write().unwrap().values_mut() {
+            if session.user_id == user_id && session.disabled == 0 {
+                session.disabled = 1;
+                count += 1;
+            }
+        }
+
+        return Ok(count);
+    }
+
+    async fn delete_expired_sessions(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let mut sessions = self.sessions.write().unwrap();
+        let before = sessions.len();
+
+        sessions.retain(|_, session| session.valid_to >= now);
+
+        return Ok((before - sessions.len()) as u64);
+    }
+
+    async fn delete_stale_sessions(&self, now: DateTime<Utc>, limit: u32) -> Result<u64, sqlx::Error> {
+        let mut sessions = self.sessions.write().unwrap();
+
+        let stale: Vec<String> = sessions.values()
+            .filter(|session| session.valid_to < now || session.disabled != 0)
+            .take(limit as usize)
+            .map(|session| session.token.clone())
+            .collect();
+
+        for token in &stale {
+            sessions.remove(token);
+        }
+
+        return Ok(stale.len() as u64);
+    }
+
+    async fn count_active_sessions(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let sessions = self.sessions.read().unwrap();
+
+        let count = sessions.values()
+            .filter(|session| session.valid_to >= now && session.disabled == 0)
+            .count();
+
+        return Ok(count as u64);
+    }
+
+    async fn remember_device(&self, user_id: &str, device_id: &str, seen_at: DateTime<Utc>) -> Result<DeviceSeen, sqlx::Error> {
+        let mut devices = self.known_devices.write().unwrap();
+        if let Some(last_seen_at) = devices.get_mut(&(user_id.to_string(), device_id.to_string())) {
+            *last_seen_at = seen_at;
+            return Ok(DeviceSeen::Known);
+        }
+
+        let first = !devices.keys().any(|(id, _)| id == user_id);
+        devices.insert((user_id.to_string(), device_id.to_string()), seen_at);
+
+        return Ok(if first { DeviceSeen::First } else { DeviceSeen::New });
+    }
+}
+
+#[async_trait]
+impl OrgStore for MemoryStore {
+    async fn add_org(&self, org: &Organization, owner: &Membership) -> Result<(), sqlx::Error> {
+        self.orgs.write().unwrap().insert(org.id.clone(), org.clone());
+        self.memberships.write().unwrap().insert((owner.org_id.clone(), owner.user_id.clone()), owner.clone());
+
+        return Ok(());
+    }
+
+    async fn get_org(&self, id: &str) -> Result<Option<Organization>, sqlx::Error> {
+        return Ok(self.orgs.read().unwrap().get(id).cloned());
+    }
+
+    async fn list_user_orgs(&self, user_id: &str) -> Result<Vec<UserOrg>, sqlx::Error> {
+        let orgs = self.orgs.read().unwrap();
+
+        let mut user_orgs: Vec<UserOrg> = self.memberships.read().unwrap().values()
+            .filter(|membership| membership.user_id == user_id)
+            .filter_map(|membership| Some(UserOrg {
+                org: orgs.get(&membership.org_id)?.clone(),
+                role: membership.role,
+                joined_at: membership.created_at
+            }))
+            .collect();
+        user_orgs.sort_by(|a, b| a.org.name.cmp(&b.org.name));
+
+        return Ok(user_orgs);
+    }
+
+    async fn get_membership(&self, org_id: &str, user_id: &str) -> Result<Option<Membership>, sqlx::Error> {
+        return Ok(self.memberships.read().unwrap().get(&(org_id.to_string(), user_id.to_string())).cloned());
+    }
+
+    async fn list_org_members(&self, org_id: &str) -> Result<Vec<OrgMember>, sqlx::Error> {
+        let users = self.users.read().unwrap();
+
+        let mut members: Vec<OrgMember> = self.memberships.read().unwrap().values()
+            .filter(|membership| membership.org_id == org_id)
+            .filter_map(|membership| Some(OrgMember {
+                user: users.get(&membership.user_id)?.clone(),
+                role: membership.role,
+                joined_at: membership.created_at
+            }))
+            .collect();
+        members.sort_by(|a, b| a.user.username.cmp(&b.user.username));
+
+        return Ok(members);
+    }
+
+    async fn set_member_role(&self, org_id: &str, user_id: &str, role: OrgRole) -> Result<bool, sqlx::Error> {
+        return Ok(match self.memberships.write().unwrap().get_mut(&(org_id.to_string(), user_id.to_string())) {
+            Some(membership) => {
+                membership.role = role;
+                true
+            },
+            None => false
+        });
+    }
+
+    async fn delete_membership(&self, org_id: &str, user_id: &str) -> Result<bool, sqlx::Error> {
+        if self.memberships.write().unwrap().remove(&(org_id.to_string(), user_id.to_string())).is_none() {
+            return Ok(false);
+        }
+
+        for session in self.sessions.write().unwrap().values_mut() {
+            if session.user_id == user_id && session.org_id.as_deref() == Some(org_id) {
+                session.org_id = None;
+            }
+        }
+
+        return Ok(true);
+    }
+
+    async fn add_invitation(&self, invitation: &Invitation) -> Result<(), sqlx::Error> {
+        self.invitations.write().unwrap().insert(invitation.id.clone(), invitation.clone());
+
+        return Ok(());
+    }
+
+    async fn get_invitation(&self, id: &str) -> Result<Option<Invitation>, sqlx::Error> {
+        return Ok(self.invitations.read().unwrap().get(id).cloned());
+    }
+
+    async fn list_pending_invitations(&self, org_id: &str) -> Result<Vec<Invitation>, sqlx::Error> {
+        let mut invitations: Vec<Invitation> = self.invitations.read().unwrap().values()
+            .filter(|invitation| invitation.org_id == org_id && invitation.accepted_at.is_none())
+            .cloned()
+            .collect();
+        invitations.sort_by_key(|invitation| std::cmp::Reverse(invitation.created_at));
+
+        return Ok(invitations);
+    }
+
+    async fn delete_invitation(&self, org_id: &str, id: &str) -> Result<bool, sqlx::Error> {
+        let mut invitations = self.invitations.write().unwrap();
+
+        return Ok(match invitations.get(id) {
+            Some(invitation) if invitation.org_id == org_id && invitation.accepted_at.is_none() => invitations.remove(id).is_some(),
+            _ => false
+        });
+    }
+
+    async fn accept_invitation(&self, invitation_id: &str, membership: &Membership) -> AcceptInvitationResult {
+        // Both locks are held throughout, which makes the two changes one.
+        let mut invitations = self.invitations.write().unwrap();
+        let mut memberships = self.memberships.write().unwrap();
+
+        let invitation = match invitations.get_mut(invitation_id) {
+            Some(invitation) if invitation.accepted_at.is_none() => invitation,
+            _ => return AcceptInvitationResult::Unavailable
+        };
+
+        let key = (membership.org_id.clone(), membership.user_id.clone());
+        if memberships.contains_key(&key) {
+            return AcceptInvitationResult::AlreadyMember;
+        }
+
+        invitation.accepted_by = Some(membership.user_id.clone());
+        invitation.accepted_at = Some(membership.created_at);
+        memberships.insert(key, membership.clone());
+
+        return AcceptInvitationResult::Success;
+    }
+}
+
+#[async_trait]
+impl AuditStore for MemoryStore {
+    async fn add_audit_event(&self, event: &NewAuditEvent) -> Result<(), sqlx::Error> {
+        let mut events = self.audit_events.write().unwrap();
+
+        let id = events.len() as i64 + 1;
+        events.push(AuditEvent {
+            id,
+            occurred_at: event.occurred_at,
+            kind: event.kind.as_str().to_string(),
+            actor_id: event.actor_id.clone(),
+            target_id: event.target_id.clone(),
+            org_id: event.org_id.clone(),
+            impersonator_id: event.impersonator_id.clone(),
+            ip: event.ip.clone(),
+            user_agent: event.user_agent.clone(),
+            request_id: event.request_id.clone(),
+            details: serde_json::Value::Object(event.details.clone()).to_string()
+        });
+
+        return Ok(());
+    }
+
+    async fn list_audit_events(&self, filter: &AuditFilter, limit: u32) -> Result<Vec<AuditEvent>, sqlx::Error> {
+        let members: Vec<String> = self.memberships.read().unwrap().keys()
+            .filter(|(org_id, _)| match &filter.scope {
+                AuditScope::All => false,
+                AuditScope::Tenant(tenant_id) => org_id == tenant_id,
+                AuditScope::Unowned => true
+            })
+            .map(|(_, user_id)| user_id.clone())
+            .collect();
+        let events = self.audit_events.read().unwrap();
+
+        return Ok(events.iter()
+            .rev()
+            .filter(|event| filter.matches(event, &members))
+            .take(limit as usize)
+            .cloned()
+            .collect());
+    }
+
+    async fn anonymize_audit_events(&self, user_id: &str) -> Result<u64, sqlx::Error> {
+        let mut events = self.audit_events.write().unwrap();
+        let mut count = 0;
+
+        for event in events.iter_mut() {
+            let involved = event.actor_id.as_deref() == Some(user_id) || event.target_id.as_deref() == Some(user_id);
+            if involved && (event.ip.is_some() || event.user_agent.is_some()) {
+                event.ip = None;
+                event.user_agent = None;
+                count += 1;
+            }
+        }
+
+        return Ok(count);
+    }
+}
+
+#[async_trait]
+impl StoreHealth for MemoryStore {
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        return Ok(());
+    }
+
+    async fn schema_version(&self) -> Result<Option<i64>, sqlx::Error> {
+        return Ok(None);
+    }
+
+    fn expected_schema_version(&self) -> Option<i64> {
+        return None;
+    }
+}
@@ -0,0 +1,185 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::{
+    audit::{AuditEvent, AuditFilter, NewAuditEvent},
+    org::{AcceptInvitationResult, Invitation, Membership, OrgMember, OrgRole, Organization, UserOrg},
+    user::{AddUserResult, DeviceSeen, UpdateProfileResult, User, Session}
+};
+
+pub mod memory;
+pub mod sqlite;
+pub mod postgres;
+
+pub use self::memory::MemoryStore;
+pub use self::sqlite::SqliteStore;
+pub use self::postgres::PostgresStore;
+
+/// Storage for user accounts.
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    async fn add_user(&self, user: &User) -> AddUserResult;
+
+    async fn get_user_by_id(&self, id: &str) -> Result<Option<User>, sqlx::Error>;
+
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<User>, sqlx::Error>;
+
+    /// All users, ordered by username.
+    async fn list_users(&self) -> Result<Vec<User>, sqlx::Error>;
+
+    /// Returns whether a user with that id existed.
+    async fn update_password_hash(&self, id: &str, password_hash: &str) -> Result<bool, sqlx::Error>;
+
+    /// Returns whether a user with that id existed.
+    async fn set_user_disabled(&self, id: &str, disabled: bool) -> Result<bool, sqlx::Error>;
+
+    /// Grants or takes away the permission to impersonate other users. Returns whether a user with
+    /// that id existed.
+    async fn set_user_can_impersonate(&self, id: &str, allowed: bool) -> Result<bool, sqlx::Error>;
+
+    /// Saves the profile fields of `user`, including its `version` and `updated_at`, but only if
+    /// the stored version is still `expected_version`.
+    async fn update_profile(&self, user: &User, expected_version: i64) -> UpdateProfileResult;
+
+    /// Sets or, with `None`, clears `deleted_at`. Returns whether a user with that id existed.
+    async fn mark_user_deleted(&self, id: &str, deleted_at: Option<DateTime<Utc>>) -> Result<bool, sqlx::Error>;
+
+    /// Up to `limit` users that were marked as deleted before `cutoff`, the longest ago first.
+    async fn list_users_deleted_before(&self, cutoff: DateTime<Utc>, limit: u32) -> Result<Vec<User>, sqlx::Error>;
+
+    /// Removes the user for good, along with their sessions. Returns whether they existed.
+    async fn delete_user(&self, id: &str) -> Result<bool, sqlx::Error>;
+}
+
+/// Storage for login sessions.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    async fn add_session(&self, session: &Session) -> Result<(), sqlx::Error>;
+
+    async fn get_session_by_token(&self, token: &str) -> Result<Option<Session>, sqlx::Error>;
+
+    /// Every session of the user, revoked and expired ones included, the longest valid first.
+    async fn list_user_sessions(&self, user_id: &str) -> Result<Vec<Session>, sqlx::Error>;
+
+    /// Sets or, with `None`, clears the organization the session acts in. Returns whether a
+    /// session with that token existed.
+    async fn set_session_org(&self, token: &str, org_id: Option<&str>) -> Result<bool, sqlx::Error>;
+
+    /// Marks the session as disabled. Returns whether a session with that token existed.
+    async fn disable_session(&self, token: &str) -> Result<bool, sqlx::Error>;
+
+    /// Marks every session of the user as disabled, returning how many were changed.
+    async fn disable_user_sessions(&self, user_id: &str) -> Result<u64, sqlx::Error>;
+
+    /// Deletes sessions that were valid until before `now`, returning how many were deleted.
+    async fn delete_expired_sessions(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+
+    /// Deletes up to `limit` sessions that are expired or disabled, returning how many were deleted.
+    async fn delete_stale_sessions(&self, now: DateTime<Utc>, limit: u32) -> Result<u64, sqlx::Error>;
+
+    /// Counts the sessions that are neither expired nor disabled.
+    async fn count_active_sessions(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+
+    /// Notes that the user logged in from the device at `seen_at`, saying whether they had before.
+    async fn remember_device(&self, user_id: &str, device_id: &str, seen_at: DateTime<Utc>) -> Result<DeviceSeen, sqlx::Error>;
+}
+
+/// Storage for organizations, their members and invitations. Everything but [`OrgStore::get_org`]
+/// and [`OrgStore::get_invitation`] is scoped to one organization or one user.
+#[async_trait]
+pub trait OrgStore: Send + Sync {
+    /// Adds the organization together with its first member, both or neither.
+    async fn add_org(&self, org: &Organization, owner: &Membership) -> Result<(), sqlx::Error>;
+
+    async fn get_org(&self, id: &str) -> Result<Option<Organization>, sqlx::Error>;
+
+    /// The organizations the user is a member of, ordered by name.
+    async fn list_user_orgs(&self, user_id: &str) -> Result<Vec<UserOrg>, sqlx::Error>;
+
+    async fn get_membership(&self, org_id: &str, user_id: &str) -> Result<Option<Membership>, sqlx::Error>;
+
+    /// The organization's members, ordered by username.
+    async fn list_org_members(&self, org_id: &str) -> Result<Vec<OrgMember>, sqlx::Error>;
+
+    /// Returns whether the user is a member.
+    async fn set_member_role(&self, org_id: &str, user_id: &str, role: OrgRole) -> Result<bool, sqlx::Error>;
+
+    /// Removes the user from the organization and takes it off their sessions. Returns whether
+    /// they were a member.
+    async fn delete_membership(&self, org_id: &str, user_id: &str) -> Result<bool, sqlx::Error>;
+
+    async fn add_invitation(&self, invitation: &Invitation) -> Result<(), sqlx::Error>;
+
+    async fn get_invitation(&self, id: &str) -> Result<Option<Invitation>, sqlx::Error>;
+
+    /// The organization's invitations nobody accepted yet, expired ones included, newest first.
+    async fn list_pending_invitations(&self, org_id: &str) -> Result<Vec<Invitation>, sqlx::Error>;
+
+    /// Revokes an invitation of the organization that nobody accepted yet. Returns whether there
+    /// was one.
+    async fn delete_invitation(&self, org_id: &str, id: &str) -> Result<bool, sqlx::Error>;
+
+    /// Marks the invitation as accepted by `membership.user_id` and adds the membership, both or
+    /// neither.
+    async fn accept_invitation(&self, invitation_id: &str, membership: &Membership) -> AcceptInvitationResult;
+}
+
+/// Storage for the audit log. Events are only ever added, never changed or deleted.
+#[async_trait]
+pub trait AuditStore: Send + Sync {
+    async fn add_audit_event(&self, event: &NewAuditEvent) -> Result<(), sqlx::Error>;
+
+    /// Up to `limit` events matching the filter, newest first.
+    async fn list_audit_events(&self, filter: &AuditFilter, limit: u32) -> Result<Vec<AuditEvent>, sqlx::Error>;
+
+    /// Clears the address and browser of every event the user did or that was done to them, the
+    /// one change the log allows. Returns how many events had either.
+    async fn anonymize_audit_events(&self, user_id: &str) -> Result<u64, sqlx::Error>;
+}
+
+/// Checks behind the readiness probe.
+#[async_trait]
+pub trait StoreHealth: Send + Sync {
+    /// Runs a trivial query to check the database answers.
+    async fn ping(&self) -> Result<(), sqlx::Error>;
+
+    /// The newest migration applied to the database, `None` if there is no schema to migrate.
+    async fn schema_version(&self) -> Result<Option<i64>, sqlx::Error>;
+
+    /// The newest migration this build ships with, `None` if there is no schema to migrate.
+    fn expected_schema_version(&self) -> Option<i64>;
+}
+
+/// A backend that stores users, sessions, organizations and the audit log.
+pub trait Store: UserStore + SessionStore + OrgStore + AuditStore + StoreHealth {}
+
+impl<T: UserStore + SessionStore + OrgStore + AuditStore + StoreHealth> Store for T {}
+
+/// The store shared between handlers.
+pub type DynStore = Arc<dyn Store>;
+
+/// Connects to the store named by `db_url` and applies any pending migrations.
+///
+/// `postgres://` and `postgresql://` URLs use Postgres, `memory://` keeps everything in process
+/// and anything else is treated as SQLite, including `sqlite::memory:`.
+pub async fn connect(db_url: &str) -> Result<DynStore, sqlx::Error> {
+    if db_url == "memory://" {
+        return Ok(Arc::new(MemoryStore::new()));
+    }
+
+    if db_url.starts_with("postgres://") || db_url.starts_with("postgresql://") {
+        return Ok(Arc::new(PostgresStore::connect(db_url).await?));
+    }
+
+    return Ok(Arc::new(SqliteStore::connect(db_url).await?));
+}
+
+/// Whether the error is a UNIQUE or PRIMARY KEY constraint failure.
+pub fn is_unique_violation(err: &sqlx::Error) -> bool {
+    return match err {
+        // SQLITE_CONSTRAINT_UNIQUE, SQLITE_CONSTRAINT_PRIMARYKEY and Postgres unique_violation
+        sqlx::Error::Database(err) => matches!(err.code().as_deref(), Some("2067") | Some("1555") | Some("23505")),
+        _ => false
+    };
+}
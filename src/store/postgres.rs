@@ -0,0 +1,511 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres, QueryBuilder, PgPool, migrate::{MigrateDatabase, Migrator}};
+
+use super::{is_unique_violation, AuditStore, OrgStore, SessionStore, StoreHealth, UserStore};
+use crate::{
+    audit::{AuditEvent, AuditFilter, AuditScope, NewAuditEvent},
+    org::{AcceptInvitationResult, Invitation, Membership, OrgMember, OrgRole, Organization, UserOrg},
+    user::{AddUserResult, DeviceSeen, UpdateProfileResult, User, Session}
+};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// A store backed by a Postgres database.
+#[derive(Clone, Debug)]
+pub struct PostgresStore {
+    pub db: Pool<Postgres>
+}
+
+impl PostgresStore {
+    /// Creates the database if needed, connects and runs the migrations.
+    pub async fn connect(db_url: &str) -> Result<Self, sqlx::Error> {
+        if !Postgres::database_exists(db_url).await? {
+            tracing::info!(db_url, "creating database");
+            Postgres::create_database(db_url).await?;
+        }
+
+        let db = PgPool::connect(db_url).await?;
+
+        MIGRATOR.run(&db).await?;
+
+        return Ok(Self { db });
+    }
+}
+
+#[async_trait]
+impl UserStore for PostgresStore {
+    async fn add_user(&self, user: &User) -> AddUserResult {
+        return match sqlx::query("INSERT INTO users (id, username, password_hash, disabled, is_admin, can_impersonate, display_name, email, locale, timezone, avatar_key, version, created_at, updated_at, deleted_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15);")
+            .bind(&user.id)
+            .bind(&user.username)
+            .bind(&user.password_hash)
+            .bind(user.disabled)
+            .bind(user.is_admin)
+            .bind(user.can_impersonate)
+            .bind(&user.display_name)
+            .bind(&user.email)
+            .bind(&user.locale)
+            .bind(&user.timezone)
+            .bind(&user.avatar_key)
+            .bind(user.version)
+            .bind(user.created_at)
+            .bind(user.updated_at)
+            .bind(user.deleted_at)
+            .execute(&self.db).await {
+            Ok(_) => AddUserResult::Success,
+            Err(err) if is_unique_violation(&err) => AddUserResult::UsernameTaken,
+            Err(_) => AddUserResult::DatabaseError
+        };
+    }
+
+    async fn get_user_by_id(&self, id: &str) -> Result<Option<User>, sqlx::Error> {
+        return sqlx::query_as::<_, User>("SELECT id, username, password_hash, disabled, is_admin, can_impersonate, display_name, email, locale, timezone, avatar_key, version, created_at, updated_at, deleted_at FROM users WHERE id = $1;")
+            .bind(id)
+            .fetch_optional(&self.db).await;
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<User>, sqlx::Error> {
+        return sqlx::query_as::<_, User>("SELECT id, username, password_hash, disabled, is_admin, can_impersonate, display_name, email, locale, timezone, avatar_key, version, created_at, updated_at, deleted_at FROM users WHERE username = $1;")
+            .bind(username)
+            .fetch_optional(&self.db).await;
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>, sqlx::Error> {
+        return sqlx::query_as::<_, User>("SELECT id, username, password_hash, disabled, is_admin, can_impersonate, display_name, email, locale, timezone, avatar_key, version, created_at, updated_at, deleted_at FROM users ORDER BY username;")
+            .fetch_all(&self.db).await;
+    }
+
+    async fn update_password_hash(&self, id: &str, password_hash: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2;")
+            .bind(password_hash)
+            .bind(id)
+            .execute(&self.db).await?;
+
+        return Ok(result.rows_affected() > 0);
+    }
+
+    async fn set_user_disabled(&self, id: &str, disabled: bool) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE users SET disabled = $1 WHERE id = $2;")
+            .bind(disabled as i64)
+            .bind(id)
+            .execute(&self.db).await?;
+
+        return Ok(result.rows_affected() > 0);
+    }
+
+    async fn set_user_can_impersonate(&self, id: &str, allowed: bool) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE users SET can_impersonate = $1 WHERE id = $2;")
+            .bind(allowed as i64)
+            .bind(id)
+            .execute(&self.db).await?;
+
+        return Ok(result.rows_affected() > 0);
+    }
+
+    async fn update_profile(&self, user: &User, expected_version: i64) -> UpdateProfileResult {
+        return match sqlx::query("UPDATE users SET display_name = $1, email = $2, locale = $3, timezone = $4, avatar_key = $5, version = $6, updated_at = $7 WHERE id = $8 AND version = $9;")
+            .bind(&user.display_name)
+            .bind(&user.email)
+            .bind(&user.locale)
+            .bind(&user.timezone)
+            .bind(&user.avatar_key)
+            .bind(user.version)
+            .bind(user.updated_at)
+            .bind(&user.id)
+            .bind(expected_version)
+            .execute(&self.db).await {
+            Ok(result) if result.rows_affected() > 0 => UpdateProfileResult::Success,
+            Ok(_) => UpdateProfileResult::Conflict,
+            Err(err) if is_unique_violation(&err) => UpdateProfileResult::EmailTaken,
+            Err(_) => UpdateProfileResult::DatabaseError
+        };
+    }
+
+    async fn mark_user_deleted(&self, id: &str, deleted_at: Option<DateTime<Utc>>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE users SET deleted_at = $1 WHERE id = $2;")
+            .bind(deleted_at)
+            .bind(id)
+            .execute(&self.db).await?;
+
+        return Ok(result.rows_affected() > 0);
+    }
+
+    async fn list_users_deleted_before(&self, cutoff: DateTime<Utc>, limit: u32) -> Result<Vec<User>, sqlx::Error> {
+        return sqlx::query_as::<_, User>("SELECT id, username, password_hash, disabled, is_admin, can_impersonate, display_name, email, locale, timezone, avatar_key, version, created_at, updated_at, deleted_at FROM users WHERE deleted_at < $1 ORDER BY deleted_at LIMIT $2;")
+            .bind(cutoff)
+            .bind(limit as i64)
+            .fetch_all(&self.db).await;
+    }
+
+    async fn delete_user(&self, id: &str) -> Result<bool, sqlx::Error> {
+        // Sessions go with it through their foreign key.
+        let result = sqlx::query("DELETE FROM users WHERE id = $1;")
+            .bind(id)
+            .execute(&self.db).await?;
+
+        return Ok(result.rows_affected() > 0);
+    }
+}
+
+#[async_trait]
+impl SessionStore for PostgresStore {
+    async fn add_session(&self, session: &Session) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO sessions (id, token, user_id, valid_to, disabled, org_id, impersonator_id, user_agent, ip, device_id, bound_range) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11);")
+            .bind(&session.id)
+            .bind(&session.token)
+            .bind(&session.user_id)
+            .bind(session.valid_to)
+            .bind(session.disabled)
+            .bind(&session.org_id)
+            .bind(&session.impersonator_id)
+            .bind(&session.user_agent)
+            .bind(&session.ip)
+            .bind(&session.device_id)
+            .bind(&session.bound_range)
+            .execute(&self.db).await?;
+
+        return Ok(());
+    }
+
+    async fn get_session_by_token(&self, token: &str) -> Result<Option<Session>, sqlx::Error> {
+        return sqlx::query_as::<_, Session>("SELECT id, token, user_id, valid_to, disabled, org_id, impersonator_id, user_agent, ip, device_id, bound_range FROM sessions WHERE token = $1;")
+            .bind(token)
+            .fetch_optional(&self.db).await;
+    }
+
+    async fn list_user_sessions(&self, user_id: &str) -> Result<Vec<Session>, sqlx::Error> {
+        return sqlx::query_as::<_, Session>("SELECT id, token, user_id, valid_to, disabled, org_id, impersonator_id, user_agent, ip, device_id, bound_range FROM sessions WHERE user_id = $1 ORDER BY valid_to DESC;")
+            .bind(user_id)
+            .fetch_all(&self.db).await;
+    }
+
+    async fn set_session_org(&self, token: &str, org_id: Option<&str>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE sessions SET org_id = $1 WHERE token = $2;")
+            .bind(org_id)
+            .bind(token)
+            .execute(&self.db).await?;
+
+        return Ok(result.rows_affected() > 0);
+    }
+
+    async fn disable_session(&self, token: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE sessions SET disabled = 1 WHERE token = $1;")
+            .bind(token)
+            .execute(&self.db).await?;
+
+        return Ok(result.rows_affected() > 0);
+    }
+
+    async fn disable_user_sessions(&self, user_id: &str) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("UPDATE sessions SET disabled = 1 WHERE user_id = $1 AND disabled = 0;")
+            .bind(user_id)
+            .execute(&self.db).await?;
+
+        return Ok(result.rows_affected());
+    }
+
+    async fn delete_expired_sessions(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM sessions WHERE valid_to < $1;")
+            .bind(now)
+            .execute(&self.db).await?;
+
+        return Ok(result.rows_affected());
+    }
+
+    async fn delete_stale_sessions(&self, now: DateTime<Utc>, limit: u32) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "DELETE FROM sessions WHERE id IN \
+            (SELECT id FROM sessions WHERE valid_to < $1 OR disabled <> 0 LIMIT $2);")
+            .bind(now)
+            .bind(limit as i64)
+            .execute(&self.db).await?;
+
+        return Ok(result.rows_affected());
+    }
+
+    async fn count_active_sessions(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sessions WHERE valid_to >= $1 AND disabled = 0;")
+            .bind(now)
+            .fetch_one(&self.db).await?;
+
+        return Ok(count as u64);
+    }
+
+    async fn remember_device(&self, user_id: &str, device_id: &str, seen_at: DateTime<Utc>) -> Result<DeviceSeen, sqlx::Error> {
+        let inserted = sqlx::query("INSERT INTO known_devices (user_id, device_id, first_seen_at, last_seen_at) VALUES ($1, $2, $3, $3) ON CONFLICT (user_id, device_id) DO NOTHING;")
+            .bind(user_id)
+            .bind(device_id)
+            .bind(seen_at)
+            .execute(&self.db).await?;
+
+        if inserted.rows_affected() == 0 {
+            sqlx::query("UPDATE known_devices SET last_seen_at = $1 WHERE user_id = $2 AND device_id = $3;")
+                .bind(seen_at)
+                .bind(user_id)
+                .bind(device_id)
+                .execute(&self.db).await?;
+
+            return Ok(DeviceSeen::Known);
+        }
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM known_devices WHERE user_id = $1;")
+            .bind(user_id)
+            .fetch_one(&self.db).await?;
+
+        return Ok(if count == 1 { DeviceSeen::First } else { DeviceSeen::New });
+    }
+}
+
+#[async_trait]
+impl OrgStore for PostgresStore {
+    async fn add_org(&self, org: &Organization, owner: &Membership) -> Result<(), sqlx::Error> {
+        let mut tx = self.db.begin().await?;
+
+        sqlx::query("INSERT INTO organizations (id, name, created_at) VALUES ($1, $2, $3);")
+            .bind(&org.id)
+            .bind(&org.name)
+            .bind(org.created_at)
+            .execute(&mut tx).await?;
+
+        sqlx::query("INSERT INTO memberships (org_id, user_id, role, created_at) VALUES ($1, $2, $3, $4);")
+            .bind(&owner.org_id)
+            .bind(&owner.user_id)
+            .bind(owner.role.as_str())
+            .bind(owner.created_at)
+            .execute(&mut tx).await?;
+
+        return tx.commit().await;
+    }
+
+    async fn get_org(&self, id: &str) -> Result<Option<Organization>, sqlx::Error> {
+        return sqlx::query_as::<_, Organization>("SELECT id, name, created_at FROM organizations WHERE id = $1;")
+            .bind(id)
+            .fetch_optional(&self.db).await;
+    }
+
+    async fn list_user_orgs(&self, user_id: &str) -> Result<Vec<UserOrg>, sqlx::Error> {
+        return sqlx::query_as::<_, UserOrg>(
+            "SELECT o.id, o.name, o.created_at, m.role, m.created_at AS joined_at \
+            FROM memberships m JOIN organizations o ON o.id = m.org_id WHERE m.user_id = $1 ORDER BY o.name;")
+            .bind(user_id)
+            .fetch_all(&self.db).await;
+    }
+
+    async fn get_membership(&self, org_id: &str, user_id: &str) -> Result<Option<Membership>, sqlx::Error> {
+        return sqlx::query_as::<_, Membership>("SELECT org_id, user_id, role, created_at FROM memberships WHERE org_id = $1 AND user_id = $2;")
+            .bind(org_id)
+            .bind(user_id)
+            .fetch_optional(&self.db).await;
+    }
+
+    async fn list_org_members(&self, org_id: &str) -> Result<Vec<OrgMember>, sqlx::Error> {
+        return sqlx::query_as::<_, OrgMember>(
+            "SELECT u.id, u.username, u.password_hash, u.disabled, u.is_admin, u.can_impersonate, u.display_name, u.email, u.locale, u.timezone, u.avatar_key, u.version, u.created_at, u.updated_at, u.deleted_at, \
+            m.role, m.created_at AS joined_at \
+            FROM memberships m JOIN users u ON u.id = m.user_id WHERE m.org_id = $1 ORDER BY u.username;")
+            .bind(org_id)
+            .fetch_all(&self.db).await;
+    }
+
+    async fn set_member_role(&self, org_id: &str, user_id: &str, role: OrgRole) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE memberships SET role = $1 WHERE org_id = $2 AND user_id = $3;")
+            .bind(role.as_str())
+            .bind(org_id)
+            .bind(user_id)
+            .execute(&self.db).await?;
+
+        return Ok(result.rows_affected() > 0);
+    }
+
+    async fn delete_membership(&self, org_id: &str, user_id: &str) -> Result<bool, sqlx::Error> {
+        let mut tx = self.db.begin().await?;
+
+        sqlx::query("UPDATE sessions SET org_id = NULL WHERE org_id = $1 AND user_id = $2;")
+            .bind(org_id)
+            .bind(user_id)
+            .execute(&mut tx).await?;
+
+        let result = sqlx::query("DELETE FROM memberships WHERE org_id = $1 AND user_id = $2;")
+            .bind(org_id)
+            .bind(user_id)
+            .execute(&mut tx).await?;
+
+        tx.commit().await?;
+
+        return Ok(result.rows_affected() > 0);
+    }
+
+    async fn add_invitation(&self, invitation: &Invitation) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO invitations (id, org_id, role, email, created_by, created_at, expires_at, accepted_by, accepted_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9);")
+            .bind(&invitation.id)
+            .bind(&invitation.org_id)
+            .bind(invitation.role.as_str())
+            .bind(&invitation.email)
+            .bind(&invitation.created_by)
+            .bind(invitation.created_at)
+            .bind(invitation.expires_at)
+            .bind(&invitation.accepted_by)
+            .bind(invitation.accepted_at)
+            .execute(&self.db).await?;
+
+        return Ok(());
+    }
+
+    async fn get_invitation(&self, id: &str) -> Result<Option<Invitation>, sqlx::Error> {
+        return sqlx::query_as::<_, Invitation>("SELECT id, org_id, role, email, created_by, created_at, expires_at, accepted_by, accepted_at FROM invitations WHERE id = $1;")
+            .bind(id)
+            .fetch_optional(&self.db).await;
+    }
+
+    async fn list_pending_invitations(&self, org_id: &str) -> Result<Vec<Invitation>, sqlx::Error> {
+        return sqlx::query_as::<_, Invitation>("SELECT id, org_id, role, email, created_by, created_at, expires_at, accepted_by, accepted_at FROM invitations WHERE org_id = $1 AND accepted_at IS NULL ORDER BY created_at DESC;")
+            .bind(org_id)
+            .fetch_all(&self.db).await;
+    }
+
+    async fn delete_invitation(&self, org_id: &str, id: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM invitations WHERE org_id = $1 AND id = $2 AND accepted_at IS NULL;")
+            .bind(org_id)
+            .bind(id)
+            .execute(&self.db).await?;
+
+        return Ok(result.rows_affected() > 0);
+    }
+
+    async fn accept_invitation(&self, invitation_id: &str, membership: &Membership) -> AcceptInvitationResult {
+        let mut tx = match self.db.begin().await {
+            Ok(tx) => tx,
+            Err(_) => return AcceptInvitationResult::DatabaseError
+        };
+
+        // Claimed first, so two people can't both use the same invitation.
+        match sqlx::query("UPDATE invitations SET accepted_by = $1, accepted_at = $2 WHERE id = $3 AND accepted_at IS NULL;")
+            .bind(&membership.user_id)
+            .bind(membership.created_at)
+            .bind(invitation_id)
+            .execute(&mut tx).await {
+            Ok(result) if result.rows_affected() > 0 => {},
+            Ok(_) => return AcceptInvitationResult::Unavailable,
+            Err(_) => return AcceptInvitationResult::DatabaseError
+        }
+
+        // Dropping the transaction rolls the claim back, the invitation stays usable.
+        match sqlx::query("INSERT INTO memberships (org_id, user_id, role, created_at) VALUES ($1, $2, $3, $4);")
+            .bind(&membership.org_id)
+            .bind(&membership.user_id)
+            .bind(membership.role.as_str())
+            .bind(membership.created_at)
+            .execute(&mut tx).await {
+            Ok(_) => {},
+            Err(err) if is_unique_violation(&err) => return AcceptInvitationResult::AlreadyMember,
+            Err(_) => return AcceptInvitationResult::DatabaseError
+        }
+
+        return match tx.commit().await {
+            Ok(_) => AcceptInvitationResult::Success,
+            Err(_) => AcceptInvitationResult::DatabaseError
+        };
+    }
+}
+
+#[async_trait]
+impl AuditStore for PostgresStore {
+    async fn add_audit_event(&self, event: &NewAuditEvent) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO audit_events (occurred_at, kind, actor_id, target_id, org_id, impersonator_id, ip, user_agent, request_id, details) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10);")
+            .bind(event.occurred_at)
+            .bind(event.kind.as_str())
+            .bind(&event.actor_id)
+            .bind(&event.target_id)
+            .bind(&event.org_id)
+            .bind(&event.impersonator_id)
+            .bind(&event.ip)
+            .bind(&event.user_agent)
+            .bind(&event.request_id)
+            .bind(serde_json::Value::Object(event.details.clone()).to_string())
+            .execute(&self.db).await?;
+
+        return Ok(());
+    }
+
+    async fn list_audit_events(&self, filter: &AuditFilter, limit: u32) -> Result<Vec<AuditEvent>, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(
+            "SELECT id, occurred_at, kind, actor_id, target_id, org_id, impersonator_id, ip, user_agent, request_id, details \
+            FROM audit_events WHERE 1 = 1");
+
+        if let Some(kind) = &filter.kind {
+            query.push(" AND kind = ").push_bind(kind);
+        }
+        if let Some(actor_id) = &filter.actor_id {
+            query.push(" AND actor_id = ").push_bind(actor_id);
+        }
+        if let Some(target_id) = &filter.target_id {
+            query.push(" AND target_id = ").push_bind(target_id);
+        }
+        if let Some(user_id) = &filter.user_id {
+            query.push(" AND (actor_id = ").push_bind(user_id).push(" OR target_id = ").push_bind(user_id).push(")");
+        }
+        if let Some(org_id) = &filter.org_id {
+            query.push(" AND org_id = ").push_bind(org_id);
+        }
+        match &filter.scope {
+            AuditScope::All => {},
+            AuditScope::Tenant(org_id) => {
+                query.push(" AND (org_id = ").push_bind(org_id)
+                    .push(" OR (org_id IS NULL AND EXISTS (SELECT 1 FROM memberships WHERE memberships.org_id = ").push_bind(org_id)
+                    .push(" AND memberships.user_id IN (audit_events.actor_id, audit_events.target_id))))");
+            },
+            AuditScope::Unowned => {
+                query.push(" AND org_id IS NULL AND NOT EXISTS (SELECT 1 FROM memberships WHERE memberships.user_id IN (audit_events.actor_id, audit_events.target_id))");
+            }
+        }
+        if let Some(impersonator_id) = &filter.impersonator_id {
+            query.push(" AND impersonator_id = ").push_bind(impersonator_id);
+        }
+        if let Some(ip) = &filter.ip {
+            query.push(" AND ip = ").push_bind(ip);
+        }
+        if let Some(since) = filter.since {
+            query.push(" AND occurred_at >= ").push_bind(since);
+        }
+        if let Some(until) = filter.until {
+            query.push(" AND occurred_at < ").push_bind(until);
+        }
+        if let Some(before) = filter.before {
+            query.push(" AND id < ").push_bind(before);
+        }
+
+        query.push(" ORDER BY id DESC LIMIT ").push_bind(limit as i64);
+
+        return query.build_query_as::<AuditEvent>().fetch_all(&self.db).await;
+    }
+
+    async fn anonymize_audit_events(&self, user_id: &str) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("UPDATE audit_events SET ip = NULL, user_agent = NULL \
+            WHERE (actor_id = $1 OR target_id = $1) AND (ip IS NOT NULL OR user_agent IS NOT NULL);")
+            .bind(user_id)
+            .execute(&self.db).await?;
+
+        return Ok(result.rows_affected());
+    }
+}
+
+#[async_trait]
+impl StoreHealth for PostgresStore {
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT 1;").execute(&self.db).await?;
+
+        return Ok(());
+    }
+
+    async fn schema_version(&self) -> Result<Option<i64>, sqlx::Error> {
+        return sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success;")
+            .fetch_one(&self.db).await;
+    }
+
+    fn expected_schema_version(&self) -> Option<i64> {
+        return MIGRATOR.iter().map(|migration| migration.version).max();
+    }
+}
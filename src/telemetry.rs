@@ -0,0 +1,109 @@
+use std::{convert::Infallible, time::Instant};
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, MatchedPath, Request},
+    http::{request::Parts, HeaderValue},
+    middleware::Next,
+    response::Response
+};
+use tracing::Instrument;
+use tracing_subscriber::EnvFilter;
+use uuid::Uuid;
+
+use crate::config::LogFormat;
+
+/// Target of the structured authentication events (logins, lockouts, revoked sessions), so they
+/// can be filtered on their own, e.g. `RUST_LOG=info,auth=debug`.
+pub const AUTH: &str = "auth";
+
+/// Header carrying the request id, both on the request and the response.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest request id accepted from a client, anything longer is replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Installs the global subscriber, writing to stderr so command output on stdout stays clean.
+///
+/// Which events are kept is read from `RUST_LOG` and defaults to `info`. Does nothing if a
+/// subscriber is already installed.
+pub fn init(format: LogFormat) {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::new("info")
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+
+    let _ = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().flatten_event(true).with_current_span(true).try_init()
+    };
+}
+
+/// The id of the request being handled, see [`trace_request`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequestId
+where
+    S: Send + Sync
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        return match parts.extensions.get::<RequestId>() {
+            Some(id) => Ok(id.clone()),
+            // Only happens without the middleware, e.g. in handler unit tests.
+            None => Ok(RequestId(Uuid::new_v4().to_string()))
+        };
+    }
+}
+
+/// Middleware that gives every request an id and runs it in a `request` span.
+///
+/// The id is taken from the `X-Request-Id` header when a proxy in front already set one, or
+/// generated otherwise, and sent back in the response. The span records the method and the
+/// matched route (`/login`, not the raw path), and a `finished request` event with the status
+/// and latency is logged when the response is ready.
+pub async fn trace_request(mut req: Request, next: Next) -> Response {
+    let request_id = match req.headers().get(REQUEST_ID_HEADER).and_then(|value| value.to_str().ok()) {
+        Some(id) if is_valid_request_id(id) => id.to_string(),
+        _ => Uuid::new_v4().to_string()
+    };
+
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+
+    let route = match req.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => "unmatched".to_string()
+    };
+
+    let span = tracing::info_span!("request", request_id = %request_id, method = %req.method(), route = %route);
+
+    let started = Instant::now();
+    let mut response = next.run(req).instrument(span.clone()).await;
+
+    tracing::info!(
+        parent: &span,
+        status = response.status().as_u16(),
+        latency_ms = started.elapsed().as_millis() as u64,
+        "finished request"
+    );
+
+    // Accepted and generated ids are both plain ASCII, so this doesn't fail in practice.
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    return response;
+}
+
+/// Ids from clients end up in logs, so only short ones made of harmless characters are kept.
+fn is_valid_request_id(id: &str) -> bool {
+    return !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+}
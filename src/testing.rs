@@ -0,0 +1,93 @@
+//! Helpers for building the whole application in tests.
+//!
+//! Only compiled for tests and with the `testing` feature.
+
+use std::{path::PathBuf, sync::Arc};
+use axum::Router;
+use chrono::{Duration, Utc};
+
+use crate::{app, blob::MemoryBlobStore, config::Config, org::{AcceptInvitationResult, Invitation, Membership, OrgRole, Organization}, state::AppState, store::{DynStore, MemoryStore, SqliteStore, Store}};
+
+/// Default config with the cheapest bcrypt cost, so tests don't spend their time hashing.
+pub fn test_config() -> Config {
+    return Config {
+        bcrypt_cost: 4,
+        ..Config::default()
+    };
+}
+
+/// State with the [`test_config`] and uploads kept in a [`MemoryBlobStore`] instead of on disk.
+pub fn test_state(db: DynStore) -> AppState {
+    let mut state = AppState::new(db, test_config());
+    state.blobs = Arc::new(MemoryBlobStore::new());
+
+    return state;
+}
+
+/// Builds the router against a fresh [`MemoryStore`], returning the store so tests can inspect it.
+pub fn memory_app() -> (Router, Arc<MemoryStore>) {
+    let store = Arc::new(MemoryStore::new());
+
+    return (app(test_state(store.clone())), store);
+}
+
+/// Builds the router against a fresh, migrated SQLite `:memory:` database.
+pub async fn sqlite_memory_app() -> (Router, Arc<SqliteStore>) {
+    let store = Arc::new(SqliteStore::connect("sqlite::memory:").await.unwrap());
+
+    return (app(test_state(store.clone())), store);
+}
+
+/// A migrated SQLite database in its own file under the temp directory, deleted again on drop.
+///
+/// Unlike `:memory:` this goes through the same file handling as a deployment.
+pub struct TempDatabase {
+    pub store: Arc<SqliteStore>,
+    path: PathBuf
+}
+
+impl TempDatabase {
+    pub async fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("axum-user-test-{}.db", uuid::Uuid::new_v4()));
+        let store = Arc::new(SqliteStore::connect(path.to_str().unwrap()).await.unwrap());
+
+        return Self { store, path };
+    }
+}
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            let mut path = self.path.clone().into_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Builds the router against a [`TempDatabase`] of its own. Keep the database alive for as long
+/// as the router is used.
+pub async fn temp_sqlite_app() -> (Router, TempDatabase) {
+    let db = TempDatabase::new().await;
+
+    return (app(test_state(db.store.clone())), db);
+}
+
+/// Creates an organization owned by `user_id` and makes it the one the session `token` acts in,
+/// which is what `/admin` needs besides `is_admin`.
+pub async fn active_org(db: &dyn Store, user_id: &str, token: &str) -> Organization {
+    let org = Organization::new("Operators");
+    db.add_org(&org, &Membership::new(&org.id, user_id, OrgRole::Owner)).await.unwrap();
+    assert!(db.set_session_org(token, Some(&org.id)).await.unwrap());
+
+    return org;
+}
+
+/// Adds `user_id` to the organization with `role`, the way accepting an invitation does.
+pub async fn add_member(db: &dyn Store, org_id: &str, user_id: &str, role: OrgRole) {
+    let invitation = Invitation::new(org_id, role, None, user_id, Utc::now() + Duration::hours(1));
+    db.add_invitation(&invitation).await.unwrap();
+
+    let result = db.accept_invitation(&invitation.id, &Membership::new(org_id, user_id, role)).await;
+    assert_eq!(result, AcceptInvitationResult::Success);
+}
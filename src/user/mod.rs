@@ -1,69 +1,166 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
 use serde::Serialize;
-use sqlx::{Pool, Sqlite, sqlite::SqliteQueryResult, FromRow, migrate::MigrateDatabase};
+use sqlx::FromRow;
 use uuid::Uuid;
 use bcrypt::{DEFAULT_COST, hash, BcryptError};
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 use rand::{self,  Rng};
 
+use crate::{client::ClientInfo, store::Store, telemetry::AUTH};
+
+#[derive(Debug, PartialEq)]
 pub enum AddUserResult {
     Success,
     UsernameTaken,
     DatabaseError
 }
 
-#[derive(FromRow, Debug, Serialize)]
+/// Whether a user had logged in from a device before, see [`crate::store::SessionStore::remember_device`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceSeen {
+    /// The user's first device, nothing to compare it with.
+    First,
+    /// A device the user hadn't logged in from before.
+    New,
+    Known
+}
+
+/// bcrypt jobs waiting for or running on the blocking thread pool.
+static BCRYPT_QUEUE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// How long a new session stays valid.
+pub const SESSION_LIFETIME_DAYS: i64 = 7;
+
+#[derive(Debug, PartialEq)]
+pub enum UpdateProfileResult {
+    Success,
+    /// The user is gone or their profile changed since it was read.
+    Conflict,
+    EmailTaken,
+    DatabaseError
+}
+
+#[derive(Debug, PartialEq)]
+pub enum UpdateUserResult {
+    Success,
+    NotFound,
+    DatabaseError
+}
+
+#[derive(FromRow, Debug, Clone, Serialize)]
 pub struct User {
     pub id: String,
     pub username: String,
-    pub password_hash: String
+    #[serde(skip_serializing)]
+    pub password_hash: String,
+    pub disabled: i64,
+    pub is_admin: i64,
+    /// Whether the admin may act as other users, see [`crate::impersonation`].
+    pub can_impersonate: i64,
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+    /// A BCP 47 language tag, e.g. `en-GB`.
+    pub locale: Option<String>,
+    /// An IANA time zone name, e.g. `Europe/Berlin`.
+    pub timezone: Option<String>,
+    /// Where the avatar is in the blob store, see [`crate::blob`].
+    pub avatar_key: Option<String>,
+    /// Goes up with every profile change, see [`crate::store::UserStore::update_profile`].
+    pub version: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// When the owner deleted the account. It's locked from then on and removed for good once the
+    /// grace period is over, see [`crate::jobs::AccountDeleter`].
+    pub deleted_at: Option<DateTime<Utc>>
 }
 
 impl User {
    pub fn new(username:&String, password:&String) -> Result<User, BcryptError>{
-        let hash: String = match hash(password, DEFAULT_COST) {
+        return Self::with_cost(username, password, DEFAULT_COST);
+    }
+
+    pub fn with_cost(username:&String, password:&String, cost:u32) -> Result<User, BcryptError>{
+        let hash: String = match hash(password, cost) {
             Ok(result) => result,
             Err(err) => return Err(err)
         };
 
+        let now = Utc::now();
+
         return Ok(User {
             username: username.clone(),
             id: Uuid::new_v4().to_string(),
-            password_hash: hash 
+            password_hash: hash,
+            disabled: 0,
+            is_admin: 0,
+            can_impersonate: 0,
+            display_name: None,
+            email: None,
+            locale: None,
+            timezone: None,
+            avatar_key: None,
+            version: 1,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None
         });
     }
 
-    pub async fn add_to_database(&self, db: &Pool<Sqlite>) -> AddUserResult {
-        let existing_user = sqlx::query!("SELECT * FROM users WHERE username = ?;", self.username).fetch_optional(db).await;
+    /// Like [`User::with_cost`], but hashes the password on the blocking thread pool.
+    pub async fn create(username:&String, password:&String, cost:u32) -> Result<User, BcryptError> {
+        let (username, password) = (username.clone(), password.clone());
 
-        match existing_user {
-            Ok(Some(_)) => return AddUserResult::UsernameTaken,
-            Ok(None) => (),
-            Err(_) => return AddUserResult::DatabaseError 
-        };
+        return run_bcrypt(move || Self::with_cost(&username, &password, cost)).await;
+    }
 
-        match sqlx::query!(
-            "INSERT INTO users (id, username, password_hash) VALUES (?, ?, ?);", 
-            self.id, self.username, self.password_hash).execute(db).await {
-            Ok(_) => return AddUserResult::Success,
-            Err(_) => return AddUserResult::DatabaseError
-        }
+    pub async fn add_to_database(&self, db: &dyn Store) -> AddUserResult {
+        return db.add_user(self).await;
     }
 }
 
-pub async fn init_database(db_url:&String) {
-    if !Sqlite::database_exists(db_url).await.unwrap_or(false) {
-        println!("Creating database {}", db_url);
-        match Sqlite::create_database(db_url).await {
-            Ok(_) => println!("Created database {}", db_url),
-            Err(error) => panic!("Failed to create database with error: {}", error)
-        }
-    } else {
-        println!("Database {} already exists", db_url);
+/// How many password hashes are being computed or waiting for a thread right now.
+pub fn bcrypt_queue_depth() -> usize {
+    return BCRYPT_QUEUE_DEPTH.load(Ordering::Relaxed);
+}
+
+/// Runs a bcrypt operation on the blocking thread pool, so a slow hash doesn't hold up every other
+/// request on the same worker thread.
+async fn run_bcrypt<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static
+{
+    // Owned by the job itself, so the depth stays right when the request is dropped mid-hash.
+    let queued = QueuedBcrypt::new();
+
+    let result = tokio::task::spawn_blocking(move || {
+        let _queued = queued;
+        f()
+    }).await;
+
+    return match result {
+        Ok(value) => value,
+        Err(err) => std::panic::resume_unwind(err.into_panic())
+    };
+}
+
+struct QueuedBcrypt;
+
+impl QueuedBcrypt {
+    fn new() -> Self {
+        BCRYPT_QUEUE_DEPTH.fetch_add(1, Ordering::Relaxed);
+        return Self;
+    }
+}
+
+impl Drop for QueuedBcrypt {
+    fn drop(&mut self) {
+        BCRYPT_QUEUE_DEPTH.fetch_sub(1, Ordering::Relaxed);
     }
 }
 
-pub async fn register_user(username:&String, password:&String, db:&Pool<Sqlite>) -> AddUserResult{
-    let new_user:User = match User::new(&username, &password) {
+pub async fn register_user(username:&String, password:&String, cost:u32, db:&dyn Store) -> AddUserResult{
+    let new_user:User = match User::create(username, password, cost).await {
         Ok(res) => res,
         Err(_) => return AddUserResult::DatabaseError
     };
@@ -71,18 +168,162 @@ pub async fn register_user(username:&String, password:&String, db:&Pool<Sqlite>)
     return new_user.add_to_database(db).await;
 } 
 
-pub async fn login_user(username:&String, password:&String, db: &Pool<Sqlite>) -> Option<User> {
-    let user:User = match sqlx::query_as!(User, "SELECT * FROM users WHERE username = ?;", username).fetch_one(db).await {
+/// Why [`authenticate`] turned a login down.
+#[derive(Debug, PartialEq)]
+pub enum LoginError {
+    UnknownUser,
+    /// The account is disabled, holds its id.
+    AccountLocked(String),
+    /// Holds the id of the account.
+    WrongPassword(String),
+    /// The owner deleted the account, holds its id.
+    AccountDeleted(String),
+    DatabaseError
+}
+
+impl LoginError {
+    /// The `reason` logged and audited for the failure.
+    pub fn reason(&self) -> &'static str {
+        return match self {
+            LoginError::UnknownUser => "unknown_user",
+            LoginError::AccountLocked(_) => "account_locked",
+            LoginError::WrongPassword(_) => "wrong_password",
+            LoginError::AccountDeleted(_) => "account_deleted",
+            LoginError::DatabaseError => "database_error"
+        };
+    }
+
+    /// The account the login was for, if it exists.
+    pub fn user_id(&self) -> Option<&str> {
+        return match self {
+            LoginError::AccountLocked(id) | LoginError::WrongPassword(id) | LoginError::AccountDeleted(id) => Some(id),
+            LoginError::UnknownUser | LoginError::DatabaseError => None
+        };
+    }
+}
+
+/// Checks the credentials, logging the outcome as an `auth` event.
+pub async fn login_user(username:&String, password:&String, db: &dyn Store) -> Option<User> {
+    return authenticate(username, password, db).await.ok();
+}
+
+/// Like [`login_user`], but says why the login failed.
+pub async fn authenticate(username:&String, password:&String, db: &dyn Store) -> Result<User, LoginError> {
+    let user:User = match db.get_user_by_username(username).await {
+        Ok(Some(result)) => result,
+        Ok(None) => {
+            tracing::info!(target: AUTH, event = "login_failed", username = %username, reason = "unknown_user");
+            return Err(LoginError::UnknownUser);
+        },
+        Err(err) => {
+            tracing::error!(target: AUTH, event = "login_failed", username = %username, reason = "database_error", error = %err);
+            return Err(LoginError::DatabaseError);
+        }
+    };
+
+    if user.disabled != 0 {
+        tracing::warn!(target: AUTH, event = "login_failed", user_id = %user.id, username = %username, reason = "account_locked");
+        return Err(LoginError::AccountLocked(user.id));
+    }
+
+    if user.deleted_at.is_some() {
+        tracing::warn!(target: AUTH, event = "login_failed", user_id = %user.id, username = %username, reason = "account_deleted");
+        return Err(LoginError::AccountDeleted(user.id));
+    }
+
+    if !verify_password(&user, password).await {
+        tracing::warn!(target: AUTH, event = "login_failed", user_id = %user.id, username = %username, reason = "wrong_password");
+        return Err(LoginError::WrongPassword(user.id));
+    }
+
+    tracing::info!(target: AUTH, event = "login_succeeded", user_id = %user.id, username = %username);
+    return Ok(user);
+}
+
+/// Whether `password` is the user's, e.g. to confirm a destructive action.
+pub async fn verify_password(user: &User, password: &String) -> bool {
+    let (password, password_hash) = (password.clone(), user.password_hash.clone());
+
+    return match run_bcrypt(move || bcrypt::verify(password, &password_hash)).await {
+        Ok(valid) => valid,
+        Err(_) => false
+    };
+}
+
+/// Replaces the user's password and revokes their existing sessions.
+pub async fn set_password(username:&String, password:&String, cost:u32, db:&dyn Store) -> UpdateUserResult {
+    let user:User = match db.get_user_by_username(username).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return UpdateUserResult::NotFound,
+        Err(_) => return UpdateUserResult::DatabaseError
+    };
+
+    let password = password.clone();
+    let hash: String = match run_bcrypt(move || hash(password, cost)).await {
         Ok(result) => result,
-        Err(_) => return None
+        Err(_) => return UpdateUserResult::DatabaseError
+    };
+
+    if db.update_password_hash(&user.id, &hash).await.is_err() {
+        return UpdateUserResult::DatabaseError;
+    }
+
+    tracing::info!(target: AUTH, event = "password_changed", user_id = %user.id, username = %username);
+
+    return match db.disable_user_sessions(&user.id).await {
+        Ok(count) => {
+            tracing::info!(target: AUTH, event = "sessions_revoked", user_id = %user.id, count, reason = "password_changed");
+            UpdateUserResult::Success
+        },
+        Err(_) => UpdateUserResult::DatabaseError
+    };
+}
+
+/// Disables the account so it can't log in and revokes its existing sessions.
+pub async fn disable_user(username:&String, db:&dyn Store) -> UpdateUserResult {
+    let user:User = match db.get_user_by_username(username).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return UpdateUserResult::NotFound,
+        Err(_) => return UpdateUserResult::DatabaseError
+    };
+
+    if db.set_user_disabled(&user.id, true).await.is_err() {
+        return UpdateUserResult::DatabaseError;
+    }
+
+    tracing::warn!(target: AUTH, event = "account_locked", user_id = %user.id, username = %username);
+
+    return match db.disable_user_sessions(&user.id).await {
+        Ok(count) => {
+            tracing::info!(target: AUTH, event = "sessions_revoked", user_id = %user.id, count, reason = "account_locked");
+            UpdateUserResult::Success
+        },
+        Err(_) => UpdateUserResult::DatabaseError
+    };
+}
+
+/// Marks the account as deleted and revokes its sessions. It stays in the database, locked, until
+/// the grace period is over.
+pub async fn schedule_deletion(user: &User, deleted_at: DateTime<Utc>, db: &dyn Store) -> UpdateUserResult {
+    match db.mark_user_deleted(&user.id, Some(deleted_at)).await {
+        Ok(true) => {},
+        Ok(false) => return UpdateUserResult::NotFound,
+        Err(_) => return UpdateUserResult::DatabaseError
     };
 
-    return match bcrypt::verify(password, &user.password_hash) {
-        Ok(valid) => if valid {Some(user)} else {None}, 
-        Err(_) => None 
+    tracing::warn!(target: AUTH, event = "account_deleted", user_id = %user.id, username = %user.username);
+
+    return match db.disable_user_sessions(&user.id).await {
+        Ok(count) => {
+            tracing::info!(target: AUTH, event = "sessions_revoked", user_id = %user.id, count, reason = "account_deleted");
+            UpdateUserResult::Success
+        },
+        Err(_) => UpdateUserResult::DatabaseError
     };
 }
 
+// Returned once per lookup and matched right away, so the size of `User` doesn't matter here.
+#[allow(clippy::large_enum_variant)]
 pub enum TokenUserResult {
     User(User),
     NotFound,
@@ -90,6 +331,8 @@ pub enum TokenUserResult {
     Disabled
 }
 
+// Returned once per lookup and matched right away, so the size of `User` doesn't matter here.
+#[allow(clippy::large_enum_variant)]
 pub enum GetTokenUserResult {
     Success(User),
     NotFound,
@@ -97,13 +340,32 @@ pub enum GetTokenUserResult {
     DatabaseError,
 }
 
-#[derive(FromRow, Debug)]
+// Like `GetTokenUserResult`, matched right away.
+#[allow(clippy::large_enum_variant)]
+pub enum GetTokenSessionResult {
+    Success(Session, User),
+    NotFound,
+    Unauthorized,
+    DatabaseError,
+}
+
+#[derive(FromRow, Debug, Clone)]
 pub struct Session {
     pub id: String,
     pub token: String,
     pub user_id: String,
     pub valid_to: sqlx::types::chrono::DateTime<Utc>,
-    pub disabled: i64 
+    pub disabled: i64,
+    /// The organization the session acts in, see [`crate::org`].
+    pub org_id: Option<String>,
+    /// The admin acting as `user_id` through this session, see [`crate::impersonation`].
+    pub impersonator_id: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    /// Fingerprint of the device the session was started from, see [`crate::client::device_id`].
+    pub device_id: Option<String>,
+    /// The network the session has to be used from, e.g. `192.0.2.0/24`, `None` for anywhere.
+    pub bound_range: Option<String>
 }
 
 impl Session {
@@ -122,7 +384,11 @@ impl Session {
     }
 
     pub fn new(user:&User) -> Self {
-        let token:String = Self::generate_session_token(32);
+        return Self::with_token_length(user, 32);
+    }
+
+    pub fn with_token_length(user:&User, length:usize) -> Self {
+        let token:String = Self::generate_session_token(length);
 
         // let hash: String = match hash(token, DEFAULT_COST) {
         //     Ok(result) => result,
@@ -133,34 +399,101 @@ impl Session {
             id: Uuid::new_v4().to_string(),
             token,
             user_id: user.id.clone(),
-            valid_to: Utc::now(),
-            disabled: 0 
+            valid_to: Utc::now() + Duration::days(SESSION_LIFETIME_DAYS),
+            disabled: 0,
+            org_id: None,
+            impersonator_id: None,
+            user_agent: None,
+            ip: None,
+            device_id: None,
+            bound_range: None
         };
     }
 
-    pub async fn add_to_database(&self, db: &Pool<Sqlite>) -> Result<SqliteQueryResult, sqlx::Error> {
-        return sqlx::query(
-            &*format!("INSERT INTO sessions (id, token, user_id, valid_to, disabled) \
-            VALUES ('{}', '{}', '{}', '{}', {});", self.id, self.token, self.user_id, self.valid_to, self.disabled) 
-        ).execute(db).await;
+    /// A session for `impersonator` to act as `subject` until `valid_to`.
+    pub fn impersonating(subject:&User, impersonator:&User, valid_to: DateTime<Utc>, length:usize) -> Self {
+        let mut session = Self::with_token_length(subject, length);
+        session.valid_to = valid_to;
+        session.impersonator_id = Some(impersonator.id.clone());
+
+        return session;
     }
 
+    /// Records the client the session was started by.
+    pub fn started_by(&mut self, client:&ClientInfo) {
+        self.user_agent = client.user_agent.clone();
+        self.ip = client.ip.map(|ip| ip.to_string());
+        self.device_id = Some(client.device_id.clone());
+    }
 
-    pub async fn get_token_user(token: &String, db: &Pool<Sqlite>) -> GetTokenUserResult {
-        let user_id_query = match sqlx::query!("SELECT user_id FROM sessions WHERE token = ?;", token).fetch_optional(db).await {
+    pub async fn add_to_database(&self, db: &dyn Store) -> Result<(), sqlx::Error> {
+        return db.add_session(self).await;
+    }
+
+
+    /// The valid session behind the token, along with its user.
+    pub async fn get_token_session(token: &String, db: &dyn Store) -> GetTokenSessionResult {
+        let session = match db.get_session_by_token(token).await {
             Ok(res) => match res {
                 Some(res) => res,
-                None => return GetTokenUserResult::NotFound
+                None => {
+                    tracing::debug!(target: AUTH, event = "token_rejected", reason = "unknown_token");
+                    return GetTokenSessionResult::NotFound;
+                }
             },
-            Err(_) => return GetTokenUserResult::DatabaseError 
+            Err(_) => return GetTokenSessionResult::DatabaseError 
         };
 
-        return match sqlx::query_as!(User, "SELECT * FROM users WHERE id = ?;", user_id_query.user_id).fetch_optional(db).await {
+        if session.disabled != 0 || session.valid_to < Utc::now() {
+            let reason = if session.disabled != 0 { "revoked" } else { "expired" };
+            tracing::debug!(target: AUTH, event = "token_rejected", session_id = %session.id, user_id = %session.user_id, reason);
+            return GetTokenSessionResult::Unauthorized;
+        }
+
+        return match db.get_user_by_id(&session.user_id).await {
             Ok(res) => match res {
-                Some(res) => GetTokenUserResult::Success(res),
-                None => GetTokenUserResult::NotFound
+                Some(res) if res.disabled != 0 => {
+                    tracing::debug!(target: AUTH, event = "token_rejected", session_id = %session.id, user_id = %res.id, reason = "account_locked");
+                    GetTokenSessionResult::Unauthorized
+                },
+                Some(res) if res.deleted_at.is_some() => {
+                    tracing::debug!(target: AUTH, event = "token_rejected", session_id = %session.id, user_id = %res.id, reason = "account_deleted");
+                    GetTokenSessionResult::Unauthorized
+                },
+                Some(res) => match Self::impersonator_allowed(&session, db).await {
+                    Ok(true) => GetTokenSessionResult::Success(session, res),
+                    Ok(false) => GetTokenSessionResult::Unauthorized,
+                    Err(_) => GetTokenSessionResult::DatabaseError
+                },
+                None => GetTokenSessionResult::NotFound
             },
-            Err(_) => GetTokenUserResult::DatabaseError 
+            Err(_) => GetTokenSessionResult::DatabaseError 
+        };
+    }
+
+    /// An impersonation only lasts as long as the admin behind it may still impersonate. Always
+    /// true for the user's own sessions.
+    async fn impersonator_allowed(session: &Session, db: &dyn Store) -> Result<bool, sqlx::Error> {
+        let impersonator_id = match &session.impersonator_id {
+            Some(id) => id,
+            None => return Ok(true)
+        };
+
+        return match db.get_user_by_id(impersonator_id).await? {
+            Some(admin) if admin.is_admin != 0 && admin.can_impersonate != 0 && admin.disabled == 0 && admin.deleted_at.is_none() => Ok(true),
+            _ => {
+                tracing::debug!(target: AUTH, event = "token_rejected", session_id = %session.id, user_id = %session.user_id, impersonator_id = %impersonator_id, reason = "impersonator_revoked");
+                Ok(false)
+            }
+        };
+    }
+
+    pub async fn get_token_user(token: &String, db: &dyn Store) -> GetTokenUserResult {
+        return match Self::get_token_session(token, db).await {
+            GetTokenSessionResult::Success(_, user) => GetTokenUserResult::Success(user),
+            GetTokenSessionResult::NotFound => GetTokenUserResult::NotFound,
+            GetTokenSessionResult::Unauthorized => GetTokenUserResult::Unauthorized,
+            GetTokenSessionResult::DatabaseError => GetTokenUserResult::DatabaseError
         };
     }
 }
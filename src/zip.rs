@@ -0,0 +1,205 @@
+//! A small ZIP writer for archives that are streamed while they're being made.
+//!
+//! Files are stored uncompressed and each one is followed by a data descriptor, so it can be sent
+//! before its size and checksum are known. There's no ZIP64, an archive holds at most 65535 files
+//! and 4 GiB.
+
+use axum::body::Bytes;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use crc::{Crc, Digest, CRC_32_ISO_HDLC};
+use std::io;
+
+static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+const LOCAL_FILE_HEADER: u32 = 0x04034b50;
+const DATA_DESCRIPTOR: u32 = 0x08074b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+
+/// ZIP 2.0, the first version with data descriptors.
+const VERSION: u16 = 20;
+/// Sizes and checksum follow the data (bit 3), file names are UTF-8 (bit 11).
+const FLAGS: u16 = 0x0008 | 0x0800;
+const STORED: u16 = 0;
+
+/// Writes an archive into an internal buffer, which [`ZipWriter::take`] hands out piece by piece.
+///
+/// Growing the archive past the limits of the format is an error, after which it can't be finished.
+pub struct ZipWriter {
+    buffer: Vec<u8>,
+    /// Bytes written so far, including the ones already taken.
+    offset: u64,
+    files: Vec<FileEntry>,
+    current: Option<OpenFile>,
+    /// The modification time of every file, in MS-DOS format.
+    dos_time: u16,
+    dos_date: u16
+}
+
+struct FileEntry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32
+}
+
+struct OpenFile {
+    name: String,
+    offset: u32,
+    size: u64,
+    digest: Digest<'static, u32>
+}
+
+impl ZipWriter {
+    /// An empty archive whose files are all dated `modified`.
+    pub fn new(modified: DateTime<Utc>) -> Self {
+        let year = modified.year().clamp(1980, 2107) as u16;
+
+        return Self {
+            buffer: Vec::new(),
+            offset: 0,
+            files: Vec::new(),
+            current: None,
+            dos_time: (modified.hour() as u16) << 11 | (modified.minute() as u16) << 5 | ((modified.second() as u16) / 2),
+            dos_date: (year - 1980) << 9 | (modified.month() as u16) << 5 | modified.day() as u16
+        };
+    }
+
+    /// Starts a new file, finishing the previous one.
+    pub fn start_file(&mut self, name: &str) -> io::Result<()> {
+        self.finish_file()?;
+
+        let offset = u32::try_from(self.offset).map_err(|_| too_large("ZIP archive larger than 4 GiB"))?;
+        let name_length = u16::try_from(name.len()).map_err(|_| too_large("file name longer than 65535 bytes in a ZIP archive"))?;
+
+        self.put_u32(LOCAL_FILE_HEADER);
+        self.put_u16(VERSION);
+        self.put_u16(FLAGS);
+        self.put_u16(STORED);
+        self.put_u16(self.dos_time);
+        self.put_u16(self.dos_date);
+        // Checksum and sizes are in the data descriptor.
+        self.put_u32(0);
+        self.put_u32(0);
+        self.put_u32(0);
+        self.put_u16(name_length);
+        self.put_u16(0);
+        self.put_bytes(name.as_bytes());
+
+        self.current = Some(OpenFile {
+            name: name.to_string(),
+            offset,
+            size: 0,
+            digest: CRC32.digest()
+        });
+
+        return Ok(());
+    }
+
+    /// Appends to the file started last.
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        let file = match self.current.as_mut() {
+            Some(file) => file,
+            None => return Err(io::Error::other("no file started in the ZIP archive"))
+        };
+        file.digest.update(data);
+        file.size += data.len() as u64;
+
+        self.put_bytes(data);
+
+        return Ok(());
+    }
+
+    /// A whole file at once.
+    pub fn file(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        self.start_file(name)?;
+
+        return self.write(data);
+    }
+
+    /// Finishes the last file and writes the central directory. Nothing can be added afterwards.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.finish_file()?;
+
+        let directory_offset = u32::try_from(self.offset).map_err(|_| too_large("ZIP archive larger than 4 GiB"))?;
+        let count = u16::try_from(self.files.len()).map_err(|_| too_large("more than 65535 files in a ZIP archive"))?;
+        let files = std::mem::take(&mut self.files);
+
+        for file in &files {
+            self.put_u32(CENTRAL_DIRECTORY_HEADER);
+            self.put_u16(VERSION);
+            self.put_u16(VERSION);
+            self.put_u16(FLAGS);
+            self.put_u16(STORED);
+            self.put_u16(self.dos_time);
+            self.put_u16(self.dos_date);
+            self.put_u32(file.crc);
+            self.put_u32(file.size);
+            self.put_u32(file.size);
+            self.put_u16(file.name.len() as u16);
+            // Extra field and comment lengths, disk number, internal and external attributes.
+            self.put_u16(0);
+            self.put_u16(0);
+            self.put_u16(0);
+            self.put_u16(0);
+            self.put_u32(0);
+            self.put_u32(file.offset);
+            self.put_bytes(file.name.as_bytes());
+        }
+
+        let directory_size = u32::try_from(self.offset - directory_offset as u64).map_err(|_| too_large("ZIP archive larger than 4 GiB"))?;
+
+        self.put_u32(END_OF_CENTRAL_DIRECTORY);
+        // This disk and the disk the directory starts on.
+        self.put_u16(0);
+        self.put_u16(0);
+        self.put_u16(count);
+        self.put_u16(count);
+        self.put_u32(directory_size);
+        self.put_u32(directory_offset);
+        self.put_u16(0);
+
+        return Ok(());
+    }
+
+    /// Everything written since the last call.
+    pub fn take(&mut self) -> Bytes {
+        return Bytes::from(std::mem::take(&mut self.buffer));
+    }
+
+    fn finish_file(&mut self) -> io::Result<()> {
+        let file = match self.current.take() {
+            Some(file) => file,
+            None => return Ok(())
+        };
+
+        let crc = file.digest.finalize();
+        let size = u32::try_from(file.size).map_err(|_| too_large("file larger than 4 GiB in a ZIP archive"))?;
+
+        self.put_u32(DATA_DESCRIPTOR);
+        self.put_u32(crc);
+        self.put_u32(size);
+        self.put_u32(size);
+
+        self.files.push(FileEntry { name: file.name, crc, size, offset: file.offset });
+
+        return Ok(());
+    }
+
+    fn put_u16(&mut self, value: u16) {
+        self.put_bytes(&value.to_le_bytes());
+    }
+
+    fn put_u32(&mut self, value: u32) {
+        self.put_bytes(&value.to_le_bytes());
+    }
+
+    fn put_bytes(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+        self.offset += bytes.len() as u64;
+    }
+}
+
+fn too_large(message: &str) -> io::Error {
+    return io::Error::new(io::ErrorKind::InvalidInput, message.to_string());
+}
@@ -0,0 +1,155 @@
+use axum::{http::{header, StatusCode}, test::{MultipartForm, TestClient}};
+use axum_user_jwt_template::{dto::{AccountDeletion, TokenResponse}, testing, zip::ZipWriter};
+use chrono::Utc;
+use serde_json::{json, Value};
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+async fn logged_in_client() -> (TestClient, String) {
+    let (app, _store) = testing::memory_app();
+    let client = TestClient::new(app);
+
+    let credentials = json!({ "username": "alice", "password": "hunter2" });
+    client.post("/register").json(&credentials).send().await;
+    let res = client.post("/login").header(header::USER_AGENT, "alice's browser").json(&credentials).send().await;
+    let TokenResponse { token } = res.json().await;
+
+    return (client, token);
+}
+
+/// The files in a ZIP archive, read through its central directory.
+fn unzip(archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let u16_at = |at: usize| u16::from_le_bytes([archive[at], archive[at + 1]]) as usize;
+    let u32_at = |at: usize| u32::from_le_bytes(archive[at..at + 4].try_into().unwrap()) as usize;
+
+    let end = archive.len() - 22;
+    assert_eq!(u32_at(end), 0x06054b50, "no end of central directory");
+    let count = u16_at(end + 10);
+    let mut entry = u32_at(end + 16);
+
+    let mut files = Vec::new();
+    for _ in 0..count {
+        assert_eq!(u32_at(entry), 0x02014b50);
+        let crc = u32_at(entry + 16) as u32;
+        let size = u32_at(entry + 24);
+        let name_len = u16_at(entry + 28);
+        let local = u32_at(entry + 42);
+        let name = String::from_utf8(archive[entry + 46..entry + 46 + name_len].to_vec()).unwrap();
+
+        assert_eq!(u32_at(local), 0x04034b50);
+        let data_start = local + 30 + u16_at(local + 26) + u16_at(local + 28);
+        let data = archive[data_start..data_start + size].to_vec();
+        assert_eq!(crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC).checksum(&data), crc, "{name}");
+
+        files.push((name, data));
+        entry += 46 + name_len;
+    }
+
+    return files;
+}
+
+#[tokio::test]
+async fn exports_everything_about_the_account_as_json() {
+    let (client, token) = logged_in_client().await;
+
+    // Somebody else's failed attempt at the account.
+    let res = client.post("/login").header(header::USER_AGENT, "mallory's script").json(&json!({ "username": "alice", "password": "guess" })).send().await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = client.get("/me/export").bearer_auth(&token).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+    assert_eq!(res.headers()[header::CACHE_CONTROL], "no-store");
+    assert!(res.headers()[header::CONTENT_DISPOSITION].to_str().unwrap().starts_with("attachment; filename=\"account-export-"));
+
+    let export: Value = res.json().await;
+    assert_eq!(export["profile"]["username"], "alice");
+    assert!(export["profile"].get("password_hash").is_none());
+
+    let sessions = export["sessions"].as_array().unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].get("token").is_none());
+    assert_eq!(export["api_keys"], json!([]));
+
+    let events = export["audit_events"].as_array().unwrap();
+    let kinds: Vec<&str> = events.iter().map(|event| event["kind"].as_str().unwrap()).collect();
+    assert_eq!(kinds, ["data_exported", "login_failed", "login_succeeded", "user_registered"]);
+
+    assert_eq!(events[2]["user_agent"], "alice's browser");
+    assert!(events[1]["user_agent"].is_null(), "another person's data is exported");
+}
+
+#[tokio::test]
+async fn exports_a_zip_with_the_avatar() {
+    let (client, token) = logged_in_client().await;
+
+    let form = MultipartForm::new().file("avatar", "me.png", "image/png", PNG);
+    client.put("/me/avatar").bearer_auth(&token).multipart(form).send().await;
+
+    let res = client.get("/me/export?format=zip").bearer_auth(&token).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "application/zip");
+
+    let files = unzip(&res.bytes().await);
+    let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["profile.json", "sessions.json", "api_keys.json", "avatar.png", "audit_events.json"]);
+
+    let profile: Value = serde_json::from_slice(&files[0].1).unwrap();
+    assert_eq!(profile["username"], "alice");
+    assert_eq!(files[2].1, b"[]");
+    assert_eq!(files[3].1, PNG);
+
+    let events: Vec<Value> = serde_json::from_slice(&files[4].1).unwrap();
+    assert_eq!(events[0]["kind"], "data_exported");
+    assert_eq!(events[0]["details"]["format"], "zip");
+}
+
+#[test]
+fn zip_archives_past_the_format_limits_are_errors() {
+    let mut zip = ZipWriter::new(Utc::now());
+    for i in 0..=u16::MAX as u32 {
+        zip.file(&format!("{i}.json"), b"{}").unwrap();
+    }
+    assert!(zip.finish().is_err());
+
+    let mut zip = ZipWriter::new(Utc::now());
+    assert!(zip.write(b"no file started").is_err());
+    assert!(zip.start_file(&"a".repeat(70_000)).is_err());
+}
+
+#[tokio::test]
+async fn exports_need_a_session() {
+    let (client, _token) = logged_in_client().await;
+
+    assert_eq!(client.get("/me/export").send().await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn deleting_the_account_needs_the_password() {
+    let (client, token) = logged_in_client().await;
+
+    let res = client.delete("/me").bearer_auth(&token).json(&json!({ "password": "wrong" })).send().await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    assert_eq!(client.get("/me").bearer_auth(&token).send().await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn deleted_accounts_are_locked_until_purged() {
+    let (client, token) = logged_in_client().await;
+
+    let res = client.delete("/me").bearer_auth(&token).json(&json!({ "password": "hunter2" })).send().await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+    let deletion: AccountDeletion = res.json().await;
+    assert_eq!((deletion.purge_after - deletion.deleted_at).num_days(), testing::test_config().deletion_grace_days as i64);
+
+    assert_eq!(client.get("/me").bearer_auth(&token).send().await.status(), StatusCode::UNAUTHORIZED);
+
+    let res = client.post("/login").json(&json!({ "username": "alice", "password": "hunter2" })).send().await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // The name stays taken until the account is gone for good.
+    let res = client.post("/register").json(&json!({ "username": "alice", "password": "hunter3" })).send().await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,196 @@
+use axum::{body::Body, http::{header, Request, StatusCode}, Router};
+use axum_user_jwt_template::{org::{OrgRole, Organization}, store::{MemoryStore, SessionStore, UserStore}, testing, user::User};
+use serde_json::Value;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn json_request(uri: &str, body: &str) -> Request<Body> {
+    return Request::post(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::USER_AGENT, "audit-test/1.0")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+}
+
+async fn body_bytes(res: axum::response::Response) -> Vec<u8> {
+    return hyper::body::to_bytes(res.into_body()).await.unwrap().to_vec();
+}
+
+async fn login(app: &Router, username: &str, password: &str) -> String {
+    let body = format!(r#"{{"username":"{username}","password":"{password}"}}"#);
+    let res = app.clone().oneshot(json_request("/login", &body)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body: Value = serde_json::from_slice(&body_bytes(res).await).unwrap();
+    return body["token"].as_str().unwrap().to_string();
+}
+
+/// Root, an admin logged in and acting in an organization of their own.
+async fn admin_app() -> (Router, Arc<MemoryStore>, String, Organization) {
+    let (app, store) = testing::memory_app();
+
+    let mut admin = User::with_cost(&"root".to_owned(), &"toor".to_owned(), 4).unwrap();
+    admin.is_admin = 1;
+    store.add_user(&admin).await;
+
+    let token = login(&app, "root", "toor").await;
+    let org = testing::active_org(&*store, &admin.id, &token).await;
+
+    return (app, store, token, org);
+}
+
+/// Registers `username` and puts them in `org`.
+async fn register_member(app: &Router, store: &MemoryStore, org: &Organization, username: &str) {
+    let credentials = format!(r#"{{"username":"{username}","password":"hunter2"}}"#);
+    app.clone().oneshot(json_request("/register", &credentials)).await.unwrap();
+
+    let user = store.get_user_by_username(username).await.unwrap().unwrap();
+    testing::add_member(store, &org.id, &user.id, OrgRole::Member).await;
+}
+
+fn get(uri: &str, token: &str) -> Request<Body> {
+    return Request::get(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+}
+
+#[tokio::test]
+async fn records_registrations_and_logins() {
+    let (app, store, token, org) = admin_app().await;
+
+    register_member(&app, &store, &org, "alice").await;
+    app.clone().oneshot(json_request("/login", r#"{"username":"alice","password":"nope"}"#)).await.unwrap();
+
+    let res = app.clone().oneshot(get("/admin/audit?limit=10", &token)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let page: Value = serde_json::from_slice(&body_bytes(res).await).unwrap();
+
+    let events = page["events"].as_array().unwrap();
+    let kinds: Vec<&str> = events.iter().map(|event| event["kind"].as_str().unwrap()).collect();
+    assert_eq!(kinds, ["login_failed", "user_registered", "login_succeeded"]);
+
+    let failed = &events[0];
+    assert_eq!(failed["target_id"], events[1]["target_id"]);
+    assert_eq!(failed["user_agent"], "audit-test/1.0");
+    assert_eq!(failed["details"]["reason"], "wrong_password");
+    assert!(failed["request_id"].is_string());
+    assert!(page["next_before"].is_null());
+}
+
+#[tokio::test]
+async fn filters_and_pages() {
+    let (app, _, token, _) = admin_app().await;
+
+    for _ in 0..3 {
+        app.clone().oneshot(json_request("/login", r#"{"username":"root","password":"nope"}"#)).await.unwrap();
+    }
+
+    let res = app.clone().oneshot(get("/admin/audit?kind=login_failed&limit=2", &token)).await.unwrap();
+    let page: Value = serde_json::from_slice(&body_bytes(res).await).unwrap();
+    assert_eq!(page["events"].as_array().unwrap().len(), 2);
+
+    let before = page["next_before"].as_i64().unwrap();
+    let res = app.clone().oneshot(get(&format!("/admin/audit?kind=login_failed&limit=2&before={before}"), &token)).await.unwrap();
+    let page: Value = serde_json::from_slice(&body_bytes(res).await).unwrap();
+    assert_eq!(page["events"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn exports_json_lines() {
+    let (app, _, token, _) = admin_app().await;
+
+    let res = app.clone().oneshot(get("/admin/audit/export", &token)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "application/x-ndjson");
+
+    let body = String::from_utf8(body_bytes(res).await).unwrap();
+    let kinds: Vec<String> = body
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap()["kind"].as_str().unwrap().to_string())
+        .collect();
+
+    // The export is audited before it's read, so it shows up in itself.
+    assert_eq!(kinds, ["audit_exported", "login_succeeded"]);
+}
+
+#[tokio::test]
+async fn is_only_open_to_admins() {
+    let (app, _, _, _) = admin_app().await;
+
+    let credentials = r#"{"username":"alice","password":"hunter2"}"#;
+    app.clone().oneshot(json_request("/register", credentials)).await.unwrap();
+    let token = login(&app, "alice", "hunter2").await;
+
+    let res = app.clone().oneshot(get("/admin/audit", &token)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = app.clone().oneshot(Request::get("/admin/audit").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn only_shows_the_admins_organization() {
+    let (app, store, token, org) = admin_app().await;
+    register_member(&app, &store, &org, "alice").await;
+
+    // Bob runs an organization of his own and isn't in root's, none of it is root's business.
+    app.clone().oneshot(json_request("/register", r#"{"username":"bob","password":"hunter2"}"#)).await.unwrap();
+    let bob_token = login(&app, "bob", "hunter2").await;
+    let create = Request::post("/orgs")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::AUTHORIZATION, format!("Bearer {bob_token}"))
+        .body(Body::from(r#"{"name":"Bob's"}"#))
+        .unwrap();
+    assert_eq!(app.clone().oneshot(create).await.unwrap().status(), StatusCode::CREATED);
+
+    let res = app.clone().oneshot(get("/admin/audit?limit=20", &token)).await.unwrap();
+    let page: Value = serde_json::from_slice(&body_bytes(res).await).unwrap();
+    let events = page["events"].as_array().unwrap();
+    let kinds: Vec<&str> = events.iter().map(|event| event["kind"].as_str().unwrap()).collect();
+    assert_eq!(kinds, ["user_registered", "login_succeeded"]);
+
+    let bob = store.get_user_by_username("bob").await.unwrap().unwrap();
+    let res = app.clone().oneshot(get(&format!("/admin/audit?user_id={}", bob.id), &token)).await.unwrap();
+    let page: Value = serde_json::from_slice(&body_bytes(res).await).unwrap();
+    assert_eq!(page["events"].as_array().unwrap().len(), 0);
+}
+
+#[tokio::test]
+async fn events_of_no_organization_are_seen_from_outside_all() {
+    let (app, store, token, org) = admin_app().await;
+    register_member(&app, &store, &org, "alice").await;
+    app.clone().oneshot(json_request("/login", r#"{"username":"nobody","password":"nope"}"#)).await.unwrap();
+    app.clone().oneshot(json_request("/register", r#"{"username":"mallory","password":"hunter2"}"#)).await.unwrap();
+
+    let kinds = |token: String| {
+        let app = app.clone();
+        async move {
+            let res = app.oneshot(get("/admin/audit", &token)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let page: Value = serde_json::from_slice(&body_bytes(res).await).unwrap();
+            page["events"].as_array().unwrap().iter().map(|event| event["kind"].as_str().unwrap().to_owned()).collect::<Vec<_>>()
+        }
+    };
+
+    // Neither the failed login for a name nobody has nor mallory, who is in no organization, are
+    // any organization's business.
+    assert_eq!(kinds(token).await, ["user_registered", "login_succeeded"]);
+
+    // A session of the same admin that acts in no organization sees them, and only them.
+    let outside = login(&app, "root", "toor").await;
+    assert_eq!(kinds(outside).await, ["user_registered", "login_failed"]);
+}
+
+#[tokio::test]
+async fn the_organization_has_to_be_the_admins() {
+    let (app, store, _, _) = admin_app().await;
+
+    // A second session of the same admin, switched to an organization root isn't in.
+    let token = login(&app, "root", "toor").await;
+    let other = Organization::new("Other");
+    store.set_session_org(&token, Some(&other.id)).await.unwrap();
+
+    let res = app.clone().oneshot(get("/admin/audit", &token)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,205 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use axum::{extract::connect_info::MockConnectInfo, http::{header, StatusCode}, serve::TlsConnectInfo, test::TestClient};
+use axum::BoxError;
+use axum_user_jwt_template::{app, audit::AuditFilter, config::ForwardedHeader, dto::TokenResponse, notify::{MemoryNotifier, Notification, Notifier}, state::AppState, store::{AuditStore, MemoryStore, SessionStore}, testing};
+use serde_json::json;
+
+const PROXY: &str = "10.0.0.1:40000";
+
+/// State behind a reverse proxy at 10.0.0.1, with notifications kept in memory.
+fn proxied_state() -> (AppState, Arc<MemoryStore>, Arc<MemoryNotifier>) {
+    let store = Arc::new(MemoryStore::new());
+    let notifier = Arc::new(MemoryNotifier::new());
+
+    let mut config = testing::test_config();
+    config.trusted_proxies = vec!["10.0.0.0/24".parse().unwrap()];
+    let mut state = testing::test_state(store.clone());
+    state.config = Arc::new(config);
+    state.notifier = notifier.clone();
+
+    return (state, store, notifier);
+}
+
+/// A client whose requests reach the app from `peer`.
+fn client_from(state: &AppState, peer: &str) -> TestClient {
+    let peer: SocketAddr = peer.parse().unwrap();
+
+    return TestClient::new(app(state.clone()).layer(MockConnectInfo(TlsConnectInfo::from(peer))));
+}
+
+async fn log_in(client: &TestClient, query: &str, forwarded_for: &str, user_agent: &str) -> String {
+    let credentials = json!({ "username": "alice", "password": "hunter2" });
+    let res = client.post(&format!("/login{query}"))
+        .header("x-forwarded-for", forwarded_for)
+        .header(header::USER_AGENT, user_agent)
+        .json(&credentials)
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let TokenResponse { token } = res.json().await;
+
+    return token;
+}
+
+#[tokio::test]
+async fn sessions_record_the_client_behind_the_proxy() {
+    let (state, store, _) = proxied_state();
+    let client = client_from(&state, PROXY);
+    client.post("/register").json(&json!({ "username": "alice", "password": "hunter2" })).send().await;
+
+    // The first address is made up by the client, only the one the proxy added counts.
+    let token = log_in(&client, "", "198.51.100.66, 203.0.113.9", "Firefox").await;
+
+    let session = store.get_session_by_token(&token).await.unwrap().unwrap();
+    assert_eq!(session.ip.as_deref(), Some("203.0.113.9"));
+    assert_eq!(session.user_agent.as_deref(), Some("Firefox"));
+    assert!(session.device_id.is_some());
+    assert!(session.bound_range.is_none());
+
+    // Anybody else's forwarded headers are ignored.
+    let direct = client_from(&state, "192.0.2.10:50000");
+    let token = log_in(&direct, "", "203.0.113.9", "Firefox").await;
+    let session = store.get_session_by_token(&token).await.unwrap().unwrap();
+    assert_eq!(session.ip.as_deref(), Some("192.0.2.10"));
+
+    // The audit log sees the same addresses.
+    let logins = AuditFilter { kind: Some("login_succeeded".to_owned()), ..AuditFilter::default() };
+    let events = store.list_audit_events(&logins, 10).await.unwrap();
+    let ips: Vec<Option<&str>> = events.iter().map(|event| event.ip.as_deref()).collect();
+    assert_eq!(ips, [Some("192.0.2.10"), Some("203.0.113.9")]);
+}
+
+/// Logs in through `client` with the given forwarded headers, returning the address the session
+/// recorded.
+async fn session_ip(client: &TestClient, store: &MemoryStore, headers: &[(&str, &str)]) -> Option<String> {
+    let mut req = client.post("/login").json(&json!({ "username": "alice", "password": "hunter2" }));
+    for (name, value) in headers {
+        req = req.header(*name, *value);
+    }
+    let res = req.send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let TokenResponse { token } = res.json().await;
+
+    return store.get_session_by_token(&token).await.unwrap().unwrap().ip;
+}
+
+#[tokio::test]
+async fn only_the_configured_header_is_read() {
+    let (state, store, _) = proxied_state();
+    let client = client_from(&state, PROXY);
+    client.post("/register").json(&json!({ "username": "alice", "password": "hunter2" })).send().await;
+
+    // The proxy only maintains X-Forwarded-For, a Forwarded header is the client's own.
+    let ip = session_ip(&client, &store, &[("forwarded", "for=198.51.100.66"), ("x-forwarded-for", "203.0.113.9")]).await;
+    assert_eq!(ip.as_deref(), Some("203.0.113.9"));
+    let ip = session_ip(&client, &store, &[("forwarded", "for=198.51.100.66")]).await;
+    assert_eq!(ip.as_deref(), Some("10.0.0.1"));
+
+    let mut config = (*state.config).clone();
+    config.forwarded_header = ForwardedHeader::Forwarded;
+    let mut state = state;
+    state.config = Arc::new(config);
+    let client = client_from(&state, PROXY);
+
+    let ip = session_ip(&client, &store, &[("forwarded", "for=203.0.113.9"), ("x-forwarded-for", "198.51.100.66")]).await;
+    assert_eq!(ip.as_deref(), Some("203.0.113.9"));
+}
+
+#[tokio::test]
+async fn the_walk_stops_at_a_hop_that_isnt_an_address() {
+    let (state, store, _) = proxied_state();
+    let client = client_from(&state, PROXY);
+    client.post("/register").json(&json!({ "username": "alice", "password": "hunter2" })).send().await;
+
+    // 10.0.0.2 is trusted, but it doesn't say who it got the request from.
+    let ip = session_ip(&client, &store, &[("x-forwarded-for", "198.51.100.66, unknown, 10.0.0.2")]).await;
+    assert_eq!(ip.as_deref(), Some("10.0.0.2"));
+
+    let ip = session_ip(&client, &store, &[("x-forwarded-for", "198.51.100.66, _hidden")]).await;
+    assert_eq!(ip.as_deref(), Some("10.0.0.1"));
+}
+
+/// The notifications once there are `count` of them, they're sent in the background of the login.
+async fn sent(notifier: &MemoryNotifier, count: usize) -> Vec<Notification> {
+    for _ in 0..100 {
+        if notifier.sent().len() >= count {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    return notifier.sent();
+}
+
+/// A notifier whose server never answers.
+struct HungNotifier;
+
+#[async_trait::async_trait]
+impl Notifier for HungNotifier {
+    async fn notify(&self, _: &Notification) -> Result<(), BoxError> {
+        return std::future::pending().await;
+    }
+}
+
+#[tokio::test]
+async fn logins_from_new_devices_are_notified() {
+    let (state, store, notifier) = proxied_state();
+    let client = client_from(&state, PROXY);
+    client.post("/register").json(&json!({ "username": "alice", "password": "hunter2" })).send().await;
+
+    // Nothing to compare the first device with, and the same one again isn't news.
+    log_in(&client, "", "203.0.113.9", "Firefox").await;
+    log_in(&client, "", "198.51.100.1", "Firefox").await;
+    assert!(notifier.sent().is_empty());
+
+    let token = log_in(&client, "", "198.51.100.1", "Chrome").await;
+    let session = store.get_session_by_token(&token).await.unwrap().unwrap();
+
+    let sent = sent(&notifier, 1).await;
+    assert_eq!(sent.len(), 1);
+    let Notification::NewDevice { user_id, session_id, ip, user_agent, .. } = &sent[0];
+    assert_eq!(user_id, &session.user_id);
+    assert_eq!(session_id, &session.id);
+    assert_eq!(ip.unwrap().to_string(), "198.51.100.1");
+    assert_eq!(user_agent.as_deref(), Some("Chrome"));
+}
+
+#[tokio::test]
+async fn notifications_dont_hold_up_the_login() {
+    let (mut state, _, _) = proxied_state();
+    state.notifier = Arc::new(HungNotifier);
+    let client = client_from(&state, PROXY);
+    client.post("/register").json(&json!({ "username": "alice", "password": "hunter2" })).send().await;
+
+    log_in(&client, "", "203.0.113.9", "Firefox").await;
+    let login = log_in(&client, "", "203.0.113.9", "Chrome");
+    tokio::time::timeout(Duration::from_secs(5), login).await.expect("the login waited for the notification");
+}
+
+#[tokio::test]
+async fn bound_sessions_only_work_from_their_network() {
+    let (state, _, _) = proxied_state();
+    let client = client_from(&state, PROXY);
+    client.post("/register").json(&json!({ "username": "alice", "password": "hunter2" })).send().await;
+
+    let bound = log_in(&client, "?bind_ip=true", "203.0.113.9", "Firefox").await;
+    let unbound = log_in(&client, "", "203.0.113.9", "Firefox").await;
+
+    let me = |token: String, forwarded_for: &'static str| client.get("/me").bearer_auth(&token).header("x-forwarded-for", forwarded_for).send();
+    assert_eq!(me(bound.clone(), "203.0.113.9").await.status(), StatusCode::OK);
+    assert_eq!(me(bound.clone(), "203.0.113.200").await.status(), StatusCode::OK, "same /24");
+    assert_eq!(me(bound.clone(), "198.51.100.1").await.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(me(unbound, "198.51.100.1").await.status(), StatusCode::OK);
+
+    // Checking the token on its own goes by the same rule.
+    let verify = |forwarded_for: &'static str| client.post("/verify").header("x-forwarded-for", forwarded_for).json(&json!({ "token": bound })).send();
+    assert_eq!(verify("203.0.113.9").await.status(), StatusCode::OK);
+    assert_eq!(verify("198.51.100.1").await.status(), StatusCode::UNAUTHORIZED);
+
+    // Without a known address there's nothing to bind to.
+    let (app, _) = testing::memory_app();
+    let client = TestClient::new(app);
+    client.post("/register").json(&json!({ "username": "alice", "password": "hunter2" })).send().await;
+    let res = client.post("/login?bind_ip=true").json(&json!({ "username": "alice", "password": "hunter2" })).send().await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
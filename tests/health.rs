@@ -0,0 +1,67 @@
+use std::sync::Arc;
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use axum_user_jwt_template::{app, health, state::AppState, store::MemoryStore, testing};
+use serde_json::Value;
+use tower::ServiceExt;
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, Value) {
+    let res = app
+        .clone()
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let status = res.status();
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+    return (status, serde_json::from_slice(&body).unwrap());
+}
+
+#[tokio::test]
+async fn healthz_is_ok() {
+    let (app, _) = testing::memory_app();
+
+    let (status, body) = get(&app, "/healthz").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ok");
+}
+
+#[tokio::test]
+async fn readyz_checks_the_database() {
+    let (app, _) = testing::sqlite_memory_app().await;
+
+    let (status, body) = get(&app, "/readyz").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ready");
+}
+
+#[tokio::test]
+async fn readyz_fails_once_shutdown_begins() {
+    let state = AppState::new(Arc::new(MemoryStore::new()), testing::test_config());
+    let shutdown = state.shutdown.clone();
+    let app = app(state);
+
+    assert_eq!(get(&app, "/readyz").await.0, StatusCode::OK);
+
+    shutdown.begin();
+
+    let (status, body) = get(&app, "/readyz").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["reason"], "shutting_down");
+
+    // Liveness is unaffected, the process isn't broken.
+    assert_eq!(get(&app, "/healthz").await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn version_reports_the_build_and_schema() {
+    let (app, store) = testing::sqlite_memory_app().await;
+
+    let (status, body) = get(&app, "/version").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["version"], health::VERSION);
+    assert_eq!(body["git_sha"], health::GIT_SHA);
+
+    let expected = axum_user_jwt_template::store::StoreHealth::expected_schema_version(&*store);
+    assert_eq!(body["schema_version"], expected.unwrap());
+}
@@ -0,0 +1,203 @@
+use axum::{http::{header, StatusCode}, test::{MultipartForm, TestClient}};
+use axum_user_jwt_template::{dto::{ImpersonationResponse, TokenResponse, UserProfile}, org::OrgRole, store::{SessionStore, UserStore}, testing, user::User};
+use chrono::Utc;
+use serde_json::{json, Value};
+
+async fn log_in(client: &TestClient, username: &str, password: &str) -> String {
+    let credentials = json!({ "username": username, "password": password });
+    let TokenResponse { token } = client.post("/login").json(&credentials).send().await.json().await;
+
+    return token;
+}
+
+/// An admin allowed to impersonate, logged in as `root`, and alice, a user in root's
+/// organization, logged in too.
+async fn support_and_alice() -> (TestClient, String, String, String) {
+    let (app, store) = testing::memory_app();
+    let client = TestClient::new(app);
+
+    let mut admin = User::with_cost(&"root".to_owned(), &"toor".to_owned(), 4).unwrap();
+    admin.is_admin = 1;
+    admin.can_impersonate = 1;
+    store.add_user(&admin).await;
+    let admin_token = log_in(&client, "root", "toor").await;
+    let org = testing::active_org(&*store, &admin.id, &admin_token).await;
+
+    client.post("/register").json(&json!({ "username": "alice", "password": "hunter2" })).send().await;
+    let alice_token = log_in(&client, "alice", "hunter2").await;
+    let alice: UserProfile = client.get("/me").bearer_auth(&alice_token).send().await.json().await;
+    testing::add_member(&*store, &org.id, &alice.id, OrgRole::Member).await;
+
+    return (client, admin_token, alice_token, alice.id);
+}
+
+async fn impersonate(client: &TestClient, admin_token: &str, user_id: &str) -> ImpersonationResponse {
+    let res = client.post(&format!("/admin/users/{user_id}/impersonate")).bearer_auth(admin_token).send().await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    return res.json().await;
+}
+
+#[tokio::test]
+async fn acts_as_the_user_for_a_while() {
+    let (client, admin_token, _, alice_id) = support_and_alice().await;
+    let impersonation = impersonate(&client, &admin_token, &alice_id).await;
+
+    let minutes = (impersonation.expires_at - Utc::now()).num_minutes();
+    assert!((29..=30).contains(&minutes), "{minutes}");
+
+    let res = client.get("/me").bearer_auth(&impersonation.token).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CACHE_CONTROL], "no-store");
+    let me: UserProfile = res.json().await;
+    assert_eq!(me.username, "alice");
+    let flag = me.impersonation.unwrap();
+    assert_eq!(flag.impersonator_id, impersonation.impersonator_id);
+    assert_eq!(flag.expires_at, impersonation.expires_at);
+
+    // The admin's own session is still theirs.
+    let me: Value = client.get("/me").bearer_auth(&admin_token).send().await.json().await;
+    assert_eq!(me["username"], "root");
+    assert!(me.get("impersonation").is_none());
+}
+
+#[tokio::test]
+async fn flags_everything_done_in_the_audit_log() {
+    let (client, admin_token, _, alice_id) = support_and_alice().await;
+    let impersonation = impersonate(&client, &admin_token, &alice_id).await;
+
+    let res = client.get("/me").bearer_auth(&impersonation.token).send().await;
+    let etag = res.headers()[header::ETAG].to_str().unwrap().to_owned();
+    let res = client.patch("/me").bearer_auth(&impersonation.token).header(header::IF_MATCH, etag.as_str()).json(&json!({ "display_name": "Alice" })).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let page: Value = client.get(&format!("/admin/audit?user_id={alice_id}")).bearer_auth(&admin_token).send().await.json().await;
+    let events = page["events"].as_array().unwrap();
+    let kinds: Vec<&str> = events.iter().map(|event| event["kind"].as_str().unwrap()).collect();
+    assert_eq!(kinds, ["profile_updated", "impersonation_started", "login_succeeded", "user_registered"]);
+
+    assert_eq!(events[0]["actor_id"], alice_id.as_str());
+    assert_eq!(events[0]["impersonator_id"], impersonation.impersonator_id.as_str());
+    assert_eq!(events[1]["actor_id"], impersonation.impersonator_id.as_str());
+    assert!(events[2]["impersonator_id"].is_null());
+}
+
+#[tokio::test]
+async fn cant_change_credentials_or_take_the_data() {
+    let (client, admin_token, _, alice_id) = support_and_alice().await;
+    let impersonation = impersonate(&client, &admin_token, &alice_id).await;
+
+    let res = client.delete("/me").bearer_auth(&impersonation.token).json(&json!({ "password": "hunter2" })).send().await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = client.get("/me").bearer_auth(&impersonation.token).send().await;
+    let etag = res.headers()[header::ETAG].to_str().unwrap().to_owned();
+    let res = client.patch("/me").bearer_auth(&impersonation.token).header(header::IF_MATCH, etag.as_str()).json(&json!({ "email": "mallory@example.com" })).send().await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // Nor take the account's data or change its avatar.
+    assert_eq!(client.get("/me/export").bearer_auth(&impersonation.token).send().await.status(), StatusCode::FORBIDDEN);
+    let form = MultipartForm::new().file("avatar", "me.png", "image/png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".as_slice());
+    assert_eq!(client.put("/me/avatar").bearer_auth(&impersonation.token).multipart(form).send().await.status(), StatusCode::FORBIDDEN);
+    assert_eq!(client.delete("/me/avatar").bearer_auth(&impersonation.token).send().await.status(), StatusCode::FORBIDDEN);
+
+    // Nor impersonate anybody else from there.
+    let res = client.post(&format!("/admin/users/{alice_id}/impersonate")).bearer_auth(&impersonation.token).send().await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn needs_the_permission() {
+    let (app, store) = testing::memory_app();
+    let client = TestClient::new(app);
+
+    let mut admin = User::with_cost(&"root".to_owned(), &"toor".to_owned(), 4).unwrap();
+    admin.is_admin = 1;
+    store.add_user(&admin).await;
+    let mut support = User::with_cost(&"support".to_owned(), &"secret".to_owned(), 4).unwrap();
+    support.is_admin = 1;
+    support.can_impersonate = 1;
+    store.add_user(&support).await;
+    client.post("/register").json(&json!({ "username": "alice", "password": "hunter2" })).send().await;
+
+    let admin_token = log_in(&client, "root", "toor").await;
+    let support_token = log_in(&client, "support", "secret").await;
+    let alice_token = log_in(&client, "alice", "hunter2").await;
+    let alice: UserProfile = client.get("/me").bearer_auth(&alice_token).send().await.json().await;
+
+    let org = testing::active_org(&*store, &support.id, &support_token).await;
+    testing::add_member(&*store, &org.id, &admin.id, OrgRole::Admin).await;
+    store.set_session_org(&admin_token, Some(&org.id)).await.unwrap();
+    testing::add_member(&*store, &org.id, &alice.id, OrgRole::Member).await;
+
+    for token in [&admin_token, &alice_token] {
+        let res = client.post(&format!("/admin/users/{}/impersonate", alice.id)).bearer_auth(token).send().await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    let impersonate = |user_id: String| client.post(&format!("/admin/users/{user_id}/impersonate")).bearer_auth(&support_token).send();
+    assert_eq!(impersonate(admin.id.clone()).await.status(), StatusCode::FORBIDDEN, "other admins");
+    assert_eq!(impersonate(support.id.clone()).await.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(impersonate("made-up".to_owned()).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn ends_on_request() {
+    let (client, admin_token, alice_token, alice_id) = support_and_alice().await;
+    let impersonation = impersonate(&client, &admin_token, &alice_id).await;
+
+    // Only impersonation sessions can be ended this way.
+    assert_eq!(client.delete("/session/impersonation").bearer_auth(&alice_token).send().await.status(), StatusCode::NOT_FOUND);
+
+    let res = client.delete("/session/impersonation").bearer_auth(&impersonation.token).send().await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(client.get("/me").bearer_auth(&impersonation.token).send().await.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(client.get("/me").bearer_auth(&alice_token).send().await.status(), StatusCode::OK);
+
+    let page: Value = client.get("/admin/audit?kind=impersonation_ended").bearer_auth(&admin_token).send().await.json().await;
+    let event = &page["events"][0];
+    assert_eq!(event["actor_id"], impersonation.impersonator_id.as_str());
+    assert_eq!(event["target_id"], alice_id.as_str());
+    assert_eq!(event["impersonator_id"], impersonation.impersonator_id.as_str());
+}
+
+#[tokio::test]
+async fn only_reaches_members_of_the_organization() {
+    let (client, admin_token, _, alice_id) = support_and_alice().await;
+
+    client.post("/register").json(&json!({ "username": "bob", "password": "hunter2" })).send().await;
+    let bob_token = log_in(&client, "bob", "hunter2").await;
+    let bob: UserProfile = client.get("/me").bearer_auth(&bob_token).send().await.json().await;
+
+    let res = client.post(&format!("/admin/users/{}/impersonate", bob.id)).bearer_auth(&admin_token).send().await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    // Nor without an organization to act in.
+    let outside = log_in(&client, "root", "toor").await;
+    let res = client.post(&format!("/admin/users/{alice_id}/impersonate")).bearer_auth(&outside).send().await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn stays_in_the_admins_organization() {
+    let (client, admin_token, alice_token, alice_id) = support_and_alice().await;
+
+    // Alice owns another organization, which is none of root's business.
+    let res = client.post("/orgs").bearer_auth(&alice_token).json(&json!({ "name": "Alice's" })).send().await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let other: Value = res.json().await;
+
+    let impersonation = impersonate(&client, &admin_token, &alice_id).await;
+
+    let current: Value = client.get("/org").bearer_auth(&impersonation.token).send().await.json().await;
+    assert_eq!(current["org"]["name"], "Operators");
+    assert_eq!(current["role"], "member");
+
+    let res = client.put("/session/org").bearer_auth(&impersonation.token).json(&json!({ "org_id": other["org"]["id"] })).send().await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let res = client.put("/session/org").bearer_auth(&impersonation.token).json(&json!({ "org_id": null })).send().await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let current: Value = client.get("/org").bearer_auth(&impersonation.token).send().await.json().await;
+    assert_eq!(current["org"]["name"], "Operators");
+}
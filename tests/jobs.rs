@@ -0,0 +1,125 @@
+use std::{sync::Arc, time::Duration};
+
+use axum_user_jwt_template::{
+    audit::{AuditContext, AuditFilter, AuditKind},
+    blob::{BlobStore, MemoryBlobStore},
+    jobs::{AccountDeleter, SessionPurger},
+    store::{AuditStore, MemoryStore, SessionStore, UserStore},
+    user::{Session, User},
+};
+use chrono::Utc;
+use tokio::sync::watch;
+
+async fn store_with_sessions(expired: usize, current: usize) -> Arc<MemoryStore> {
+    let store = Arc::new(MemoryStore::new());
+    let user = User::with_cost(&"alice".to_owned(), &"hunter2".to_owned(), 4).unwrap();
+    user.add_to_database(&*store).await;
+
+    for _ in 0..expired {
+        let mut session = Session::new(&user);
+        session.valid_to = Utc::now() - chrono::Duration::minutes(1);
+        session.add_to_database(&*store).await.unwrap();
+    }
+
+    for _ in 0..current {
+        Session::new(&user).add_to_database(&*store).await.unwrap();
+    }
+
+    store
+}
+
+#[tokio::test]
+async fn run_once_deletes_everything_stale_and_records_stats() {
+    let store = store_with_sessions(5, 1).await;
+    let purger = SessionPurger::new(store.clone(), 2);
+    let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let stats = purger.run_once(&shutdown_rx).await;
+
+    assert_eq!(stats.runs, 1);
+    assert_eq!(stats.last_run_deleted, 5);
+    assert_eq!(stats.last_run_batches, 3);
+    assert!(stats.last_error.is_none());
+    assert_eq!(purger.stats().total_deleted, 5);
+
+    assert_eq!(store.delete_stale_sessions(Utc::now(), 10).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn spawned_job_runs_and_stops_on_shutdown() {
+    let store = store_with_sessions(3, 0).await;
+    let purger = SessionPurger::new(store, 10);
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let job = purger.clone().spawn(Duration::from_millis(10), shutdown_rx);
+
+    // The first tick fires immediately.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(purger.stats().total_deleted, 3);
+
+    shutdown_tx.send(true).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), job)
+        .await
+        .expect("job didn't stop")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn deletes_accounts_once_the_grace_period_is_over() {
+    let store = Arc::new(MemoryStore::new());
+    let blobs = Arc::new(MemoryBlobStore::new());
+    let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let mut alice = User::with_cost(&"alice".to_owned(), &"hunter2".to_owned(), 4).unwrap();
+    alice.avatar_key = Some(format!("avatars/{}/face.png", alice.id));
+    alice.add_to_database(&*store).await;
+    blobs.put(alice.avatar_key.as_deref().unwrap(), "png".into()).await.unwrap();
+    Session::new(&alice).add_to_database(&*store).await.unwrap();
+
+    let bob = User::with_cost(&"bob".to_owned(), &"hunter2".to_owned(), 4).unwrap();
+    bob.add_to_database(&*store).await;
+
+    store.mark_user_deleted(&alice.id, Some(Utc::now() - chrono::Duration::days(31))).await.unwrap();
+    store.mark_user_deleted(&bob.id, Some(Utc::now() - chrono::Duration::days(29))).await.unwrap();
+
+    let deleter = AccountDeleter::new(store.clone(), blobs.clone(), 30, 1);
+    assert_eq!(deleter.run_once(&shutdown_rx).await.unwrap(), 1);
+
+    assert!(store.get_user_by_id(&alice.id).await.unwrap().is_none());
+    assert!(store.list_user_sessions(&alice.id).await.unwrap().is_empty());
+    assert!(blobs.is_empty());
+
+    // Still within the grace period.
+    assert!(store.get_user_by_id(&bob.id).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn purged_accounts_leave_no_address_in_the_audit_log() {
+    let store = Arc::new(MemoryStore::new());
+    let blobs = Arc::new(MemoryBlobStore::new());
+    let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let alice = User::with_cost(&"alice".to_owned(), &"hunter2".to_owned(), 4).unwrap();
+    alice.add_to_database(&*store).await;
+    let bob = User::with_cost(&"bob".to_owned(), &"hunter2".to_owned(), 4).unwrap();
+    bob.add_to_database(&*store).await;
+
+    let context = AuditContext { ip: Some("192.0.2.1".to_owned()), user_agent: Some("Firefox".to_owned()), ..AuditContext::default() };
+    context.event(AuditKind::LoginSucceeded).actor(&alice.id).target(&alice.id).record(&*store).await;
+    context.event(AuditKind::LoginFailed).target(&alice.id).record(&*store).await;
+    context.event(AuditKind::LoginSucceeded).actor(&bob.id).target(&bob.id).record(&*store).await;
+
+    store.mark_user_deleted(&alice.id, Some(Utc::now() - chrono::Duration::days(31))).await.unwrap();
+    let deleter = AccountDeleter::new(store.clone(), blobs, 30, 10);
+    assert_eq!(deleter.run_once(&shutdown_rx).await.unwrap(), 1);
+
+    // The events stay, without where they came from.
+    let events = store.list_audit_events(&AuditFilter { user_id: Some(alice.id.clone()), ..AuditFilter::default() }, 10).await.unwrap();
+    let kinds: Vec<&str> = events.iter().map(|event| event.kind.as_str()).collect();
+    assert_eq!(kinds, ["account_purged", "login_failed", "login_succeeded"]);
+    assert!(events.iter().all(|event| event.ip.is_none() && event.user_agent.is_none()));
+
+    let events = store.list_audit_events(&AuditFilter { user_id: Some(bob.id.clone()), ..AuditFilter::default() }, 10).await.unwrap();
+    assert_eq!(events[0].ip.as_deref(), Some("192.0.2.1"));
+    assert_eq!(events[0].user_agent.as_deref(), Some("Firefox"));
+}
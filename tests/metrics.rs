@@ -0,0 +1,47 @@
+use axum::{http::{header, StatusCode}, test::TestClient};
+use axum_user_jwt_template::testing;
+use serde_json::json;
+
+async fn scrape(client: &TestClient) -> String {
+    let res = client.get("/metrics").send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/plain; version=0.0.4"));
+
+    return res.text().await;
+}
+
+#[tokio::test]
+async fn counts_requests_by_route_and_status() {
+    let (app, _) = testing::memory_app();
+    let client = TestClient::new(app);
+
+    let res = client.post("/verify").json(&json!({ "token": "nope" })).send().await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let metrics = scrape(&client).await;
+    assert!(metrics.contains(r#"http_requests_total{path="/verify",status="401"} 1"#), "{metrics}");
+    assert!(metrics.contains(r#"http_request_duration_seconds_count{path="/verify",status="401"} 1"#), "{metrics}");
+}
+
+#[tokio::test]
+async fn counts_logins_registrations_and_sessions() {
+    let (app, _) = testing::memory_app();
+    let client = TestClient::new(app);
+    let credentials = json!({ "username": "alice", "password": "hunter2" });
+
+    let res = client.post("/register").json(&credentials).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client.post("/login").json(&credentials).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client.post("/login").json(&json!({ "username": "alice", "password": "wrong" })).send().await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let metrics = scrape(&client).await;
+    assert!(metrics.contains("auth_registrations_total 1"), "{metrics}");
+    assert!(metrics.contains(r#"auth_logins_total{result="succeeded"} 1"#), "{metrics}");
+    assert!(metrics.contains(r#"auth_logins_total{result="failed"} 1"#), "{metrics}");
+    assert!(metrics.contains("auth_active_sessions 1"), "{metrics}");
+    assert!(metrics.contains("bcrypt_queue_depth 0"), "{metrics}");
+}
@@ -0,0 +1,85 @@
+use axum::{http::{header, StatusCode}, test::TestClient};
+use axum_user_jwt_template::{negotiate::ResponseFormat, testing};
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Serialize)]
+struct Credentials<'a> {
+    username: &'a str,
+    password: &'a str
+}
+
+const ALICE: Credentials<'static> = Credentials { username: "alice", password: "hunter2" };
+
+const BROWSER_ACCEPT: &str = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+
+fn client() -> TestClient {
+    let (app, _store) = testing::memory_app();
+
+    return TestClient::new(app);
+}
+
+#[test]
+fn picks_the_preferred_format() {
+    assert_eq!(ResponseFormat::from_accept(BROWSER_ACCEPT), ResponseFormat::Html);
+    assert_eq!(ResponseFormat::from_accept("application/json"), ResponseFormat::Json);
+    assert_eq!(ResponseFormat::from_accept("text/html;q=0.5, application/json"), ResponseFormat::Json);
+    assert_eq!(ResponseFormat::from_accept("application/json;q=0, text/html;q=0.1"), ResponseFormat::Html);
+    assert_eq!(ResponseFormat::from_accept("*/*"), ResponseFormat::Unspecified);
+    assert_eq!(ResponseFormat::from_accept("text/plain"), ResponseFormat::Unspecified);
+}
+
+#[tokio::test]
+async fn html_forms_get_html_pages() {
+    let client = client();
+
+    let res = client.post("/register").header(header::ACCEPT, BROWSER_ACCEPT).form(&ALICE).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/html"));
+    assert!(res.text().await.contains("You can now sign in as alice."));
+
+    let res = client.post("/login?cookie=true").header(header::ACCEPT, BROWSER_ACCEPT).form(&ALICE).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.text().await.contains("You are signed in as alice."));
+    assert!(client.cookie("session").is_some());
+
+    let wrong = Credentials { username: "alice", password: "hunter3" };
+    let res = client.post("/login").header(header::ACCEPT, BROWSER_ACCEPT).form(&wrong).send().await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert!(res.text().await.contains("Wrong username or password."));
+}
+
+#[tokio::test]
+async fn forms_and_json_share_the_endpoints() {
+    let client = client();
+
+    let res = client.post("/register").header(header::ACCEPT, "application/json").form(&ALICE).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = res.json().await;
+    assert_eq!(body["username"], "alice");
+    assert!(body["id"].is_string());
+
+    let res = client.post("/login").json(&ALICE).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+    let body: Value = res.json().await;
+    assert!(body["token"].is_string());
+
+    let res = client.post("/login").form(&ALICE).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = res.json().await;
+    assert!(body["token"].is_string());
+}
+
+#[tokio::test]
+async fn clients_without_accept_get_the_old_responses() {
+    let client = client();
+
+    let res = client.post("/register").json(&ALICE).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await, "Success");
+
+    let res = client.post("/register").json(&ALICE).send().await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert!(res.bytes().await.is_empty());
+}
@@ -0,0 +1,236 @@
+use axum::{http::StatusCode, test::TestClient};
+use axum_user_jwt_template::{dto::{InvitationResponse, OrgResponse, TokenResponse}, org::OrgRole, testing};
+use serde_json::{json, Value};
+
+async fn sign_up(client: &TestClient, username: &str) -> String {
+    let credentials = json!({ "username": username, "password": "hunter2" });
+    client.post("/register").json(&credentials).send().await;
+    let TokenResponse { token } = client.post("/login").json(&credentials).send().await.json().await;
+
+    return token;
+}
+
+/// Alice's organization, active in her session.
+async fn org_with_owner() -> (TestClient, String, OrgResponse) {
+    let (app, _store) = testing::memory_app();
+    let client = TestClient::new(app);
+    let alice = sign_up(&client, "alice").await;
+
+    let res = client.post("/orgs").bearer_auth(&alice).json(&json!({ "name": " Acme " })).send().await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let org: OrgResponse = res.json().await;
+    assert_eq!(org.org.name, "Acme");
+    assert_eq!(org.role, OrgRole::Owner);
+
+    let res = client.put("/session/org").bearer_auth(&alice).json(&json!({ "org_id": org.org.id })).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    return (client, alice, org);
+}
+
+async fn invite(client: &TestClient, token: &str, role: &str) -> InvitationResponse {
+    let res = client.post("/org/invitations").bearer_auth(token).json(&json!({ "role": role })).send().await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    return res.json().await;
+}
+
+/// Signs up `username`, joins through an invitation with `role` and switches to the organization.
+async fn join(client: &TestClient, owner: &str, org: &OrgResponse, username: &str, role: &str) -> String {
+    let token = sign_up(client, username).await;
+    let invitation = invite(client, owner, role).await;
+
+    let res = client.post(&format!("{}/accept", invitation.link)).bearer_auth(&token).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    client.put("/session/org").bearer_auth(&token).json(&json!({ "org_id": org.org.id })).send().await;
+
+    return token;
+}
+
+#[tokio::test]
+async fn the_session_acts_in_one_organization() {
+    let (client, alice, org) = org_with_owner().await;
+
+    let res = client.get("/org").bearer_auth(&alice).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let current: OrgResponse = res.json().await;
+    assert_eq!(current, org);
+
+    let orgs: Value = client.get("/orgs").bearer_auth(&alice).send().await.json().await;
+    assert_eq!(orgs[0]["id"], org.org.id.as_str());
+    assert_eq!(orgs[0]["role"], "owner");
+
+    // Other sessions of the same user aren't affected.
+    let TokenResponse { token: other } = client.post("/login").json(&json!({ "username": "alice", "password": "hunter2" })).send().await.json().await;
+    assert_eq!(client.get("/org").bearer_auth(&other).send().await.status(), StatusCode::FORBIDDEN);
+
+    let res = client.put("/session/org").bearer_auth(&alice).json(&json!({ "org_id": null })).send().await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(client.get("/org").bearer_auth(&alice).send().await.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn only_members_can_switch_to_an_organization() {
+    let (client, _alice, org) = org_with_owner().await;
+    let mallory = sign_up(&client, "mallory").await;
+
+    let res = client.put("/session/org").bearer_auth(&mallory).json(&json!({ "org_id": org.org.id })).send().await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert_eq!(client.get("/org/members").bearer_auth(&mallory).send().await.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn invitation_links_add_members() {
+    let (client, alice, _org) = org_with_owner().await;
+    let bob = sign_up(&client, "bob").await;
+    let invitation = invite(&client, &alice, "admin").await;
+
+    // No session needed to see what it's for.
+    let res = client.get(&invitation.link).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let preview: Value = res.json().await;
+    assert_eq!(preview["org"]["name"], "Acme");
+    assert_eq!(preview["role"], "admin");
+
+    let res = client.post(&format!("{}/accept", invitation.link)).bearer_auth(&bob).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let joined: OrgResponse = res.json().await;
+    assert_eq!(joined.role, OrgRole::Admin);
+
+    // Links work once.
+    let carol = sign_up(&client, "carol").await;
+    let res = client.post(&format!("{}/accept", invitation.link)).bearer_auth(&carol).send().await;
+    assert_eq!(res.status(), StatusCode::GONE);
+
+    let members: Value = client.get("/org/members").bearer_auth(&alice).send().await.json().await;
+    let members: Vec<(&str, &str)> = members.as_array().unwrap().iter()
+        .map(|member| (member["username"].as_str().unwrap(), member["role"].as_str().unwrap()))
+        .collect();
+    assert_eq!(members, [("alice", "owner"), ("bob", "admin")]);
+}
+
+#[tokio::test]
+async fn invitation_emails_are_only_recorded() {
+    let (client, alice, _org) = org_with_owner().await;
+    let res = client.post("/org/invitations").bearer_auth(&alice).json(&json!({ "role": "member", "email": "bob@example.com" })).send().await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let invitation: InvitationResponse = res.json().await;
+    assert_eq!(invitation.email.as_deref(), Some("bob@example.com"));
+
+    // Whoever has the link joins, whatever email their account has.
+    let carol = sign_up(&client, "carol").await;
+    let res = client.post(&format!("{}/accept", invitation.link)).bearer_auth(&carol).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn invitation_links_cant_be_forged() {
+    let (client, alice, _org) = org_with_owner().await;
+    let bob = sign_up(&client, "bob").await;
+    let invitation = invite(&client, &alice, "member").await;
+
+    let (id, signature) = invitation.link.rsplit_once('.').unwrap();
+    let mut forged = signature.to_owned().into_bytes();
+    forged[0] = if forged[0] == b'A' { b'B' } else { b'A' };
+    let forged = format!("{}.{}", id, String::from_utf8(forged).unwrap());
+
+    for link in [forged.as_str(), "/invitations/made-up.AAAA", "/invitations/no-signature"] {
+        assert_eq!(client.get(link).send().await.status(), StatusCode::NOT_FOUND, "{link}");
+        assert_eq!(client.post(&format!("{link}/accept")).bearer_auth(&bob).send().await.status(), StatusCode::NOT_FOUND, "{link}");
+    }
+
+    // Revoked links stop working.
+    let res = client.delete(&format!("/org/invitations/{}", invitation.id)).bearer_auth(&alice).send().await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(client.get(&invitation.link).send().await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn roles_limit_what_members_can_do() {
+    let (client, alice, org) = org_with_owner().await;
+    let bob = join(&client, &alice, &org, "bob", "admin").await;
+    let carol = join(&client, &alice, &org, "carol", "member").await;
+    let members: Value = client.get("/org/members").bearer_auth(&carol).send().await.json().await;
+    let alice_id = members[0]["user_id"].as_str().unwrap().to_owned();
+    let carol_id = members[2]["user_id"].as_str().unwrap().to_owned();
+
+    // Members can look but not invite.
+    let res = client.post("/org/invitations").bearer_auth(&carol).json(&json!({ "role": "member" })).send().await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    assert_eq!(client.get("/org/audit").bearer_auth(&carol).send().await.status(), StatusCode::FORBIDDEN);
+
+    // Admins manage members, but not owners.
+    let res = client.post("/org/invitations").bearer_auth(&bob).json(&json!({ "role": "owner" })).send().await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let res = client.put(&format!("/org/members/{alice_id}")).bearer_auth(&bob).json(&json!({ "role": "member" })).send().await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let res = client.put(&format!("/org/members/{carol_id}")).bearer_auth(&bob).json(&json!({ "role": "admin" })).send().await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = client.delete(&format!("/org/members/{carol_id}")).bearer_auth(&bob).send().await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    // Removed members lose access right away.
+    assert_eq!(client.get("/org").bearer_auth(&carol).send().await.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn organizations_keep_an_owner() {
+    let (client, alice, org) = org_with_owner().await;
+    let bob = join(&client, &alice, &org, "bob", "owner").await;
+
+    let members: Value = client.get("/org/members").bearer_auth(&alice).send().await.json().await;
+    let alice_id = members[0]["user_id"].as_str().unwrap().to_owned();
+    let bob_id = members[1]["user_id"].as_str().unwrap().to_owned();
+
+    let res = client.delete(&format!("/org/members/{bob_id}")).bearer_auth(&bob).send().await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = client.delete(&format!("/org/members/{alice_id}")).bearer_auth(&alice).send().await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    let res = client.put(&format!("/org/members/{alice_id}")).bearer_auth(&alice).json(&json!({ "role": "admin" })).send().await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn the_only_owner_cant_delete_their_account() {
+    let (client, alice, org) = org_with_owner().await;
+    let delete_me = |token: String| client.delete("/me").bearer_auth(token).json(&json!({ "password": "hunter2" })).send();
+
+    let res = delete_me(alice.clone()).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    assert_eq!(client.get("/me").bearer_auth(&alice).send().await.status(), StatusCode::OK);
+
+    let bob = join(&client, &alice, &org, "bob", "owner").await;
+    let members: Value = client.get("/org/members").bearer_auth(&alice).send().await.json().await;
+    let bob_id = members[1]["user_id"].as_str().unwrap().to_owned();
+    assert_eq!(delete_me(alice).await.status(), StatusCode::ACCEPTED);
+
+    // Alice is on her way out, so bob is the only owner now, and stays.
+    let res = client.delete(&format!("/org/members/{bob_id}")).bearer_auth(&bob).send().await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    assert_eq!(delete_me(bob).await.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn the_audit_log_is_scoped_to_the_organization() {
+    let (client, alice, acme) = org_with_owner().await;
+    join(&client, &alice, &acme, "bob", "member").await;
+
+    let eve = sign_up(&client, "eve").await;
+    let res = client.post("/orgs").bearer_auth(&eve).json(&json!({ "name": "Evil Corp" })).send().await;
+    let evil: OrgResponse = res.json().await;
+    client.put("/session/org").bearer_auth(&eve).json(&json!({ "org_id": evil.org.id })).send().await;
+
+    let page: Value = client.get("/org/audit").bearer_auth(&alice).send().await.json().await;
+    let kinds: Vec<&str> = page["events"].as_array().unwrap().iter().map(|event| event["kind"].as_str().unwrap()).collect();
+    assert_eq!(kinds, ["member_added", "invitation_created", "org_created"]);
+
+    // Asking for another organization's events doesn't get around the scope.
+    let page: Value = client.get(&format!("/org/audit?org_id={}", acme.org.id)).bearer_auth(&eve).send().await.json().await;
+    let events = page["events"].as_array().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["org_id"], evil.org.id.as_str());
+
+    let members: Value = client.get("/org/members").bearer_auth(&eve).send().await.json().await;
+    assert_eq!(members.as_array().unwrap().len(), 1);
+}
@@ -0,0 +1,273 @@
+use axum::{http::{header, StatusCode}, test::{MultipartForm, TestClient}};
+use axum_user_jwt_template::{dto::{TokenResponse, UserProfile}, store::UserStore, testing, user::User};
+use serde_json::{json, Value};
+
+async fn logged_in_client() -> (TestClient, String) {
+    let (app, _store) = testing::memory_app();
+    let client = TestClient::new(app);
+
+    let credentials = json!({ "username": "alice", "password": "hunter2" });
+    client.post("/register").json(&credentials).send().await;
+    let res = client.post("/login").json(&credentials).send().await;
+    let TokenResponse { token } = res.json().await;
+
+    return (client, token);
+}
+
+fn assert_no_credentials(profile: &Value) {
+    let profile = profile.as_object().unwrap();
+    for key in ["password_hash", "token", "disabled", "version", "avatar_key"] {
+        assert!(!profile.contains_key(key), "{key} is exposed");
+    }
+    assert!(profile.contains_key("id") && profile.contains_key("username"));
+}
+
+#[tokio::test]
+async fn verify_returns_the_public_profile() {
+    let (client, token) = logged_in_client().await;
+
+    let res = client.post("/verify").json(&json!({ "token": token })).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+
+    let profile: Value = res.json().await;
+    assert_no_credentials(&profile);
+    assert_eq!(profile["username"], "alice");
+    assert_eq!(profile["is_admin"], false);
+}
+
+#[tokio::test]
+async fn me_returns_the_callers_profile() {
+    let (client, token) = logged_in_client().await;
+
+    let res = client.get("/me").bearer_auth(&token).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let profile: Value = res.json().await;
+    assert_no_credentials(&profile);
+
+    let profile: UserProfile = serde_json::from_value(profile).unwrap();
+    assert_eq!(profile.username, "alice");
+
+    assert_eq!(client.get("/me").send().await.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(client.get("/me").bearer_auth("made-up").send().await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn serialized_users_leave_out_the_password_hash() {
+    let user = User::with_cost(&"alice".to_owned(), &"hunter2".to_owned(), 4).unwrap();
+
+    let value = serde_json::to_value(&user).unwrap();
+    assert!(value.get("password_hash").is_none());
+    assert_eq!(value["username"], "alice");
+}
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+async fn profile_with_etag(client: &TestClient, token: &str) -> (Value, String) {
+    let res = client.get("/me").bearer_auth(token).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let etag = res.headers()[header::ETAG].to_str().unwrap().to_owned();
+    return (res.json().await, etag);
+}
+
+#[tokio::test]
+async fn new_users_have_an_empty_profile() {
+    let (client, token) = logged_in_client().await;
+
+    let (profile, _) = profile_with_etag(&client, &token).await;
+    for field in ["display_name", "email", "locale", "timezone", "avatar_url"] {
+        assert!(profile[field].is_null(), "{field}");
+    }
+    assert_eq!(profile["created_at"], profile["updated_at"]);
+}
+
+#[tokio::test]
+async fn patches_merge_into_the_profile() {
+    let (client, token) = logged_in_client().await;
+    let (_, etag) = profile_with_etag(&client, &token).await;
+
+    let res = client
+        .patch("/me")
+        .bearer_auth(&token)
+        .header(header::IF_MATCH, etag.as_str())
+        .header(header::CONTENT_TYPE, "application/merge-patch+json")
+        .body(r#"{"display_name": "Alice", "email": " Alice@Example.com ", "locale": "en-GB", "timezone": "Europe/London"}"#)
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let new_etag = res.headers()[header::ETAG].to_str().unwrap().to_owned();
+    assert_ne!(new_etag, etag);
+
+    let profile: Value = res.json().await;
+    assert_eq!(profile["display_name"], "Alice");
+    assert_eq!(profile["email"], "alice@example.com");
+    assert_eq!(profile["locale"], "en-GB");
+    assert_eq!(profile["timezone"], "Europe/London");
+
+    // Left out stays, null clears.
+    let res = client
+        .patch("/me")
+        .bearer_auth(&token)
+        .header(header::IF_MATCH, new_etag.as_str())
+        .json(&json!({ "locale": null }))
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let (profile, _) = profile_with_etag(&client, &token).await;
+    assert_eq!(profile["display_name"], "Alice");
+    assert!(profile["locale"].is_null());
+    assert_ne!(profile["created_at"], profile["updated_at"]);
+}
+
+#[tokio::test]
+async fn edits_need_the_current_etag() {
+    let (client, token) = logged_in_client().await;
+    let (_, etag) = profile_with_etag(&client, &token).await;
+    let patch = json!({ "display_name": "Alice" });
+
+    let res = client.patch("/me").bearer_auth(&token).json(&patch).send().await;
+    assert_eq!(res.status(), StatusCode::PRECONDITION_REQUIRED);
+
+    let res = client.patch("/me").bearer_auth(&token).header(header::IF_MATCH, etag.as_str()).json(&patch).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // A second client still holding the old ETag doesn't overwrite the change.
+    let res = client
+        .patch("/me")
+        .bearer_auth(&token)
+        .header(header::IF_MATCH, etag.as_str())
+        .json(&json!({ "display_name": "Mallory" }))
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+
+    let (profile, current) = profile_with_etag(&client, &token).await;
+    assert_eq!(profile["display_name"], "Alice");
+
+    let res = client.get("/me").bearer_auth(&token).header(header::IF_NONE_MATCH, current.as_str()).send().await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn invalid_patches_are_rejected() {
+    let (client, token) = logged_in_client().await;
+    let (_, etag) = profile_with_etag(&client, &token).await;
+
+    for (patch, field) in [
+        (json!({ "email": "not an address" }), "email"),
+        (json!({ "locale": "english please" }), "locale"),
+        (json!({ "timezone": "../../etc/passwd" }), "timezone"),
+        (json!({ "display_name": "x".repeat(101) }), "display_name")
+    ] {
+        let res = client.patch("/me").bearer_auth(&token).header(header::IF_MATCH, etag.as_str()).json(&patch).send().await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY, "{patch}");
+        let err: Value = res.json().await;
+        assert_eq!(err["field"], field);
+    }
+
+    // Fields that aren't editable are an error rather than silently ignored.
+    let res = client.patch("/me").bearer_auth(&token).header(header::IF_MATCH, etag.as_str()).json(&json!({ "username": "root" })).send().await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (_, unchanged) = profile_with_etag(&client, &token).await;
+    assert_eq!(unchanged, etag);
+}
+
+#[tokio::test]
+async fn emails_are_unique() {
+    let (client, alice) = logged_in_client().await;
+
+    let credentials = json!({ "username": "bob", "password": "hunter2" });
+    client.post("/register").json(&credentials).send().await;
+    let TokenResponse { token: bob } = client.post("/login").json(&credentials).send().await.json().await;
+
+    let patch = json!({ "email": "shared@example.com" });
+    for token in [&alice, &bob] {
+        let (_, etag) = profile_with_etag(&client, token).await;
+        let res = client.patch("/me").bearer_auth(token).header(header::IF_MATCH, etag.as_str()).json(&patch).send().await;
+
+        let expected = if token == &alice { StatusCode::OK } else { StatusCode::CONFLICT };
+        assert_eq!(res.status(), expected);
+    }
+}
+
+#[tokio::test]
+async fn uploads_serves_and_deletes_avatars() {
+    let (client, token) = logged_in_client().await;
+
+    let form = MultipartForm::new().file("avatar", "me.png", "image/png", PNG);
+    let res = client.put("/me/avatar").bearer_auth(&token).multipart(form).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let profile: Value = res.json().await;
+    let avatar_url = profile["avatar_url"].as_str().unwrap().to_owned();
+
+    // No session needed, so it works in an <img> tag.
+    let res = client.get(&avatar_url).send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "image/png");
+    assert_eq!(res.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    assert_eq!(&res.bytes().await[..], PNG);
+
+    let res = client.delete("/me/avatar").bearer_auth(&token).send().await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(client.get(&avatar_url).send().await.status(), StatusCode::NOT_FOUND);
+
+    let (profile, _) = profile_with_etag(&client, &token).await;
+    assert!(profile["avatar_url"].is_null());
+}
+
+#[tokio::test]
+async fn avatars_of_disabled_and_deleted_accounts_are_gone() {
+    let (app, store) = testing::memory_app();
+    let client = TestClient::new(app);
+
+    let mut avatar_urls = Vec::new();
+    for username in ["alice", "bob"] {
+        let credentials = json!({ "username": username, "password": "hunter2" });
+        client.post("/register").json(&credentials).send().await;
+        let TokenResponse { token } = client.post("/login").json(&credentials).send().await.json().await;
+
+        let form = MultipartForm::new().file("avatar", "me.png", "image/png", PNG);
+        let profile: Value = client.put("/me/avatar").bearer_auth(&token).multipart(form).send().await.json().await;
+        let avatar_url = profile["avatar_url"].as_str().unwrap().to_owned();
+        assert_eq!(client.get(&avatar_url).send().await.status(), StatusCode::OK);
+
+        avatar_urls.push((token, profile["id"].as_str().unwrap().to_owned(), avatar_url));
+    }
+
+    let (_, alice_id, alice_avatar) = &avatar_urls[0];
+    store.set_user_disabled(alice_id, true).await.unwrap();
+    assert_eq!(client.get(alice_avatar).send().await.status(), StatusCode::NOT_FOUND);
+
+    let (bob_token, _, bob_avatar) = &avatar_urls[1];
+    let res = client.delete("/me").bearer_auth(bob_token).json(&json!({ "password": "hunter2" })).send().await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    assert_eq!(client.get(bob_avatar).send().await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn avatars_have_to_be_small_images() {
+    let (client, token) = logged_in_client().await;
+
+    let form = MultipartForm::new().file("avatar", "me.svg", "image/svg+xml", "<svg/>");
+    let res = client.put("/me/avatar").bearer_auth(&token).multipart(form).send().await;
+    assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    // Claiming to be a PNG isn't enough.
+    let form = MultipartForm::new().file("avatar", "me.png", "image/png", "<html>");
+    let res = client.put("/me/avatar").bearer_auth(&token).multipart(form).send().await;
+    assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let mut huge = PNG.to_vec();
+    huge.resize(testing::test_config().avatar_max_bytes + 1, 0);
+    let form = MultipartForm::new().file("avatar", "me.png", "image/png", huge);
+    let res = client.put("/me/avatar").bearer_auth(&token).multipart(form).send().await;
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let (profile, _) = profile_with_etag(&client, &token).await;
+    assert!(profile["avatar_url"].is_null());
+}
@@ -0,0 +1,153 @@
+//! End-to-end scenarios over the whole router, each against a temporary database of its own.
+
+use axum::{http::{header, StatusCode}, test::TestClient};
+use axum_user_jwt_template::{store::UserStore, testing::{self, TempDatabase}};
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+
+async fn client() -> (TestClient, TempDatabase) {
+    let (app, db) = testing::temp_sqlite_app().await;
+
+    return (TestClient::new(app), db);
+}
+
+async fn register(client: &TestClient, username: &str, password: &str) -> StatusCode {
+    return client
+        .post("/register")
+        .json(&json!({ "username": username, "password": password }))
+        .send()
+        .await
+        .status();
+}
+
+async fn login(client: &TestClient, username: &str, password: &str) -> Result<String, StatusCode> {
+    let res = client
+        .post("/login")
+        .json(&json!({ "username": username, "password": password }))
+        .send()
+        .await;
+
+    if res.status() != StatusCode::OK {
+        return Err(res.status());
+    }
+
+    let body: Value = res.json().await;
+    return Ok(body["token"].as_str().unwrap().to_owned());
+}
+
+async fn verify(client: &TestClient, token: &str) -> StatusCode {
+    return client.post("/verify").json(&json!({ "token": token })).send().await.status();
+}
+
+async fn logout(client: &TestClient, token: &str) -> StatusCode {
+    return client.post("/logout").bearer_auth(token).send().await.status();
+}
+
+#[tokio::test]
+async fn register_login_verify_logout() {
+    let (client, _db) = client().await;
+
+    assert_eq!(register(&client, "alice", "hunter2").await, StatusCode::OK);
+
+    let token = login(&client, "alice", "hunter2").await.unwrap();
+    assert_eq!(verify(&client, &token).await, StatusCode::OK);
+
+    assert_eq!(logout(&client, &token).await, StatusCode::NO_CONTENT);
+    assert_eq!(verify(&client, &token).await, StatusCode::UNAUTHORIZED);
+
+    // The token is spent, logging out twice doesn't work either.
+    assert_eq!(logout(&client, &token).await, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn logout_only_ends_its_own_session() {
+    let (client, _db) = client().await;
+    register(&client, "alice", "hunter2").await;
+
+    let laptop = login(&client, "alice", "hunter2").await.unwrap();
+    let phone = login(&client, "alice", "hunter2").await.unwrap();
+
+    assert_eq!(logout(&client, &laptop).await, StatusCode::NO_CONTENT);
+    assert_eq!(verify(&client, &laptop).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(verify(&client, &phone).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn expired_sessions_are_rejected() {
+    let (client, db) = client().await;
+    register(&client, "alice", "hunter2").await;
+
+    let token = login(&client, "alice", "hunter2").await.unwrap();
+    assert_eq!(verify(&client, &token).await, StatusCode::OK);
+
+    sqlx::query("UPDATE sessions SET valid_to = ? WHERE token = ?;")
+        .bind(Utc::now() - Duration::minutes(1))
+        .bind(&token)
+        .execute(&db.store.db)
+        .await
+        .unwrap();
+
+    assert_eq!(verify(&client, &token).await, StatusCode::UNAUTHORIZED);
+
+    // Logging in again gives a fresh session.
+    let token = login(&client, "alice", "hunter2").await.unwrap();
+    assert_eq!(verify(&client, &token).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn duplicate_usernames_are_rejected() {
+    let (client, db) = client().await;
+
+    assert_eq!(register(&client, "alice", "hunter2").await, StatusCode::OK);
+    assert_eq!(register(&client, "alice", "something else").await, StatusCode::UNAUTHORIZED);
+
+    assert_eq!(db.store.list_users().await.unwrap().len(), 1);
+
+    // The first registration still owns the name.
+    assert!(login(&client, "alice", "hunter2").await.is_ok());
+    assert_eq!(login(&client, "alice", "something else").await, Err(StatusCode::UNAUTHORIZED));
+}
+
+#[tokio::test]
+async fn wrong_passwords_and_unknown_users_are_rejected() {
+    let (client, _db) = client().await;
+    register(&client, "alice", "hunter2").await;
+
+    assert_eq!(login(&client, "alice", "hunter3").await, Err(StatusCode::UNAUTHORIZED));
+    assert_eq!(login(&client, "alice", "").await, Err(StatusCode::UNAUTHORIZED));
+    assert_eq!(login(&client, "bob", "hunter2").await, Err(StatusCode::UNAUTHORIZED));
+
+    assert!(login(&client, "alice", "hunter2").await.is_ok());
+}
+
+#[tokio::test]
+async fn made_up_tokens_are_rejected() {
+    let (client, _db) = client().await;
+
+    assert_eq!(verify(&client, "not-a-token").await, StatusCode::UNAUTHORIZED);
+    assert_eq!(logout(&client, "not-a-token").await, StatusCode::UNAUTHORIZED);
+    assert_eq!(client.post("/logout").send().await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn malformed_json_is_rejected() {
+    let (client, db) = client().await;
+
+    for uri in ["/register", "/login", "/verify"] {
+        let res = client
+            .post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(r#"{"username": "alice""#)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{uri}");
+
+        let res = client.post(uri).json(&json!({ "unexpected": true })).send().await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY, "{uri}");
+
+        let res = client.post(uri).body(r#"{"username":"alice","password":"hunter2","token":"x"}"#).send().await;
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE, "{uri}");
+    }
+
+    assert!(db.store.list_users().await.unwrap().is_empty());
+}
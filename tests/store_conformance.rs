@@ -0,0 +1,561 @@
+//! Behaviour every `Store` backend has to share, run against each implementation.
+
+use axum_user_jwt_template::{
+    audit::{AuditContext, AuditFilter, AuditKind, AuditScope},
+    org::{AcceptInvitationResult, Invitation, Membership, OrgRole, Organization},
+    store::{AuditStore, MemoryStore, SqliteStore, Store},
+    user::{self, AddUserResult, DeviceSeen, GetTokenUserResult, Session, UpdateProfileResult, UpdateUserResult, User},
+};
+use chrono::{Duration, Utc};
+
+async fn sqlite_store() -> SqliteStore {
+    let path = std::env::temp_dir().join(format!("conformance-{}.db", uuid::Uuid::new_v4()));
+    SqliteStore::connect(path.to_str().unwrap()).await.unwrap()
+}
+
+async fn registered_user(store: &dyn Store, username: &str, password: &str) -> User {
+    let user = User::with_cost(&username.to_owned(), &password.to_owned(), 4).unwrap();
+    assert_eq!(user.add_to_database(store).await, AddUserResult::Success);
+    user
+}
+
+async fn registers_and_finds_users(store: &dyn Store) {
+    let user = registered_user(store, "alice", "hunter2").await;
+
+    let by_id = store.get_user_by_id(&user.id).await.unwrap().unwrap();
+    assert_eq!(by_id.username, "alice");
+
+    let by_name = store.get_user_by_username("alice").await.unwrap().unwrap();
+    assert_eq!(by_name.id, user.id);
+
+    assert!(store.get_user_by_username("bob").await.unwrap().is_none());
+}
+
+async fn rejects_duplicate_usernames(store: &dyn Store) {
+    registered_user(store, "alice", "hunter2").await;
+
+    let result = user::register_user(&"alice".to_owned(), &"other".to_owned(), 4, store).await;
+    assert_eq!(result, AddUserResult::UsernameTaken);
+}
+
+async fn logs_in_with_correct_password_only(store: &dyn Store) {
+    registered_user(store, "alice", "hunter2").await;
+
+    let alice = "alice".to_owned();
+    assert!(user::login_user(&alice, &"hunter2".to_owned(), store).await.is_some());
+    assert!(user::login_user(&alice, &"wrong".to_owned(), store).await.is_none());
+    assert!(user::login_user(&"bob".to_owned(), &"hunter2".to_owned(), store).await.is_none());
+}
+
+async fn resolves_session_tokens(store: &dyn Store) {
+    let user = registered_user(store, "alice", "hunter2").await;
+
+    let session = Session::new(&user);
+    session.add_to_database(store).await.unwrap();
+
+    let stored = store.get_session_by_token(&session.token).await.unwrap().unwrap();
+    assert_eq!(stored.id, session.id);
+    assert_eq!(stored.user_id, user.id);
+
+    match Session::get_token_user(&session.token, store).await {
+        GetTokenUserResult::Success(found) => assert_eq!(found.id, user.id),
+        _ => panic!("expected the session's user"),
+    }
+
+    assert!(matches!(
+        Session::get_token_user(&"missing".to_owned(), store).await,
+        GetTokenUserResult::NotFound
+    ));
+}
+
+async fn lists_users_by_username(store: &dyn Store) {
+    registered_user(store, "carol", "hunter2").await;
+    registered_user(store, "alice", "hunter2").await;
+
+    let names: Vec<String> = store
+        .list_users()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|user| user.username)
+        .collect();
+    assert_eq!(names, ["alice", "carol"]);
+}
+
+async fn password_change_revokes_sessions(store: &dyn Store) {
+    let user = registered_user(store, "alice", "hunter2").await;
+    let session = Session::new(&user);
+    session.add_to_database(store).await.unwrap();
+
+    let alice = "alice".to_owned();
+    let result = user::set_password(&alice, &"correct horse".to_owned(), 4, store).await;
+    assert_eq!(result, UpdateUserResult::Success);
+
+    assert!(user::login_user(&alice, &"hunter2".to_owned(), store).await.is_none());
+    assert!(user::login_user(&alice, &"correct horse".to_owned(), store).await.is_some());
+    assert!(matches!(
+        Session::get_token_user(&session.token, store).await,
+        GetTokenUserResult::Unauthorized
+    ));
+}
+
+async fn disabled_users_cannot_log_in(store: &dyn Store) {
+    let user = registered_user(store, "alice", "hunter2").await;
+    let session = Session::new(&user);
+    session.add_to_database(store).await.unwrap();
+
+    let alice = "alice".to_owned();
+    assert_eq!(user::disable_user(&alice, store).await, UpdateUserResult::Success);
+
+    assert!(user::login_user(&alice, &"hunter2".to_owned(), store).await.is_none());
+    assert!(matches!(
+        Session::get_token_user(&session.token, store).await,
+        GetTokenUserResult::Unauthorized
+    ));
+    assert_eq!(
+        user::disable_user(&"bob".to_owned(), store).await,
+        UpdateUserResult::NotFound
+    );
+}
+
+async fn revokes_and_purges_sessions(store: &dyn Store) {
+    let user = registered_user(store, "alice", "hunter2").await;
+
+    let current = Session::new(&user);
+    current.add_to_database(store).await.unwrap();
+
+    let mut expired = Session::new(&user);
+    expired.valid_to = Utc::now() - Duration::hours(1);
+    expired.add_to_database(store).await.unwrap();
+
+    assert!(matches!(
+        Session::get_token_user(&expired.token, store).await,
+        GetTokenUserResult::Unauthorized
+    ));
+
+    assert_eq!(store.delete_expired_sessions(Utc::now()).await.unwrap(), 1);
+    assert!(store.get_session_by_token(&expired.token).await.unwrap().is_none());
+
+    assert!(store.disable_session(&current.token).await.unwrap());
+    assert!(!store.disable_session("missing").await.unwrap());
+    assert!(matches!(
+        Session::get_token_user(&current.token, store).await,
+        GetTokenUserResult::Unauthorized
+    ));
+}
+
+async fn deletes_stale_sessions_in_batches(store: &dyn Store) {
+    let user = registered_user(store, "alice", "hunter2").await;
+
+    let current = Session::new(&user);
+    current.add_to_database(store).await.unwrap();
+
+    for _ in 0..3 {
+        let mut expired = Session::new(&user);
+        expired.valid_to = Utc::now() - Duration::hours(1);
+        expired.add_to_database(store).await.unwrap();
+    }
+
+    let mut disabled = Session::new(&user);
+    disabled.disabled = 1;
+    disabled.add_to_database(store).await.unwrap();
+
+    assert_eq!(store.delete_stale_sessions(Utc::now(), 3).await.unwrap(), 3);
+    assert_eq!(store.delete_stale_sessions(Utc::now(), 3).await.unwrap(), 1);
+    assert_eq!(store.delete_stale_sessions(Utc::now(), 3).await.unwrap(), 0);
+
+    assert!(store.get_session_by_token(&current.token).await.unwrap().is_some());
+}
+
+async fn counts_active_sessions(store: &dyn Store) {
+    let user = registered_user(store, "alice", "hunter2").await;
+
+    for _ in 0..2 {
+        Session::new(&user).add_to_database(store).await.unwrap();
+    }
+
+    let mut expired = Session::new(&user);
+    expired.valid_to = Utc::now() - Duration::hours(1);
+    expired.add_to_database(store).await.unwrap();
+
+    let mut disabled = Session::new(&user);
+    disabled.disabled = 1;
+    disabled.add_to_database(store).await.unwrap();
+
+    assert_eq!(store.count_active_sessions(Utc::now()).await.unwrap(), 2);
+}
+
+async fn records_and_filters_audit_events(store: &dyn Store) {
+    let context = AuditContext {
+        ip: Some("192.0.2.1".to_owned()),
+        user_agent: Some("curl/8.0".to_owned()),
+        request_id: Some("req-1".to_owned()),
+        impersonator_id: None,
+    };
+
+    context.event(AuditKind::LoginFailed).target("alice-id").detail("reason", "wrong_password").record(store).await;
+    context.event(AuditKind::LoginSucceeded).actor("alice-id").target("alice-id").record(store).await;
+    AuditContext::cli().event(AuditKind::AccountDisabled).target("bob-id").record(store).await;
+
+    let all = store.list_audit_events(&AuditFilter::default(), 10).await.unwrap();
+    let kinds: Vec<&str> = all.iter().map(|event| event.kind.as_str()).collect();
+    assert_eq!(kinds, ["account_disabled", "login_succeeded", "login_failed"]);
+
+    let failed = &all[2];
+    assert_eq!(failed.target_id.as_deref(), Some("alice-id"));
+    assert_eq!(failed.actor_id, None);
+    assert_eq!(failed.ip.as_deref(), Some("192.0.2.1"));
+    assert_eq!(failed.user_agent.as_deref(), Some("curl/8.0"));
+    assert_eq!(failed.request_id.as_deref(), Some("req-1"));
+    assert_eq!(failed.details, r#"{"reason":"wrong_password"}"#);
+
+    let alice = AuditFilter { target_id: Some("alice-id".to_owned()), ..AuditFilter::default() };
+    assert_eq!(store.list_audit_events(&alice, 10).await.unwrap().len(), 2);
+
+    let logins = AuditFilter { kind: Some("login_succeeded".to_owned()), ..AuditFilter::default() };
+    assert_eq!(store.list_audit_events(&logins, 10).await.unwrap().len(), 1);
+
+    let future = AuditFilter { since: Some(Utc::now() + Duration::hours(1)), ..AuditFilter::default() };
+    assert!(store.list_audit_events(&future, 10).await.unwrap().is_empty());
+
+    // Paging backwards with the cursor.
+    let first_page = store.list_audit_events(&AuditFilter::default(), 2).await.unwrap();
+    assert_eq!(first_page.len(), 2);
+    let next = AuditFilter { before: Some(first_page[1].id), ..AuditFilter::default() };
+    let second_page = store.list_audit_events(&next, 2).await.unwrap();
+    assert_eq!(second_page.len(), 1);
+    assert_eq!(second_page[0].kind, "login_failed");
+}
+
+async fn updates_profiles_with_optimistic_concurrency(store: &dyn Store) {
+    let alice = registered_user(store, "alice", "hunter2").await;
+    let bob = registered_user(store, "bob", "hunter2").await;
+
+    let mut edited = alice.clone();
+    edited.display_name = Some("Alice".to_owned());
+    edited.email = Some("alice@example.com".to_owned());
+    edited.version = 2;
+    assert_eq!(store.update_profile(&edited, 1).await, UpdateProfileResult::Success);
+
+    let stored = store.get_user_by_id(&alice.id).await.unwrap().unwrap();
+    assert_eq!(stored.display_name.as_deref(), Some("Alice"));
+    assert_eq!(stored.email.as_deref(), Some("alice@example.com"));
+    assert_eq!(stored.version, 2);
+
+    // Written against the version before the edit.
+    let mut stale = alice.clone();
+    stale.display_name = Some("Mallory".to_owned());
+    stale.version = 2;
+    assert_eq!(store.update_profile(&stale, 1).await, UpdateProfileResult::Conflict);
+    assert_eq!(store.get_user_by_id(&alice.id).await.unwrap().unwrap().display_name.as_deref(), Some("Alice"));
+
+    let mut taken = bob.clone();
+    taken.email = Some("alice@example.com".to_owned());
+    taken.version = 2;
+    assert_eq!(store.update_profile(&taken, 1).await, UpdateProfileResult::EmailTaken);
+    assert_eq!(store.get_user_by_id(&bob.id).await.unwrap().unwrap().version, 1);
+}
+
+async fn soft_deletes_and_purges_users(store: &dyn Store) {
+    let alice = registered_user(store, "alice", "hunter2").await;
+    let bob = registered_user(store, "bob", "hunter2").await;
+    Session::new(&alice).add_to_database(store).await.unwrap();
+    Session::new(&alice).add_to_database(store).await.unwrap();
+    Session::new(&bob).add_to_database(store).await.unwrap();
+
+    assert_eq!(store.list_user_sessions(&alice.id).await.unwrap().len(), 2);
+
+    let deleted_at = Utc::now() - Duration::days(2);
+    assert!(store.mark_user_deleted(&alice.id, Some(deleted_at)).await.unwrap());
+    assert!(!store.mark_user_deleted("nobody", Some(deleted_at)).await.unwrap());
+    assert!(store.get_user_by_id(&alice.id).await.unwrap().unwrap().deleted_at.is_some());
+
+    // Only accounts deleted before the cutoff are due.
+    assert!(store.list_users_deleted_before(Utc::now() - Duration::days(3), 10).await.unwrap().is_empty());
+    let due = store.list_users_deleted_before(Utc::now() - Duration::days(1), 10).await.unwrap();
+    assert_eq!(due.iter().map(|user| user.id.as_str()).collect::<Vec<_>>(), [alice.id.as_str()]);
+
+    // Restoring clears the mark.
+    assert!(store.mark_user_deleted(&alice.id, None).await.unwrap());
+    assert!(store.get_user_by_id(&alice.id).await.unwrap().unwrap().deleted_at.is_none());
+    assert!(store.list_users_deleted_before(Utc::now(), 10).await.unwrap().is_empty());
+
+    assert!(store.delete_user(&alice.id).await.unwrap());
+    assert!(!store.delete_user(&alice.id).await.unwrap());
+    assert!(store.get_user_by_id(&alice.id).await.unwrap().is_none());
+    assert!(store.list_user_sessions(&alice.id).await.unwrap().is_empty());
+    assert_eq!(store.list_user_sessions(&bob.id).await.unwrap().len(), 1);
+}
+
+async fn manages_organizations_and_invitations(store: &dyn Store) {
+    let alice = registered_user(store, "alice", "hunter2").await;
+    let bob = registered_user(store, "bob", "hunter2").await;
+
+    let acme = Organization::new("Acme");
+    store.add_org(&acme, &Membership::new(&acme.id, &alice.id, OrgRole::Owner)).await.unwrap();
+    let other = Organization::new("Other");
+    store.add_org(&other, &Membership::new(&other.id, &bob.id, OrgRole::Owner)).await.unwrap();
+
+    assert_eq!(store.get_org(&acme.id).await.unwrap().unwrap().name, "Acme");
+    let orgs = store.list_user_orgs(&alice.id).await.unwrap();
+    assert_eq!(orgs.len(), 1);
+    assert_eq!((orgs[0].org.id.as_str(), orgs[0].role), (acme.id.as_str(), OrgRole::Owner));
+
+    let invitation = Invitation::new(&acme.id, OrgRole::Admin, None, &alice.id, Utc::now() + Duration::hours(1));
+    store.add_invitation(&invitation).await.unwrap();
+    assert_eq!(store.get_invitation(&invitation.id).await.unwrap().unwrap(), invitation);
+    assert_eq!(store.list_pending_invitations(&acme.id).await.unwrap().len(), 1);
+    assert!(store.list_pending_invitations(&other.id).await.unwrap().is_empty());
+    // Other organizations can't revoke it.
+    assert!(!store.delete_invitation(&other.id, &invitation.id).await.unwrap());
+
+    // Alice already is a member, which leaves the invitation usable.
+    let membership = Membership::new(&acme.id, &alice.id, OrgRole::Admin);
+    assert_eq!(store.accept_invitation(&invitation.id, &membership).await, AcceptInvitationResult::AlreadyMember);
+
+    let membership = Membership::new(&acme.id, &bob.id, OrgRole::Admin);
+    assert_eq!(store.accept_invitation(&invitation.id, &membership).await, AcceptInvitationResult::Success);
+    assert_eq!(store.accept_invitation(&invitation.id, &membership).await, AcceptInvitationResult::Unavailable);
+    assert!(store.list_pending_invitations(&acme.id).await.unwrap().is_empty());
+
+    let members = store.list_org_members(&acme.id).await.unwrap();
+    let members: Vec<(&str, OrgRole)> = members.iter().map(|member| (member.user.username.as_str(), member.role)).collect();
+    assert_eq!(members, [("alice", OrgRole::Owner), ("bob", OrgRole::Admin)]);
+
+    assert!(store.set_member_role(&acme.id, &bob.id, OrgRole::Member).await.unwrap());
+    assert_eq!(store.get_membership(&acme.id, &bob.id).await.unwrap().unwrap().role, OrgRole::Member);
+
+    // Leaving takes the organization off the member's sessions.
+    let session = Session::new(&bob);
+    session.add_to_database(store).await.unwrap();
+    assert!(store.set_session_org(&session.token, Some(&acme.id)).await.unwrap());
+    assert_eq!(store.get_session_by_token(&session.token).await.unwrap().unwrap().org_id.as_deref(), Some(acme.id.as_str()));
+
+    assert!(store.delete_membership(&acme.id, &bob.id).await.unwrap());
+    assert!(!store.delete_membership(&acme.id, &bob.id).await.unwrap());
+    assert!(store.get_membership(&acme.id, &bob.id).await.unwrap().is_none());
+    assert!(store.get_session_by_token(&session.token).await.unwrap().unwrap().org_id.is_none());
+
+    // Memberships go with the account.
+    assert!(store.delete_user(&alice.id).await.unwrap());
+    assert!(store.list_org_members(&acme.id).await.unwrap().is_empty());
+}
+
+async fn scopes_audit_events_by_organization(store: &dyn Store) {
+    let alice = registered_user(store, "alice", "hunter2").await;
+    let bob = registered_user(store, "bob", "hunter2").await;
+    let acme = Organization::new("Acme");
+    store.add_org(&acme, &Membership::new(&acme.id, &alice.id, OrgRole::Owner)).await.unwrap();
+    let other = Organization::new("Other");
+    store.add_org(&other, &Membership::new(&other.id, &bob.id, OrgRole::Owner)).await.unwrap();
+
+    let cli = AuditContext::cli();
+    cli.event(AuditKind::LoginSucceeded).actor(&alice.id).target(&alice.id).record(store).await;
+    cli.event(AuditKind::OrgCreated).actor(&alice.id).org(&acme.id).record(store).await;
+    cli.event(AuditKind::OrgCreated).actor(&bob.id).org(&other.id).record(store).await;
+    cli.event(AuditKind::LoginFailed).detail("reason", "unknown_user").record(store).await;
+    cli.event(AuditKind::UserCreated).target("carol-id").record(store).await;
+
+    let kinds = |scope: AuditScope| async move {
+        let filter = AuditFilter { scope, ..AuditFilter::default() };
+        let events = store.list_audit_events(&filter, 10).await.unwrap();
+        events.iter().map(|event| event.kind.clone()).collect::<Vec<_>>()
+    };
+
+    assert_eq!(kinds(AuditScope::Tenant(acme.id.clone())).await, ["org_created", "login_succeeded"]);
+    assert_eq!(kinds(AuditScope::Tenant(other.id.clone())).await, ["org_created"]);
+    assert_eq!(kinds(AuditScope::Unowned).await, ["user_created", "login_failed"]);
+    assert_eq!(kinds(AuditScope::All).await.len(), 5);
+}
+
+async fn keeps_impersonation_sessions_apart(store: &dyn Store) {
+    let mut admin = User::with_cost(&"root".to_owned(), &"toor".to_owned(), 4).unwrap();
+    admin.is_admin = 1;
+    assert_eq!(admin.add_to_database(store).await, AddUserResult::Success);
+    let alice = registered_user(store, "alice", "hunter2").await;
+
+    assert!(store.set_user_can_impersonate(&admin.id, true).await.unwrap());
+    assert_eq!(store.get_user_by_id(&admin.id).await.unwrap().unwrap().can_impersonate, 1);
+
+    let session = Session::impersonating(&alice, &admin, Utc::now() + Duration::minutes(30), 32);
+    session.add_to_database(store).await.unwrap();
+
+    let stored = store.get_session_by_token(&session.token).await.unwrap().unwrap();
+    assert_eq!(stored.user_id, alice.id);
+    assert_eq!(stored.impersonator_id.as_deref(), Some(admin.id.as_str()));
+    assert!(matches!(Session::get_token_user(&session.token, store).await, GetTokenUserResult::Success(user) if user.id == alice.id));
+
+    // Only as long as the admin keeps the permission.
+    store.set_user_can_impersonate(&admin.id, false).await.unwrap();
+    assert!(matches!(Session::get_token_user(&session.token, store).await, GetTokenUserResult::Unauthorized));
+
+    let context = AuditContext { impersonator_id: Some(admin.id.clone()), ..AuditContext::default() };
+    context.event(AuditKind::ProfileUpdated).actor(&alice.id).target(&alice.id).record(store).await;
+    AuditContext::cli().event(AuditKind::ProfileUpdated).actor(&alice.id).target(&alice.id).record(store).await;
+
+    let by_admin = AuditFilter { impersonator_id: Some(admin.id.clone()), ..AuditFilter::default() };
+    let events = store.list_audit_events(&by_admin, 10).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].impersonator_id.as_deref(), Some(admin.id.as_str()));
+
+    // The session goes with the admin's account.
+    assert!(store.delete_user(&admin.id).await.unwrap());
+    assert!(store.get_session_by_token(&session.token).await.unwrap().is_none());
+}
+
+async fn remembers_devices_and_where_sessions_came_from(store: &dyn Store) {
+    let alice = registered_user(store, "alice", "hunter2").await;
+    let bob = registered_user(store, "bob", "hunter2").await;
+
+    let mut session = Session::new(&alice);
+    session.user_agent = Some("curl/8.0".to_owned());
+    session.ip = Some("192.0.2.7".to_owned());
+    session.device_id = Some("laptop".to_owned());
+    session.bound_range = Some("192.0.2.0/24".to_owned());
+    session.add_to_database(store).await.unwrap();
+
+    let stored = store.get_session_by_token(&session.token).await.unwrap().unwrap();
+    assert_eq!(stored.user_agent.as_deref(), Some("curl/8.0"));
+    assert_eq!(stored.ip.as_deref(), Some("192.0.2.7"));
+    assert_eq!(stored.device_id.as_deref(), Some("laptop"));
+    assert_eq!(stored.bound_range.as_deref(), Some("192.0.2.0/24"));
+    assert_eq!(store.list_user_sessions(&alice.id).await.unwrap()[0].ip.as_deref(), Some("192.0.2.7"));
+
+    let now = Utc::now();
+    assert_eq!(store.remember_device(&alice.id, "laptop", now).await.unwrap(), DeviceSeen::First);
+    assert_eq!(store.remember_device(&alice.id, "laptop", now).await.unwrap(), DeviceSeen::Known);
+    assert_eq!(store.remember_device(&alice.id, "phone", now).await.unwrap(), DeviceSeen::New);
+    // Devices are per user.
+    assert_eq!(store.remember_device(&bob.id, "laptop", now).await.unwrap(), DeviceSeen::First);
+
+    // Known devices don't keep the account from being removed.
+    assert!(store.delete_user(&alice.id).await.unwrap());
+}
+
+async fn is_healthy_and_fully_migrated(store: &dyn Store) {
+    store.ping().await.unwrap();
+
+    assert_eq!(store.schema_version().await.unwrap(), store.expected_schema_version());
+}
+
+macro_rules! conformance_tests {
+    ($backend:ident, $store:expr) => {
+        mod $backend {
+            use super::*;
+
+            #[tokio::test]
+            async fn registers_and_finds_users() {
+                super::registers_and_finds_users(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn rejects_duplicate_usernames() {
+                super::rejects_duplicate_usernames(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn logs_in_with_correct_password_only() {
+                super::logs_in_with_correct_password_only(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn resolves_session_tokens() {
+                super::resolves_session_tokens(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn lists_users_by_username() {
+                super::lists_users_by_username(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn password_change_revokes_sessions() {
+                super::password_change_revokes_sessions(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn disabled_users_cannot_log_in() {
+                super::disabled_users_cannot_log_in(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn revokes_and_purges_sessions() {
+                super::revokes_and_purges_sessions(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn deletes_stale_sessions_in_batches() {
+                super::deletes_stale_sessions_in_batches(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn counts_active_sessions() {
+                super::counts_active_sessions(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn records_and_filters_audit_events() {
+                super::records_and_filters_audit_events(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn updates_profiles_with_optimistic_concurrency() {
+                super::updates_profiles_with_optimistic_concurrency(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn soft_deletes_and_purges_users() {
+                super::soft_deletes_and_purges_users(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn manages_organizations_and_invitations() {
+                super::manages_organizations_and_invitations(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn scopes_audit_events_by_organization() {
+                super::scopes_audit_events_by_organization(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn keeps_impersonation_sessions_apart() {
+                super::keeps_impersonation_sessions_apart(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn remembers_devices_and_where_sessions_came_from() {
+                super::remembers_devices_and_where_sessions_came_from(&$store).await;
+            }
+
+            #[tokio::test]
+            async fn is_healthy_and_fully_migrated() {
+                super::is_healthy_and_fully_migrated(&$store).await;
+            }
+        }
+    };
+}
+
+conformance_tests!(sqlite, sqlite_store().await);
+conformance_tests!(
+    sqlite_memory,
+    SqliteStore::connect("sqlite::memory:").await.unwrap()
+);
+conformance_tests!(memory, MemoryStore::new());
+
+#[tokio::test]
+async fn sqlite_audit_events_are_append_only() {
+    let store = SqliteStore::connect("sqlite::memory:").await.unwrap();
+    let context = AuditContext { ip: Some("192.0.2.1".to_owned()), ..AuditContext::cli() };
+    context.event(AuditKind::UserCreated).target("alice-id").record(&store).await;
+
+    assert!(sqlx::query("UPDATE audit_events SET target_id = 'bob-id';").execute(&store.db).await.is_err());
+    assert!(sqlx::query("DELETE FROM audit_events;").execute(&store.db).await.is_err());
+
+    // Clearing the address and browser is the one change allowed, and only on its own.
+    assert!(sqlx::query("UPDATE audit_events SET ip = '192.0.2.1';").execute(&store.db).await.is_err());
+    assert!(sqlx::query("UPDATE audit_events SET ip = NULL, user_agent = NULL, kind = 'user_deleted';").execute(&store.db).await.is_err());
+    assert_eq!(store.anonymize_audit_events("alice-id").await.unwrap(), 1);
+
+    assert_eq!(store.list_audit_events(&AuditFilter::default(), 10).await.unwrap().len(), 1);
+}
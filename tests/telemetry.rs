@@ -0,0 +1,56 @@
+use axum::{body::Body, http::{Request, StatusCode}};
+use axum_user_jwt_template::{telemetry::REQUEST_ID_HEADER, testing};
+use tower::ServiceExt;
+
+fn verify_request() -> axum::http::request::Builder {
+    return Request::post("/verify").header("content-type", "application/json");
+}
+
+#[tokio::test]
+async fn generates_a_request_id() {
+    let (app, _) = testing::memory_app();
+
+    let res = app
+        .oneshot(verify_request().body(Body::from(r#"{"token":"nope"}"#)).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let id = res.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+    assert!(uuid::Uuid::parse_str(id).is_ok());
+}
+
+#[tokio::test]
+async fn propagates_the_callers_request_id() {
+    let (app, _) = testing::memory_app();
+
+    let res = app
+        .oneshot(
+            verify_request()
+                .header(REQUEST_ID_HEADER, "edge-1234")
+                .body(Body::from(r#"{"token":"nope"}"#))
+                .unwrap()
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.headers()[REQUEST_ID_HEADER], "edge-1234");
+}
+
+#[tokio::test]
+async fn replaces_request_ids_that_arent_safe_to_log() {
+    let (app, _) = testing::memory_app();
+
+    let res = app
+        .oneshot(
+            verify_request()
+                .header(REQUEST_ID_HEADER, "evil\tid with spaces")
+                .body(Body::from(r#"{"token":"nope"}"#))
+                .unwrap()
+        )
+        .await
+        .unwrap();
+
+    let id = res.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+    assert!(uuid::Uuid::parse_str(id).is_ok());
+}
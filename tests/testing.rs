@@ -0,0 +1,30 @@
+use axum::{body::Body, http::{header, Request, StatusCode}};
+use axum_user_jwt_template::{store::UserStore, testing};
+use tower::ServiceExt;
+
+fn register_request() -> Request<Body> {
+    Request::post("/register")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"username":"alice","password":"hunter2"}"#))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn memory_app_registers_into_its_store() {
+    let (app, store) = testing::memory_app();
+
+    let res = app.oneshot(register_request()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    assert!(store.get_user_by_username("alice").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn sqlite_memory_app_registers_into_its_store() {
+    let (app, store) = testing::sqlite_memory_app().await;
+
+    let res = app.oneshot(register_request()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    assert!(store.get_user_by_username("alice").await.unwrap().is_some());
+}